@@ -0,0 +1,18 @@
+fn main() {
+    let cfg = match autocfg::AutoCfg::new() {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            println!(
+                "cargo:warning=smol-macros: failed to detect compiler features: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    // `std::backtrace` was stabilized in Rust 1.65.
+    autocfg::emit_possibility("smol_macros_no_backtrace");
+    if !cfg.probe_path("std::backtrace::Backtrace") {
+        autocfg::emit("smol_macros_no_backtrace");
+    }
+}
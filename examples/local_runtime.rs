@@ -0,0 +1,34 @@
+//! Drive a local executor from a manual main loop, like the one of a game.
+
+use smol_macros::main_executor::LocalRuntime;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    let runtime = LocalRuntime::new();
+    let score = Rc::new(Cell::new(0));
+
+    // A task that needs a few frames to complete.
+    let task = runtime.spawn({
+        let score = score.clone();
+        async move {
+            for _ in 0..3 {
+                async_io::Timer::after(Duration::from_millis(10)).await;
+                score.set(score.get() + 10);
+            }
+            println!("Task finished with a score of {}", score.get());
+        }
+    });
+
+    let mut frame = 0;
+    while !task.is_finished() {
+        frame += 1;
+
+        // Update and render the frame, then let the tasks catch up without blocking.
+        let ran = runtime.run_until_stalled();
+        println!("Frame {frame}: ran {ran} tasks, score is {}", score.get());
+        thread::sleep(Duration::from_millis(16));
+    }
+}
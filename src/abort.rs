@@ -0,0 +1,28 @@
+//! Aborting the process on panics.
+
+use std::panic;
+use std::process;
+use std::sync::Arc;
+
+/// Run a function with a panic hook that aborts the process after the panic is reported.
+pub fn abort_on_panic<T>(f: impl FnOnce() -> T) -> T {
+    let previous = Arc::new(panic::take_hook());
+    panic::set_hook({
+        let previous = previous.clone();
+        Box::new(move |info| {
+            previous(info);
+            process::abort();
+        })
+    });
+
+    let result = f();
+
+    // Dropping our hook releases its reference to the previous one.
+    drop(panic::take_hook());
+    match Arc::try_unwrap(previous) {
+        Ok(previous) => panic::set_hook(previous),
+        Err(previous) => panic::set_hook(Box::new(move |info| previous(info))),
+    }
+
+    result
+}
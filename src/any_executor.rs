@@ -0,0 +1,121 @@
+//! An executor whose kind is chosen at runtime.
+
+use crate::{Executor, LocalExecutor};
+use async_executor::Task;
+use std::future::Future;
+
+/// Either a thread-safe [`Executor`] or a [`LocalExecutor`], chosen when the program starts.
+///
+/// When used as the executor in [`main`], this is a thread-safe [`Executor`] running on a thread
+/// pool by default, or a [`LocalExecutor`] running on the current thread only if the
+/// `#[single_threaded(flag)]` attribute is given a flag that is `true`. This allows the choice to
+/// be made at runtime, e.g. from a command line argument.
+///
+/// The choice can also be made when compiling, with the `#[multi_thread(predicate)]` attribute
+/// and a configuration predicate like in `#[cfg]`. This is meant for libraries that provide
+/// entry points built on [`main`] or [`test`] and let the crates that depend on them pick the
+/// runtime with a feature of the library, without changing any signatures. The library declares
+/// the feature in its `Cargo.toml`:
+///
+/// ```toml
+/// [features]
+/// multi-thread = []
+/// ```
+///
+/// and takes an [`AnyExecutor`] wherever it needs to spawn tasks, which works the same with
+/// either kind of executor:
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, AnyExecutor};
+///
+/// #[apply(main!)]
+/// #[multi_thread(feature = "multi-thread")]
+/// async fn main(ex: &AnyExecutor<'_>) {
+///     ex.spawn(async { println!("Hello world!"); }).await;
+/// }
+/// ```
+///
+/// A downstream crate then runs the thread pool by depending on the library with
+/// `features = ["multi-thread"]`, and a single thread otherwise. Since the predicate is evaluated
+/// where the macro is expanded, the feature has to be one of the crate that calls [`main`] or
+/// [`test`], not of this crate.
+///
+/// Since either kind of executor may be running the tasks, [`AnyExecutor::spawn`] requires the
+/// future to be `Send`, like [`Executor::spawn`]. The main future itself is not spawned, so it
+/// does not need to be `Send`.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, AnyExecutor};
+///
+/// #[apply(main!)]
+/// #[single_threaded(std::env::args().any(|arg| arg == "--single-threaded"))]
+/// async fn main(ex: &AnyExecutor<'_>) {
+///     ex.spawn(async { println!("Hello world!"); }).await;
+/// }
+/// ```
+///
+/// [`main`]: crate::main
+/// [`test`]: crate::test
+#[derive(Debug)]
+pub enum AnyExecutor<'a> {
+    /// A thread-safe executor, run on a thread pool.
+    Executor(Executor<'a>),
+
+    /// A thread-local executor, run on the current thread.
+    Local(LocalExecutor<'a>),
+}
+
+impl<'a> AnyExecutor<'a> {
+    /// Spawn a task onto the executor.
+    #[inline]
+    pub fn spawn<T: Send + 'a>(&self, future: impl Future<Output = T> + Send + 'a) -> Task<T> {
+        match self {
+            Self::Executor(ex) => ex.spawn(future),
+            Self::Local(ex) => ex.spawn(future),
+        }
+    }
+
+    /// Run the executor until the future completes.
+    #[inline]
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+        match self {
+            Self::Executor(ex) => ex.run(future).await,
+            Self::Local(ex) => ex.run(future).await,
+        }
+    }
+
+    /// Wait for a single task to be ready and run it.
+    #[inline]
+    pub async fn tick(&self) {
+        match self {
+            Self::Executor(ex) => ex.tick().await,
+            Self::Local(ex) => ex.tick().await,
+        }
+    }
+
+    /// Attempt to run a single task, returning `true` if one was run.
+    #[inline]
+    pub fn try_tick(&self) -> bool {
+        match self {
+            Self::Executor(ex) => ex.try_tick(),
+            Self::Local(ex) => ex.try_tick(),
+        }
+    }
+
+    /// Check whether there are any tasks that still need to be run.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Executor(ex) => ex.is_empty(),
+            Self::Local(ex) => ex.is_empty(),
+        }
+    }
+
+    /// Check whether this is a [`LocalExecutor`] that only runs on the current thread.
+    #[inline]
+    pub fn is_local(&self) -> bool {
+        matches!(self, Self::Local(_))
+    }
+}
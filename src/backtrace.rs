@@ -0,0 +1,77 @@
+//! Capturing backtraces of panics.
+
+use std::any::Any;
+use std::fmt;
+use std::io::{self, Write};
+use std::panic;
+use std::thread;
+
+/// Run a function with a panic hook that reports panics along with a backtrace.
+///
+/// The backtrace is captured no matter what `RUST_BACKTRACE` is set to. Compilers that don't
+/// support `std::backtrace` report the panic without one.
+pub fn capture_backtrace<T>(f: impl FnOnce() -> T) -> T {
+    /// Runs a function once dropped, even while unwinding.
+    struct Defer<F: FnOnce()>(Option<F>);
+
+    impl<F: FnOnce()> Drop for Defer<F> {
+        fn drop(&mut self) {
+            if let Some(f) = self.0.take() {
+                f();
+            }
+        }
+    }
+
+    // The hook can't be changed while the thread is panicking, so if the function panics, the
+    // hook is left in place and keeps reporting panics with backtraces.
+    let previous = panic::take_hook();
+    let _restore = Defer(Some(move || {
+        if !thread::panicking() {
+            panic::set_hook(previous);
+        }
+    }));
+    panic::set_hook(Box::new(|info| {
+        let backtrace = force_capture();
+        let thread = thread::current();
+        let location = info
+            .location()
+            .map_or_else(|| "<unknown>".to_owned(), ToString::to_string);
+
+        // A panic hook that panics aborts the process, so failing to write is ignored.
+        writeln!(
+            io::stderr().lock(),
+            "thread '{}' panicked at {}:\n{}\nstack backtrace:\n{}",
+            thread.name().unwrap_or("<unnamed>"),
+            location,
+            payload_message(info.payload()),
+            backtrace
+        )
+        .ok();
+    }));
+
+    f()
+}
+
+/// Capture a backtrace of the current thread, even if backtraces are disabled.
+#[cfg(not(smol_macros_no_backtrace))]
+#[clippy::msrv = "1.65"]
+fn force_capture() -> impl fmt::Display {
+    std::backtrace::Backtrace::force_capture()
+}
+
+/// Capture a backtrace of the current thread, which isn't supported by this compiler.
+#[cfg(smol_macros_no_backtrace)]
+fn force_capture() -> impl fmt::Display {
+    "backtraces need Rust 1.65 or newer"
+}
+
+/// Get the message of a panic payload, if it is a string.
+fn payload_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
@@ -0,0 +1,108 @@
+//! A token for cancelling work cooperatively.
+
+use event_listener::Event;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A token that tells tasks to stop what they are doing, once it is cancelled.
+///
+/// Cancellation is cooperative: cancelling the token doesn't stop anything by itself, but tasks
+/// holding a clone of it can check [`is_cancelled`] or wait for [`cancelled`] and wind down. All
+/// clones share the same state, so cancelling any of them cancels all of them.
+///
+/// When the second parameter of [`main`] is a `CancellationToken`, a token is created for the
+/// runtime and cancelled as soon as the body returns, or is dropped, e.g. by
+/// `#[shutdown_on]`. Tasks that still run at that point, like with `#[await_spawned]`, get the
+/// chance to finish gracefully.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, CancellationToken, Executor};
+///
+/// #[apply(main!)]
+/// #[await_spawned]
+/// async fn main(ex: &Executor<'_>, token: CancellationToken) {
+///     ex.spawn(async move {
+///         // Runs until `main` returns.
+///         token.cancelled().await;
+///         println!("shutting down");
+///     })
+///     .detach();
+/// }
+/// ```
+///
+/// [`is_cancelled`]: CancellationToken::is_cancelled
+/// [`cancelled`]: CancellationToken::cancelled
+/// [`main`]: crate::main
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    /// The state shared between all clones.
+    inner: Arc<Inner>,
+}
+
+/// The state of a token.
+#[derive(Debug, Default)]
+struct Inner {
+    /// Whether the token has been cancelled.
+    cancelled: AtomicBool,
+
+    /// Notified once the token is cancelled.
+    event: Event,
+}
+
+impl CancellationToken {
+    /// Create a token that isn't cancelled yet.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the token, waking up everything waiting for it.
+    ///
+    /// Cancelling a token that is already cancelled does nothing.
+    #[inline]
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.event.notify_additional(usize::MAX);
+    }
+
+    /// Check whether the token has been cancelled.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            if self.is_cancelled() {
+                return;
+            }
+
+            event_listener::listener!(&self.inner.event => listener);
+
+            if self.is_cancelled() {
+                return;
+            }
+
+            listener.await;
+        }
+    }
+
+    /// Get a guard that cancels the token once it is dropped.
+    #[inline]
+    pub fn drop_guard(self) -> DropGuard {
+        DropGuard(self)
+    }
+}
+
+/// Cancels a [`CancellationToken`] once dropped.
+#[derive(Debug)]
+pub struct DropGuard(CancellationToken);
+
+impl Drop for DropGuard {
+    #[inline]
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
@@ -0,0 +1,88 @@
+//! Reading the CPU quota of the cgroup that the process runs in.
+
+use std::fs;
+use std::path::Path;
+
+/// The mount point of the unified cgroup hierarchy.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Get the number of CPUs that the cgroup v2 CPU quota of the process allows for.
+///
+/// The quota of every ancestor cgroup applies as well, so the smallest one is used. Returns
+/// `None` if there is no quota or it can't be read, e.g. because only cgroup v1 is mounted.
+pub(crate) fn cpu_quota() -> Option<usize> {
+    // The unified hierarchy is listed as `0::/path/to/cgroup`.
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+
+    let root = Path::new(CGROUP_ROOT);
+    let mut dir = root.join(path.trim_start_matches('/'));
+    let mut quota = None::<usize>;
+    loop {
+        if let Some(cpus) = read_cpu_max(&dir) {
+            quota = Some(quota.map_or(cpus, |quota| quota.min(cpus)));
+        }
+
+        if dir == root || !dir.pop() || !dir.starts_with(root) {
+            return quota;
+        }
+    }
+}
+
+/// Read the `cpu.max` file of a cgroup, rounding its quota up to a whole number of CPUs.
+fn read_cpu_max(dir: &Path) -> Option<usize> {
+    parse_cpu_max(&fs::read_to_string(dir.join("cpu.max")).ok()?)
+}
+
+/// Parse the contents of a `cpu.max` file, rounding its quota up to a whole number of CPUs.
+fn parse_cpu_max(max: &str) -> Option<usize> {
+    // The file contains the quota and the period, e.g. `50000 100000` for half a CPU. The quota
+    // is `max` if there is none, which fails to parse.
+    let mut fields = max.split_whitespace();
+    let quota: u64 = fields.next()?.parse().ok()?;
+    let period: u64 = fields.next()?.parse().ok()?;
+    if period == 0 {
+        return None;
+    }
+
+    let cpus = quota / period + u64::from(quota % period != 0);
+    Some(usize::try_from(cpus).unwrap_or(usize::MAX).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_cpu_max;
+
+    #[test]
+    fn no_quota() {
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+    }
+
+    #[test]
+    fn rounds_up() {
+        assert_eq!(parse_cpu_max("50000 100000\n"), Some(1));
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(2));
+        assert_eq!(parse_cpu_max("200000 100000\n"), Some(2));
+    }
+
+    #[test]
+    fn zero_quota() {
+        assert_eq!(parse_cpu_max("0 100000\n"), Some(1));
+    }
+
+    #[test]
+    fn large_quota() {
+        // Rounding up must not overflow.
+        let cpus = usize::try_from(u64::MAX / 2 + 1).unwrap_or(usize::MAX);
+        assert_eq!(parse_cpu_max(&format!("{} 2", u64::MAX)), Some(cpus));
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(parse_cpu_max(""), None);
+        assert_eq!(parse_cpu_max("50000"), None);
+        assert_eq!(parse_cpu_max("50000 0"), None);
+        assert_eq!(parse_cpu_max("-1 100000"), None);
+        assert_eq!(parse_cpu_max("half 100000"), None);
+    }
+}
@@ -0,0 +1,33 @@
+//! Printing and reporting the errors returned by tests.
+
+use std::fmt;
+use std::future::Future;
+
+/// Replace the error of a result with its alternate `Display` output.
+pub fn display_errors<T, E: fmt::Display>(result: Result<T, E>) -> Result<T, DisplayError> {
+    result.map_err(|err| DisplayError(format!("{err:#}")))
+}
+
+/// An error that is shown using the `Display` output of the original error.
+///
+/// The test harness and `main` print returned errors using `Debug`, so this implements `Debug`
+/// by writing the `Display` output as is.
+pub struct DisplayError(String);
+
+impl fmt::Debug for DisplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Pass the error of the result of a future to a function, before returning the result.
+pub async fn report_error<T, E>(
+    report: impl FnOnce(&E),
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let result = future.await;
+    if let Err(err) = &result {
+        report(err);
+    }
+    result
+}
@@ -0,0 +1,34 @@
+//! Keeping tests from leaking changes to the environment.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+
+/// Run a function, restoring the environment variables of the process once it returns or panics.
+///
+/// Only the variables that differ from the snapshot taken before the function ran are set or
+/// removed, the others are left alone.
+pub fn isolate_env<T>(f: impl FnOnce() -> T) -> T {
+    /// Restores the environment variables once dropped.
+    struct Restore(HashMap<OsString, OsString>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            for (key, value) in env::vars_os() {
+                match self.0.remove(&key) {
+                    Some(old) if old == value => {}
+                    Some(old) => env::set_var(key, old),
+                    None => env::remove_var(key),
+                }
+            }
+
+            // The variables that are left were removed.
+            for (key, old) in self.0.drain() {
+                env::set_var(key, old);
+            }
+        }
+    }
+
+    let _restore = Restore(env::vars_os().collect());
+    f()
+}
@@ -0,0 +1,64 @@
+//! Structured reasons for `main` to exit.
+
+use std::process::{ExitCode, Termination};
+
+/// Why `main` returned, mapped to the exit code of the process.
+///
+/// Daemons often stop for reasons that aren't errors, like being asked to shut down, and
+/// returning an [`ExitCode`] loses the difference. Returning this from the `async fn` makes the
+/// generated function return it too, and the process exits with the code of the reason: `0` for
+/// [`Normal`](ExitReason::Normal) and [`ShutdownRequested`](ExitReason::ShutdownRequested), and
+/// `1` for [`Fatal`](ExitReason::Fatal).
+///
+/// ```
+/// use smol_macros::{main, Executor, ExitReason};
+///
+/// main! {
+///     async fn main(ex: &Executor<'_>) -> ExitReason {
+///         let work = ex.spawn(async { "42".parse::<u32>() });
+///         match work.await {
+///             Ok(_) => ExitReason::Normal,
+///             Err(err) => {
+///                 eprintln!("fatal: {err}");
+///                 ExitReason::Fatal
+///             }
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExitReason {
+    /// The work is done.
+    Normal,
+
+    /// The program was asked to stop, e.g. by a signal, and did so cleanly.
+    ShutdownRequested,
+
+    /// The program stopped because of an error it couldn't recover from.
+    Fatal,
+}
+
+impl ExitReason {
+    /// Get the exit code of the process for this reason.
+    #[inline]
+    pub fn exit_code(self) -> ExitCode {
+        match self {
+            Self::Normal | Self::ShutdownRequested => ExitCode::SUCCESS,
+            Self::Fatal => ExitCode::FAILURE,
+        }
+    }
+}
+
+impl From<ExitReason> for ExitCode {
+    #[inline]
+    fn from(reason: ExitReason) -> Self {
+        reason.exit_code()
+    }
+}
+
+impl Termination for ExitReason {
+    #[inline]
+    fn report(self) -> ExitCode {
+        self.exit_code()
+    }
+}
@@ -0,0 +1,198 @@
+//! An executor that counts the tasks spawned onto it.
+
+use crate::main_executor::{Config, MainExecutor};
+use crate::Executor;
+use async_executor::Task;
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A thread-safe [`Executor`] that counts the tasks spawned onto it.
+///
+/// When used as the executor in [`main`], this is set up like [`Executor`], running on a thread
+/// pool. Tasks spawned through [`InstrumentedExecutor::spawn`] are counted, which is meant for
+/// capacity planning rather than for tracing single tasks. All other methods of [`Executor`] are
+/// reachable through [`Deref`], but tasks spawned through the inner executor aren't counted.
+///
+/// With the `peak-concurrency` feature, the executor also tracks the largest number of its tasks
+/// that were being polled at the same time, which tells whether more worker threads would help:
+/// if the peak stays below the number of threads, the tasks are waiting rather than competing
+/// for threads. This wraps every task to update a shared counter whenever it is polled, which
+/// makes polling a bit slower.
+///
+/// The statistics so far can be read with [`InstrumentedExecutor::stats`] at any time. To read
+/// them once the runtime has completely stopped, including the tasks that were still running when
+/// the main future completed, set the executor up with [`InstrumentedExecutor::with_main_stats`].
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, InstrumentedExecutor};
+///
+/// #[apply(main!)]
+/// async fn main(ex: &InstrumentedExecutor<'_>) {
+///     for i in 0..10 {
+///         ex.spawn(async move { i * 2 }).await;
+///     }
+///
+///     let stats = ex.stats();
+///     assert_eq!(stats.spawned, 10);
+///     assert_eq!(stats.completed, 10);
+/// }
+/// ```
+///
+/// [`main`]: crate::main
+#[derive(Debug, Default)]
+pub struct InstrumentedExecutor<'a> {
+    /// The executor running the tasks.
+    pub(crate) ex: Executor<'a>,
+
+    /// The counters, which are shared with the tasks.
+    counters: Arc<Counters>,
+}
+
+/// The number of tasks run by an [`InstrumentedExecutor`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RunStats {
+    /// The number of tasks that were spawned.
+    pub spawned: usize,
+
+    /// The number of tasks that ran to completion.
+    ///
+    /// Tasks that were cancelled, that panicked or that were still running when the executor was
+    /// dropped are not included.
+    pub completed: usize,
+
+    /// The largest number of tasks that were being polled at the same time.
+    ///
+    /// Only tasks spawned through [`InstrumentedExecutor::spawn`] are counted.
+    #[cfg(feature = "peak-concurrency")]
+    pub peak_concurrency: usize,
+}
+
+/// The counters updated by the tasks.
+#[derive(Debug, Default)]
+struct Counters {
+    /// The number of tasks that were spawned.
+    spawned: AtomicUsize,
+
+    /// The number of tasks that ran to completion.
+    completed: AtomicUsize,
+
+    /// The number of tasks that are being polled right now.
+    #[cfg(feature = "peak-concurrency")]
+    polling: AtomicUsize,
+
+    /// The largest number of tasks that were being polled at the same time.
+    #[cfg(feature = "peak-concurrency")]
+    peak_concurrency: AtomicUsize,
+}
+
+impl<'a> InstrumentedExecutor<'a> {
+    /// Create a new executor.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawn a task onto the executor, counting it.
+    #[inline]
+    pub fn spawn<T: Send + 'a>(&self, future: impl Future<Output = T> + Send + 'a) -> Task<T> {
+        let counters = self.counters.clone();
+        counters.spawned.fetch_add(1, Ordering::Relaxed);
+
+        self.ex.spawn(async move {
+            #[cfg(feature = "peak-concurrency")]
+            let future = counters.track_polls(future);
+
+            let output = future.await;
+            counters.completed.fetch_add(1, Ordering::Relaxed);
+            output
+        })
+    }
+
+    /// Get the number of tasks that were spawned and completed so far.
+    #[inline]
+    pub fn stats(&self) -> RunStats {
+        self.counters.stats()
+    }
+
+    /// Create this type and pass it into `main`, returning the statistics along with the result.
+    ///
+    /// The statistics are read once the worker threads have stopped, so they include tasks that
+    /// completed after `f` returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the executor can't be set up, e.g. because its threads can't be spawned.
+    ///
+    /// ```
+    /// use smol_macros::main_executor::Config;
+    /// use smol_macros::InstrumentedExecutor;
+    ///
+    /// let config = Config::new();
+    /// let (sum, stats) = InstrumentedExecutor::with_main_stats(&config, |ex| {
+    ///     config.block_on(ex.run(async {
+    ///         ex.spawn(async { 1 + 1 }).await + ex.spawn(async { 2 + 2 }).await
+    ///     }))
+    /// });
+    ///
+    /// assert_eq!(sum, 6);
+    /// assert_eq!(stats.spawned, 2);
+    /// ```
+    pub fn with_main_stats<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> (T, RunStats) {
+        let mut counters = None;
+        let result = Self::with_main(config, |ex| {
+            counters = Some(ex.counters.clone());
+            f(ex)
+        });
+
+        let counters = counters.expect("executor was not set up");
+        (result, counters.stats())
+    }
+}
+
+impl Counters {
+    /// Read the counters.
+    #[inline]
+    fn stats(&self) -> RunStats {
+        RunStats {
+            spawned: self.spawned.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            #[cfg(feature = "peak-concurrency")]
+            peak_concurrency: self.peak_concurrency.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Count a future as running while it is being polled.
+    #[cfg(feature = "peak-concurrency")]
+    async fn track_polls<T>(&self, future: impl Future<Output = T>) -> T {
+        /// Counts the task as no longer running once the poll returns or panics.
+        struct Polling<'a>(&'a AtomicUsize);
+
+        impl Drop for Polling<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        futures_lite::pin!(future);
+        futures_lite::future::poll_fn(|cx| {
+            let polling = self.polling.fetch_add(1, Ordering::Relaxed) + 1;
+            let _polling = Polling(&self.polling);
+            self.peak_concurrency.fetch_max(polling, Ordering::Relaxed);
+            future.as_mut().poll(cx)
+        })
+        .await
+    }
+}
+
+impl<'a> Deref for InstrumentedExecutor<'a> {
+    type Target = Executor<'a>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.ex
+    }
+}
@@ -0,0 +1,151 @@
+//! An executor whose thread pool only starts once a task is spawned.
+
+use crate::main_executor::{Config, ThreadPool};
+use crate::Executor;
+use async_executor::Task;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A thread-safe executor that only starts its thread pool once the first task is spawned.
+///
+/// Programs that often take a quick path that doesn't spawn anything, like a command line tool
+/// that prints its version, don't need the worker threads that [`main`] would start for an
+/// [`Executor`] before the body runs. With this executor, the body starts on the current thread
+/// alone, and the thread pool is only spawned by the first call to [`spawn`]. If nothing is
+/// spawned, no threads are created at all. Once the body completes, the pool is stopped and its
+/// threads are joined, like with [`Executor`].
+///
+/// The executor itself is cheap to create, and the current thread runs it while waiting for the
+/// body, so tasks make progress even before the pool is up, or without it with
+/// `#[current_thread]` or the `single-thread` feature. Starting the pool lazily means that
+/// the first spawn is slower, and that `#[wait_for_workers]` has no effect.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, LazyExecutor};
+///
+/// #[apply(main!)]
+/// async fn main(ex: &LazyExecutor) {
+///     if std::env::args().any(|arg| arg == "--version") {
+///         println!("{}", env!("CARGO_PKG_VERSION"));
+///         return;
+///     }
+///
+///     let task = ex.spawn(async { 1 + 2 });
+///     assert!(ex.is_started());
+///     assert_eq!(task.await, 3);
+/// }
+/// ```
+///
+/// [`main`]: crate::main
+/// [`spawn`]: LazyExecutor::spawn
+#[derive(Debug)]
+pub struct LazyExecutor {
+    /// The executor that runs the tasks.
+    pub(crate) ex: Arc<Executor<'static>>,
+
+    /// The configuration the thread pool is started with.
+    config: Config,
+
+    /// The thread pool, once it has been started.
+    pool: Mutex<Option<ThreadPool>>,
+
+    /// Whether the thread pool has been started.
+    started: AtomicBool,
+}
+
+impl Default for LazyExecutor {
+    #[inline]
+    fn default() -> Self {
+        Self::new(&Config::new())
+    }
+}
+
+impl LazyExecutor {
+    /// Create an executor that starts a thread pool with this configuration once it is needed.
+    #[inline]
+    pub fn new(config: &Config) -> Self {
+        Self {
+            ex: Arc::new(Executor::new()),
+            config: config.clone(),
+            pool: Mutex::new(None),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    /// Spawn a task onto the executor, starting the thread pool if it isn't running yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thread pool can't be started, e.g. because its threads can't be spawned.
+    #[inline]
+    pub fn spawn<T: Send + 'static>(
+        &self,
+        future: impl Future<Output = T> + Send + 'static,
+    ) -> Task<T> {
+        self.start();
+        self.ex.spawn(future)
+    }
+
+    /// Check whether the thread pool has been started.
+    #[inline]
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::Acquire)
+    }
+
+    /// Run the executor until the future completes, without starting the thread pool.
+    #[inline]
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+        self.ex.run(future).await
+    }
+
+    /// Wait for a single task to be ready and run it.
+    #[inline]
+    pub async fn tick(&self) {
+        self.ex.tick().await
+    }
+
+    /// Attempt to run a single task, returning `true` if one was run.
+    #[inline]
+    pub fn try_tick(&self) -> bool {
+        self.ex.try_tick()
+    }
+
+    /// Check whether there are any tasks that still need to be run.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ex.is_empty()
+    }
+
+    /// Start the thread pool, unless it has been started already.
+    fn start(&self) {
+        if self.started.load(Ordering::Acquire) {
+            return;
+        }
+
+        // Tasks spawned from several threads at once only start a single pool.
+        let mut pool = self.pool.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.started.load(Ordering::Acquire) {
+            return;
+        }
+        if !self.config.current_thread {
+            *pool = Some(
+                ThreadPool::spawn(&self.ex, &self.config).unwrap_or_else(|err| panic!("{err}")),
+            );
+        }
+        self.started.store(true, Ordering::Release);
+    }
+
+    /// Stop the thread pool and wait for its threads to exit, if it was started.
+    pub(crate) fn join(&self) {
+        let pool = self
+            .pool
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(pool) = pool {
+            pool.join();
+        }
+    }
+}
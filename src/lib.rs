@@ -76,9 +76,19 @@
 //! }
 //! ```
 //!
+//! ## Using `smol` Directly
+//!
+//! The [`smol`] crate doesn't define its own executor or reactor; [`smol::Executor`] and
+//! [`smol::block_on`] are re-exports from `async-executor` and `async-io`, which this crate
+//! depends on directly. As long as the major versions match, which is the case for `smol` 2,
+//! Cargo resolves both to the same crates, so the executors in this crate are the very same types
+//! as the ones in `smol` and there is only one reactor. Running `cargo tree --duplicates` shows
+//! whether this is the case in a given project.
+//!
 //! [`smol`]: https://crates.io/crates/smol
 //! [`smol::Executor`]: https://docs.rs/smol/latest/smol/struct.Executor.html
 //! [`smol::LocalExecutor`]: https://docs.rs/smol/latest/smol/struct.LocalExecutor.html
+//! [`smol::block_on`]: https://docs.rs/smol/latest/smol/fn.block_on.html
 //! [`macro_rules_attribute::apply`]: https://docs.rs/macro_rules_attribute/latest/macro_rules_attribute/attr.apply.html
 
 #![forbid(unsafe_code)]
@@ -86,6 +96,32 @@
 #[doc(no_inline)]
 pub use async_executor::{Executor, LocalExecutor};
 
+pub use any_executor::AnyExecutor;
+pub use cancellation::{CancellationToken, DropGuard};
+pub use exit_reason::ExitReason;
+pub use instrumented::{InstrumentedExecutor, RunStats};
+pub use lazy_executor::LazyExecutor;
+pub use main_executor::SetupError;
+pub use mock_clock::MockClock;
+pub use pinned_executor::PinnedExecutor;
+pub use priority_executor::PriorityExecutor;
+pub use queue_limit::QueueLimit;
+pub use reactor::{AsyncIo, Reactor};
+pub use scheduled_executor::{Schedule, ScheduledExecutor};
+pub use seed::test_seed;
+pub use service_set::ServiceSet;
+pub use spawn_blocking::spawn_blocking_on;
+pub use spawn_isolated::spawn_isolated;
+pub use split_executor::SplitExecutor;
+pub use step_executor::StepExecutor;
+pub use strategy::{Blocking, Call, NonBlocking, Strategy};
+pub use task_limit::TaskLimit;
+pub use task_set::TaskSet;
+pub use ticker::Ticker;
+
+#[cfg(feature = "task-names")]
+pub use task_names::{current_task_name, named, running_tasks, spawn_named, Named};
+
 /// Turn a main function into one that runs inside of a self-contained executor.
 ///
 /// The function created by this macro spawns an executor, spawns threads to run that executor
@@ -124,218 +160,3596 @@ pub use async_executor::{Executor, LocalExecutor};
 /// - `&`[`LocalExecutor`]
 /// - `Arc<`[`Executor`]`>`
 /// - `Rc<`[`LocalExecutor`]`>`
+/// - `&`[`AnyExecutor`], to choose between the two kinds of executor at runtime.
+/// - `&`[`InstrumentedExecutor`], a thread-safe executor that counts the tasks spawned onto it.
+/// - `&`[`StepExecutor`], a thread-local executor whose tasks only run when the body steps
+///   through them with `#[step]`.
+/// - `&`[`SplitExecutor`], to run the main future and the tasks of the thread pool on separate
+///   executors.
+/// - `&`[`PriorityExecutor`], a thread-safe executor with a lane for tasks that run first.
+/// - `&`[`LazyExecutor`], a thread-safe executor that only starts its thread pool once a task is
+///   spawned.
+/// - `&[`[`Executor`]`]`, several thread-safe executors that split the thread pool between them.
+///   This requires the `#[shards(K)]` attribute.
+/// - `Pin<&`[`Executor`]`>`, or a pinned reference to any of the other executors, for code
+///   that takes pinned references, like generic code that is shared with self-referential
+///   setups. The executors are [`Unpin`], so this is the same reference, only wrapped in a
+///   [`Pin`](core::pin::Pin). The type has to be written as `Pin<&...>`, without a path.
+///
+/// The executor can be followed by a second parameter of the type [`CancellationToken`], like
+/// `async fn main(ex: &Executor<'_>, token: CancellationToken)`, which is cancelled once the
+/// body returns. The type has to be imported and written as `CancellationToken`, without a path,
+/// since the macro can only recognize it by name.
+///
+/// For the thread-safe [`Executor`], a thread pool is spawned to run the executor on. When the
+/// `single-thread` feature is enabled, or when compiling for WebAssembly, no threads are spawned
+/// and the executor is only driven by the current thread while it blocks on the main future.
+/// This lets the same code compile on targets where threads aren't available.
+///
+/// By default, the thread pool has one thread per CPU available to the process. The current
+/// thread runs the executor's tasks too while it waits for the main future, so there is one more
+/// thread running tasks than there are CPUs; `#[main_thread_worker]` makes the totals match. In
+/// containers, the CPU quota of the container, e.g. `cpu: "500m"` in Kubernetes, is not taken
+/// into account, which can lead to more threads than the container is allowed to keep busy. With
+/// the `cgroup-quota` feature enabled on Linux, the number of threads is limited to the quota
+/// from the `cpu.max` files of the process's cgroup v2 hierarchy, rounded up to a whole number of
+/// CPUs.
+///
+/// If the main future panics, the panic is propagated once the thread pool is stopped. If one of
+/// the worker threads panics instead, that panic is propagated even if the main future completed
+/// successfully.
+///
+/// The return type of the `async fn` becomes the return type of the generated function. This
+/// includes `impl Trait` types, so `main` can return `impl `[`Termination`] to use a custom exit
+/// status.
+///
+/// The `async fn` may also be an associated function without a `self` parameter, inside of an
+/// `impl` block, in which case the generated function is an associated function too.
+///
+/// Generic parameters and a `where` clause are forwarded to the generated function, e.g. for
+/// harnesses that run the same entry point with different configurations. After the executor,
+/// further parameters can be taken by value, and become the parameters of the generated
+/// function, like `async fn serve(ex: &Executor<'_>, port: u16)` generating `fn serve(port: u16)`.
+/// The first parameter is always taken to be the executor, so a function without one can't take
+/// further parameters. A single reference after the executor is taken to be a [`TaskSet`], a
+/// [`ServiceSet`], a [`TaskLimit`], a [`QueueLimit`], a [`Ticker`] or a [`MockClock`] instead,
+/// depending on the attributes, and `#[repeat]` and `#[retry]` don't support generics or further
+/// parameters.
+///
+/// ```
+/// use smol_macros::{main, Executor};
+/// use std::fmt::Display;
+///
+/// main! {
+///     async fn describe<T>(ex: &Executor<'_>, value: T) -> String
+///     where
+///         T: Display + Send + 'static,
+///     {
+///         ex.spawn(async move { value.to_string() }).await
+///     }
+/// }
+///
+/// assert_eq!(describe(42), "42");
+/// ```
+///
+/// ## Using the executor from spawned tasks
+///
+/// The executor is created by the generated function and dropped before it returns, so a
+/// `&`[`Executor`] parameter only borrows it for the duration of the call. Since tasks spawned
+/// onto the executor may run for as long as the executor exists, they can't capture that
+/// borrow, even if the parameter is written as `&Executor<'static>`; the compiler reports that
+/// the borrowed data escapes. To use the executor from inside of a task, take an
+/// `Arc<`[`Executor`]`<'static>>` (or an `Rc<`[`LocalExecutor`]`<'static>>`) instead and move a
+/// clone of it into the task. With an `Arc`, code that isn't passed the executor at all can get
+/// a clone from [`current_executor`].
+///
+/// ```
+/// use smol_macros::{main, Executor};
+/// use std::sync::Arc;
+///
+/// main! {
+///     async fn main(ex: Arc<Executor<'static>>) {
+///         let task = ex.spawn({
+///             let ex = ex.clone();
+///             async move { ex.spawn(async { 1 + 1 }).await }
+///         });
+///
+///         assert_eq!(task.await, 2);
+///     }
+/// }
+/// ```
+///
+/// Some libraries need a `&'static `[`Executor`], e.g. to keep it in a global. With a
+/// `&'static Executor<'static>` parameter, the executor is leaked on purpose, so that it lives
+/// until the process exits, and tasks can capture the reference without an `Arc`. The worker
+/// threads still stop once `main` returns, so the executor isn't run anymore afterwards, and its
+/// memory and the tasks that are left on it are never freed. This is only meant for functions
+/// that run for the whole lifetime of the program, like `main`, and not for ones that are called
+/// over and over, like tests that are [repeated](crate::test#stress-testing).
+///
+/// ```
+/// use smol_macros::{main, Executor};
+///
+/// main! {
+///     async fn main(ex: &'static Executor<'static>) {
+///         let task = ex.spawn(async move { ex.spawn(async { 1 + 1 }).await });
+///         assert_eq!(task.await, 2);
+///     }
+/// }
+/// ```
+///
+/// The same goes for locals declared in the body: the executor outlives the body, so a task that
+/// borrows one of them doesn't compile, even though it is awaited before the body returns. The
+/// compiler reports that "borrowed data escapes outside of async block" and points at the whole
+/// macro, since the executor is declared by the generated code. Moving the data into the task
+/// (`async move`), or sharing it through an `Arc`, fixes this.
+///
+/// ```compile_fail
+/// use smol_macros::{main, Executor};
+///
+/// main! {
+///     async fn main(ex: &Executor<'_>) {
+///         let data = vec![1, 2, 3];
+///         // Error: `data` is borrowed by a task that may outlive the body.
+///         let sum = ex.spawn(async { data.iter().sum::<i32>() });
+///         assert_eq!(sum.await, 6);
+///     }
+/// }
+/// ```
+///
+/// To run tasks that borrow locals, create an executor inside of the body, after the locals, and
+/// run it alongside the future that awaits the tasks. Its tasks only run while the body runs it,
+/// so they don't run on the thread pool.
+///
+/// ```
+/// use smol_macros::{main, LocalExecutor};
+///
+/// main! {
+///     async fn main() {
+///         let data = vec![1, 2, 3];
+///         let local = LocalExecutor::new();
+///         let sum = local.spawn(async { data.iter().sum::<i32>() });
+///         assert_eq!(local.run(sum).await, 6);
+///     }
+/// }
+/// ```
+///
+/// [`current_executor`]: crate::main_executor::current_executor
+///
+/// ## Calling an existing function
+///
+/// If the `async` logic already lives in its own function, the canonical pattern is to call it
+/// from the body:
+///
+/// ```
+/// use smol_macros::{main, Executor};
+///
+/// async fn run(ex: &Executor<'_>) -> u32 {
+///     ex.spawn(async { 1 + 1 }).await
+/// }
+///
+/// main! {
+///     async fn main(ex: &Executor<'_>) {
+///         assert_eq!(run(ex).await, 2);
+///     }
+/// }
+/// ```
+///
+/// As a shorthand, a function can be generated from the path to the `async fn` and the
+/// executor it expects. The parameter is passed straight through to the function.
+///
+/// ```
+/// use smol_macros::{main, Executor};
+///
+/// async fn run(ex: &Executor<'_>) -> u32 {
+///     ex.spawn(async { 1 + 1 }).await
+/// }
+///
+/// main! {
+///     fn run_blocking(ex: &Executor<'_>) -> u32 = run;
+/// }
+///
+/// fn main() {
+///     assert_eq!(run_blocking(), 2);
+/// }
+/// ```
+///
+/// If the future is built by a helper or chosen at runtime, an `async fn` can be given an
+/// expression that evaluates to a future instead of a body. The executor parameter is in scope
+/// for the expression.
+///
+/// ```
+/// use smol_macros::{main, Executor};
+///
+/// main! {
+///     async fn compute(ex: &Executor<'_>) -> u32 = ex.spawn(async { 1 + 1 });
+/// }
+///
+/// fn main() {
+///     assert_eq!(compute(), 2);
+/// }
+/// ```
+///
+/// If the expression picks one of several futures, like an `if` whose branches call different
+/// `async fn`s, the branches have different types. Boxing each of them, e.g. with
+/// [`FutureExt::boxed_local`], gives them a common type.
+///
+/// ```
+/// use futures_lite::FutureExt;
+/// use smol_macros::main;
+///
+/// async fn fast() -> u32 { 1 }
+/// async fn thorough() -> u32 { 2 }
+///
+/// main! {
+///     async fn run() -> u32 = if std::env::args().any(|arg| arg == "--fast") {
+///         fast().boxed_local()
+///     } else {
+///         thorough().boxed_local()
+///     };
+/// }
+///
+/// fn main() {
+///     assert_eq!(run(), 2);
+/// }
+/// ```
+///
+/// [`FutureExt::boxed_local`]: https://docs.rs/futures-lite/latest/futures_lite/future/trait.FutureExt.html#method.boxed_local
+///
+/// ## Streams
+///
+/// With the `#[stream]` attribute, the body evaluates to a [`Stream`] instead of the value that
+/// is returned, and the stream is run until it ends, e.g. for a pipeline that emits records as
+/// they are processed. The items are dropped, so the work is done by the stream itself, e.g. in
+/// a `map` or `then` adapter. If the function returns a [`Result`], the items have to be
+/// [`Result`]s too, and the first error ends the run and is returned, converted with [`From`]
+/// like by the `?` operator. The other attributes apply to the whole run, e.g. `#[timeout]` to
+/// the time it takes for the stream to end. An `async fn` defined with `#[async_fn]` drives the
+/// stream as well.
+///
+/// ```
+/// use futures_lite::stream::{self, StreamExt};
+/// use smol_macros::{main, Executor};
+/// use std::num::ParseIntError;
+///
+/// main! {
+///     #[stream]
+///     async fn process(ex: &Executor<'_>) -> Result<(), ParseIntError> {
+///         let records = ["1", "2", "3"];
+///         stream::iter(records).then(|record| {
+///             ex.spawn(async move {
+///                 let value = record.parse::<u32>()?;
+///                 println!("{value}");
+///                 Ok(())
+///             })
+///         })
+///     }
+/// }
+///
+/// fn main() {
+///     process().unwrap();
+/// }
+/// ```
+///
+/// [`Stream`]: https://docs.rs/futures-lite/latest/futures_lite/stream/trait.Stream.html
+///
+/// ## Visibility
+///
+/// The generated function has the visibility of the `async fn`, so libraries can expose an
+/// entry point that sets up its own runtime. An `async fn` defined with `#[async_fn]` stays
+/// private.
+///
+/// ```
+/// mod server {
+///     use macro_rules_attribute::apply;
+///     use smol_macros::{main, Executor};
+///
+///     #[apply(main!)]
+///     pub async fn run(ex: &Executor<'_>) -> u32 {
+///         ex.spawn(async { 1 + 1 }).await
+///     }
+/// }
+///
+/// fn main() {
+///     assert_eq!(server::run(), 2);
+/// }
+/// ```
+///
+/// Likewise, an `async unsafe fn` generates an `unsafe fn`, for entry points that have to be
+/// declared `unsafe`, like ones called through FFI. The body can use unsafe operations as in any
+/// other `unsafe fn`, except with `#[repeat]`, `#[retry]` and `#[async_fn]`, which move it into
+/// a function of their own.
+///
+/// ```
+/// use smol_macros::{main, Executor};
+///
+/// main! {
+///     pub async unsafe fn first(ex: &Executor<'_>, values: Vec<u32>) -> u32 {
+///         ex.spawn(async { 1 }).await + *values.get_unchecked(0)
+///     }
+/// }
+///
+/// fn main() {
+///     // SAFETY: The vector isn't empty.
+///     assert_eq!(unsafe { first(vec![1]) }, 2);
+/// }
+/// ```
+///
+/// ## Attributes
+///
+/// Some attributes placed on the function configure the runtime instead of being forwarded
+/// onto the generated function. All other attributes, whether they come before or after
+/// `#[apply(main!)]`, end up on the generated function and not on the `async` body, so that
+/// `#[no_mangle]` or `#[export_name = "..."]` export the function that sets up the runtime, e.g.
+/// as the entry point called from another language.
+///
+/// - `#[config(expr)]`: Start from this [`Config`] instead of the default one, so that one
+///   configuration can be shared between many functions, like
+///   `#[config(my_app::runtime_config())]`. The expression can evaluate to a `Config` or a
+///   reference to one, which is cloned. The other attributes are applied on top of it, no matter
+///   where they are written, so they override its settings.
+/// - `#[shutdown_timeout(10s)]`: Once the main future completes, wait at most this long for
+///   the worker threads to stop. The worker threads borrow the executor, so they can't be
+///   detached; if they are still running once the timeout elapses (e.g. because a task is
+///   blocking its thread), the process is aborted instead of being held open forever. The
+///   duration is an integer with one of the units `ns`, `us`, `ms`, `s`, `m` or `h`, or any
+///   expression evaluating to a [`Duration`]. A literal with another unit, or one that is too
+///   long for a [`Duration`], doesn't compile. This has no effect for executors that don't spawn
+///   threads. With `#[shared_executor]`, the executor is cancelled on the shared threads instead.
+/// - `#[async_fn(name)]`: Also define an `async fn` with the given name that runs the same body,
+///   taking the same executor parameter as a reference, so that the logic can be called from code
+///   that is already running on an executor, like `name(ex).await`. The name has to be given
+///   since macros can't make up new ones, e.g. `run_async` for `run`. Only the body is shared; the
+///   other attributes only apply to the generated function that sets up the runtime.
+/// - `#[shards(K)]`: Create `K` thread-safe executors and pass them to `main` as a slice, like
+///   `async fn main(shards: &[Executor<'_>])`. The worker threads take turns running each of
+///   the shards, so that shard `i` is run by threads `i`, `i + K` and so on, and there are at least
+///   `K` worker threads. The current thread runs the first shard while it blocks on `main`. This
+///   is useful for sharding work by hand, e.g. by the hash of a key, so that the tasks of a shard
+///   don't migrate to the threads of the others. Idle worker threads don't exit with
+///   `#[shards]`, so `#[idle_timeout]` has no effect.
+/// - `#[panic_context]`: If the body panics, add the type of the executor and the number of
+///   worker threads to the panic message, like `runtime: async_executor::Executor<'_> on 4 worker
+///   threads`. This helps with failures that only happen in some environments, like on CI
+///   runners with a single CPU. The message is extended at the end, so `#[should_panic]` still
+///   matches it. Panics with a payload other than a string are left as they are, and the context
+///   is printed to stderr instead.
+/// - `#[boxed_main]`: Move the main future onto the heap before polling it. The futures of large
+///   `async fn` bodies can take up a lot of stack space, which this avoids on threads with small
+///   stacks.
+/// - `#[timeout(30s)]`: Panic if the body hasn't completed after this long, naming the function
+///   and the time that has passed. This is meant for tests that hang, so that they fail with a
+///   clear message instead of holding up CI until it gives up. The timeout is only noticed while
+///   the current thread polls the body, so a body that blocks its thread isn't stopped. The
+///   duration is given like for `#[shutdown_timeout]`.
+/// - `#[shared_executor]`: Instead of spawning a thread pool for every call, run the executor on
+///   a process-wide thread pool that is started on first use and never stopped. Each call still
+///   creates its own executor, so spawned tasks are not shared between calls; only the threads
+///   are. This is useful for test suites and other functions that are called many times, where
+///   spinning up a thread pool for every call is slow. Since the shared threads outlive the call,
+///   the executor must be `Executor<'static>`. With [`test`], what tasks print on the shared
+///   threads isn't shown with the test that printed it, see its documentation on captured output.
+///   Unlike the scoped threads of the default mode, which are joined before the call returns,
+///   the shared threads are never joined: once the body completes, the executor is cancelled on
+///   them, and tasks that are still running are dropped without waiting for the threads. There
+///   is one shared thread per CPU no matter how the first call is configured, and a task that
+///   blocks one of them holds up every call that runs at the same time.
+/// - `#[no_reactor]`: Block on futures using [`futures_lite::future::block_on`] instead of
+///   [`async_io::block_on`], both on the current thread and on the worker threads. This is
+///   meant for pure-compute workloads that never touch I/O or timers, as it avoids starting the
+///   `async-io` reactor. `async-io` primitives still work, but they are driven by its fallback
+///   `async-io` thread, which is started on first use and adds latency to every wakeup. The
+///   threads of `#[shared_executor]` always drive the reactor.
+/// - `#[spin_wait]`: Busy-wait instead of parking the thread whenever the current thread or a
+///   worker thread waits for a wakeup. This avoids the latency of parking and unparking threads,
+///   at the cost of keeping every thread of the runtime at 100% CPU usage even when there is
+///   nothing to do. This is almost never what you want, and only makes sense for latency
+///   critical programs that have CPUs to spare. Like with `#[no_reactor]`, the `async-io`
+///   reactor is then driven by its fallback thread, which parks as usual. The threads of
+///   `#[shared_executor]` always park.
+/// - `#[main_thread_name("smol-main")]`: Rename the current thread, which blocks on the main
+///   future, as soon as the function is called, so that it can be told apart from the worker
+///   threads in profilers and debuggers. The name is only changed at the operating system level,
+///   so [`std::thread::current`] still reports the old name. Renaming a running thread is only
+///   supported on Linux and Android, where names are truncated to 15 bytes; on other platforms
+///   this has no effect. Note that on Linux, the name of the main thread is also the name of the
+///   process shown by tools like `ps` and `top`.
+/// - `#[reactor_thread]`: Spawn one more thread, named `smol-macros-reactor`, that does nothing
+///   but wait for I/O events and timers with the `async-io` reactor. By default, the reactor is
+///   driven by whichever thread runs out of tasks first, so under load an I/O event may only be
+///   noticed once some thread becomes idle, or once the fallback `async-io` thread gets to it.
+///   With a dedicated thread, wakeups are dispatched as soon as the event arrives, which lowers
+///   the latency of I/O-bound services at the cost of a thread that takes a CPU away from
+///   running tasks whenever it wakes up. Whether that pays off depends on the workload, so
+///   measure it with and without this attribute. This has no effect for executors that don't
+///   spawn threads or with `#[shared_executor]`.
+/// - `#[wait_for_workers]`: Only start the body once every worker thread has run `#[worker_init]`
+///   and is about to run the executor. By default, the body starts right after the first worker
+///   thread is spawned, and that thread spawns the others, so that a body that completes right
+///   away doesn't wait for all of them to be spawned. Tasks that the body spawns may wait for the
+///   threads to come up first, which matters for startup code that relies on other threads
+///   running its tasks while it blocks. Threads that fail to start are not waited for. This has
+///   no effect for executors that don't spawn threads or with `#[shared_executor]`.
+/// - `#[main_thread_worker]`: Spawn one less worker thread than there are CPUs. The current
+///   thread always runs the executor's tasks while it waits for the main future, so with this
+///   attribute it takes the place of one of the workers instead of being an extra thread. This
+///   avoids having more busy threads than CPUs, which helps throughput when the tasks are CPU
+///   heavy. The tradeoff is latency: the main future is only polled in between the tasks that
+///   the current thread picks up, and with a single CPU no worker threads are spawned at all.
+///   Leave it off if the main future needs to respond quickly, or if it mostly waits on the
+///   spawned tasks anyway. This has no effect with `#[shared_executor]`. It can also be written as
+///   `#[reuse_main_thread]`.
+/// - `#[main_poll_budget(N)]`: While the current thread waits for the main future, it runs the
+///   executor's tasks as well. By default, it runs up to 200 tasks in a row before polling the
+///   main future again. With this attribute, it runs at most `N` tasks in between, so a smaller
+///   `N` lets the main future react sooner when the executor is busy, at the cost of more
+///   overhead per task. The tasks are run with the executor's `tick` method, which doesn't have
+///   the local queue of `run`, so even a large `N` is slightly slower than the default.
+/// - `#[threads(4)]`: Run the executor on this many threads instead of one per available CPU.
+///   With `#[main_thread_worker]`, the current thread is one of them. The count can also be a
+///   fraction of the available CPUs, like `#[threads(fraction = 0.5, min = 2)]` for half of them
+///   but at least two, which leaves headroom for other processes. The fraction has to be greater
+///   than 0 and at most 1, and is rounded to the nearest number of threads. With
+///   `#[threads(env = "APP_THREADS")]`, the count is read from an environment variable when the
+///   program starts, keeping the default if it isn't set. Invalid counts panic. This has no
+///   effect together with `#[cpu_set]` or with `#[shared_executor]`.
+/// - `#[parallelism_fallback(4)]`: Assume this many CPUs when the number of available CPUs can't
+///   be determined, which happens in some sandboxes, instead of falling back to a single thread
+///   and quietly running everything on the current thread. This only matters when the size of
+///   the pool depends on the number of CPUs, so it has no effect with a fixed `#[threads(N)]`,
+///   with `#[cpu_set]` or with `#[shared_executor]`.
+/// - `#[cpu_set(0, 2, 4, 6)]`: Spawn exactly one worker thread for each CPU in the list, pinned
+///   to that CPU, instead of one unpinned thread per available CPU. This is useful for running
+///   on cores that have been isolated for the program. The current thread is not pinned, and
+///   `#[main_thread_worker]` has no effect together with this attribute. On platforms where
+///   threads can't be pinned, or if pinning fails, the threads are left unpinned. This has no
+///   effect with `#[shared_executor]`.
+/// - `#[worker_roles("timer", "compute")]`: Name the worker threads after the roles they play,
+///   which makes them easier to tell apart in profilers and debuggers. The first threads are
+///   named after the listed roles in order, and the remaining threads are named after the last
+///   role followed by an index, e.g. `timer`, `compute-0`, `compute-1` and so on. By default,
+///   the threads are named `smol-macros-0`, `smol-macros-1` and so on. The executor still runs
+///   any task on any thread, so this only changes the names.
+/// - `#[no_thread_names]`: Don't name the threads of the pool at all, which skips allocating a
+///   name for each of them when the pool starts. The threads then show up without a name, like
+///   other threads spawned without one. This takes precedence over `#[worker_roles]`.
+/// - `#[local]`: Make sure that the executor only ever runs on the current thread. With
+///   [`LocalExecutor`] this changes nothing, since it never spawns threads anyway, and with
+///   [`AnyExecutor`] it always picks the [`LocalExecutor`]. Using it with the thread-safe
+///   [`Executor`] is a compile error, since that one always runs on a thread pool. This is mostly
+///   useful to document that `main` relies on running on a single thread.
+/// - `#[current_thread]`: Run a thread-safe executor like [`Executor`] on the current thread
+///   only, without spawning a thread pool, like the `single-thread` feature does for every
+///   function. Unlike with `#[local]` and a [`LocalExecutor`], the executor type stays the same,
+///   so spawned tasks still have to be [`Send`], and code that is meant for the thread pool
+///   compiles unchanged. Only the scheduling is different: the tasks run one at a time on the
+///   thread that blocks on the main future, which makes tests more reproducible. Bugs that only
+///   show up when tasks run in parallel, like data races through atomics that are used too
+///   weakly, don't show up then, so tests of those need a thread pool. [`worker_threads`]
+///   reports `0`, as outside of a thread pool. This has no effect with [`SplitExecutor`] and
+///   `#[shards]`, whose other executors are only run by the worker threads.
+/// - `#[fallback_local]`: If any of the worker threads can't be spawned, e.g. on a platform that
+///   is short on threads or memory, stop and join the ones that were spawned already and run
+///   the executor on the current thread only, like with `#[current_thread]`, instead of
+///   panicking or returning the error from [`try_main`]. The tasks then run one at a time, so
+///   work that relies on running in parallel gets slower, and tasks that block the thread while
+///   waiting on each other can deadlock. This has no effect with [`SplitExecutor`] and
+///   `#[shards]`, which can't run without their worker threads.
+/// - `#[schedule(lifo)]` or `#[schedule(fifo)]`: Choose the order in which a
+///   [`ScheduledExecutor`] runs the tasks that are ready: `lifo` runs the task that became ready
+///   last first, which keeps its data in the cache, and `fifo` runs the one that became ready
+///   first, which is fairer. Every executor runs its tasks in `fifo` order by default, and
+///   [`Executor`] can't be changed, so using this attribute with any other executor is a
+///   compile error.
+/// - `#[resilient_workers]` or `#[resilient_workers(N)]`: If a worker thread panics, restart its
+///   loop instead of letting the pool shrink for the rest of the run. Panics in spawned tasks are
+///   caught by the executor and resumed when the task is awaited, so this is only about panics
+///   that escape the executor itself. Restarts are counted across the whole pool and capped at
+///   `N`, or 16 if no cap is given, so that a panic that keeps recurring doesn't loop forever.
+///   Once the cap is reached, or if a worker panics while the pool is already stopping, the
+///   panic is propagated once the main future completes, like it is by default. This has no
+///   effect with `#[shared_executor]`.
+/// - `#[idle_timeout(30s)]`: Let worker threads exit once they haven't run a task for this
+///   long, instead of keeping every thread parked until the main future completes. This is
+///   meant for long-lived services with bursty load, where idle threads only hold on to memory.
+///   The duration is given like for `#[shutdown_timeout]`. Whenever a worker finds more tasks
+///   waiting right after running one, it spawns a replacement for one of the threads that
+///   exited, so the pool grows back when the load does, up to its usual size. To avoid
+///   thrashing under oscillating load, a thread only exits after a full `idle_timeout` without
+///   any task, so load that comes back more often than that keeps the pool at its peak size.
+///   Since new threads are only spawned by running workers, the pool never shrinks below one
+///   thread. This has no effect with `#[shared_executor]`.
+/// - `#[lazy_workers]`: Start with only `#[min_workers]` worker threads, one by default, instead
+///   of spawning the whole pool up front. Whenever a worker finds more tasks waiting right after
+///   running one, it spawns another thread, up to the usual size of the pool, like the pool grows
+///   back with `#[idle_timeout]`. This saves the threads of programs that are mostly idle, at the
+///   cost of latency for the first bursts of load, which have to wait for threads to be spawned.
+///   Without `#[idle_timeout]`, threads that were spawned are kept until the pool stops. This has
+///   no effect with `#[shards]` or `#[shared_executor]`.
+/// - `#[min_workers(N)]`: With `#[idle_timeout]`, keep at least `N` worker threads running even
+///   when they are idle, and with `#[lazy_workers]`, start with that many. The default is `1`,
+///   and `N` is capped at the size of the pool.
+/// - `#[stop_check_interval(N)]`: Have each worker thread check whether the pool is stopping
+///   after running at most `N` tasks. Otherwise, a worker thread that always finds another task
+///   ready runs up to 200 of them before it checks, which delays shutdown when tasks take a
+///   while. Lower values make shutdown more responsive, at the cost of some throughput, since the
+///   workers then take tasks from the shared queue instead of handing them to each other through
+///   their local queues. This has no effect with `#[shared_executor]`.
+/// - `#[stop_on_panic]`: If the body panics, stop the worker threads right away, before the
+///   panic unwinds. By default, they keep running tasks until the panic has unwound out of the
+///   body, which includes dropping all of its local variables. This is done with a panic hook,
+///   which is installed the first time and calls the previous hook afterwards. Note that this
+///   also stops the worker threads if the panic is caught within the body, e.g. with
+///   [`catch_unwind`]. Panics in tasks, including the ones that the current thread runs, don't
+///   stop the worker threads. This has no effect for executors that don't spawn threads or with
+///   `#[shared_executor]`.
+/// - `#[debug_on_panic]`: If the body panics, print to stderr whether tasks were still left on
+///   the executor and how many worker threads were running, before the panic is propagated. This
+///   helps telling after a crash whether the panic left work behind, like requests that were
+///   still being handled. The executor doesn't count its tasks, so only whether there were any
+///   is printed, per executor with `#[shards]`. This has no effect for executors that don't spawn
+///   threads.
+/// - `#[shutdown_on(future)]`: Stop the body once `future` completes, like on a signal, a failed
+///   health check or a request to shut down. The body is dropped and the output of `future` is
+///   returned in its place, so the two need to have the same output type. Shutdown work like
+///   `#[await_spawned]` still runs afterwards. The expression is evaluated once the runtime is
+///   set up, right before the body starts. With the `async-signal` feature,
+///   `#[shutdown_on(main_executor::ctrl_c())]` stops the body on Ctrl-C.
+/// - `#[single_threaded(flag)]`: Run a [`LocalExecutor`] on the current thread instead of a
+///   thread pool if the expression `flag` evaluates to `true`. This only has an effect for
+///   [`AnyExecutor`], see its documentation for details.
+/// - `#[multi_thread(feature = "multi-thread")]`: Run [`AnyExecutor`] on a thread pool only if
+///   the configuration predicate holds, and as a [`LocalExecutor`] on the current thread
+///   otherwise. The predicate takes the same form as in `#[cfg]` and is evaluated with
+///   [`cfg!`] in the crate that uses the macro, so features refer to the features of that
+///   crate. This lets a library offer entry points that downstream crates switch between a
+///   single-threaded and a multi-threaded runtime by enabling a feature, see [`AnyExecutor`]
+///   for an example. It can't be combined with `#[single_threaded]`.
+/// - `#[prelude(future)]`: Spawn and detach a task onto the executor before the main future is
+///   first polled. For the thread-safe [`Executor`], the task is spawned after the thread pool is
+///   started, so it makes progress even if the main future never yields. This is useful for
+///   seeding the executor with background work in tests. It can be used multiple times.
+/// - `#[background(future)]`: Spawn a long-lived task, like a server or a periodic job, that runs
+///   alongside the main future. Unlike with `#[prelude]`, the task is tied to the main future: it
+///   is cancelled by dropping its handle as soon as the main future completes, before any other
+///   shutdown work like `#[await_spawned]`. It can be used multiple times.
+/// - `#[task_capacity(N)]`: Make room for `N` tasks in the executor before the main future is
+///   first polled, for workloads that spawn a lot of tasks right away. `async-executor` has no
+///   way to reserve capacity up front, so this spawns `N` placeholder tasks and cancels them
+///   right away, which grows the table of active tasks, and that table keeps its capacity once
+///   the placeholders are gone. The run queue still grows as tasks are scheduled, since it
+///   allocates in small blocks that are freed once they are drained. The placeholders are
+///   cleaned up by the executor as it runs, which takes a moment per task at startup.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, Executor};
+/// use std::time::Duration;
+///
+/// #[apply(main!)]
+/// #[background(async {
+///     loop {
+///         async_io::Timer::after(Duration::from_millis(10)).await;
+///         println!("tick");
+///     }
+/// })]
+/// async fn main(ex: &Executor<'_>) {
+///     async_io::Timer::after(Duration::from_millis(50)).await;
+/// }
+/// ```
+/// - `#[await_spawned]`: Once the body returns, keep running the executor until all of the tasks
+///   spawned onto it have completed, instead of dropping the unfinished ones. This makes detached
+///   tasks run to completion, but a task that never completes, like a `#[prelude]` task that
+///   loops forever, keeps the function from ever returning. The value returned by the body is
+///   returned once the executor is empty.
+/// - `#[drain_timeout(2s)]`: Like `#[await_spawned]`, but only keep running the executor for
+///   at most this long, and drop the tasks that haven't completed by then. This gives detached
+///   tasks, like ones that flush logs or metrics, a chance to finish without risking a hang. The
+///   duration is given like for `#[shutdown_timeout]`, and a zero duration drops the tasks right
+///   away, like without this attribute.
+/// - `#[prewarm_blocking(N)]`: Before running the body, dispatch `N` trivial tasks onto the
+///   thread pool of the [`blocking`] crate at once and wait for them to complete. The pool
+///   spawns its threads lazily, so this keeps the first calls to `unblock` from paying for
+///   starting them. The pool decides how many threads to start based on how many tasks are
+///   queued, and idle threads exit again after a short while, so this mostly helps the work done
+///   right after startup.
+/// - `#[io_config(key = value, ...)]`: Tune the I/O runtime from the program instead of from
+///   environment variables set outside of it. `async-io` doesn't read any settings of its own, so
+///   the only key for now is `blocking_max_threads`, the maximum number of threads of the
+///   [`blocking`] thread pool, which sets the `BLOCKING_MAX_THREADS` environment variable. The
+///   variables are set before anything else happens, like `#[init]`. A variable that is already
+///   set in the environment is left alone, so that the value can still be overridden when running
+///   the program.
+/// - `#[abort_on_panic]`: Abort the process as soon as anything panics while the runtime is
+///   running, whether it is the main future, a spawned task or any other thread. The panic is
+///   still reported by the previously installed panic hook first. Panic hooks are process-wide,
+///   so this replaces the hook until the runtime has stopped, at which point the previous hook is
+///   restored. The hook runs before the panic starts unwinding, so the panic is never caught by
+///   `catch_unwind`: neither by the thread pool, which would otherwise stop the workers and
+///   propagate the panic, nor by any code inside of the tasks.
+/// - `#[capture_backtrace]`: Report panics with a backtrace, no matter what `RUST_BACKTRACE` is
+///   set to, so that panics in production can be diagnosed without restarting the program. This
+///   replaces the panic hook until the runtime has stopped, like `#[abort_on_panic]`, with one
+///   that writes the thread name, location and message of the panic to stderr, followed by the
+///   backtrace. If the runtime stops because of a panic, the hook is left in place, since it
+///   can't be changed while panicking. Backtraces need Rust 1.65; older compilers report the
+///   panic without one.
+/// - `#[tokio_compat]`: Run the main future inside of a tokio context using
+///   [`async_compat::Compat`], so that futures from libraries that need a tokio runtime work.
+///   Tasks spawned onto the executor only have a tokio context while the current thread runs
+///   them, so tasks running on the worker threads have to be wrapped in `Compat` themselves.
+///   This needs the `tokio-compat` feature.
+/// - `#[tokio_bridge]`: Enter a tokio runtime context on the current thread and on every worker
+///   thread for the whole run, so that tokio timers and I/O work in the main future and in all
+///   of the spawned tasks, without wrapping them in `Compat`. The runtime is the one that
+///   `async-compat` runs in the background, see `Config::tokio_bridge` for details. This has
+///   no effect with `#[block_on]`, and needs the `tokio-compat` feature.
+/// - `#[with_task_set]`: Pass a [`TaskSet`] for the executor as the second parameter, like
+///   `async fn main(ex: &Executor<'_>, tasks: &TaskSet<()>)`. Tasks that are still in the set
+///   once the body returns are cancelled, see its documentation for details.
+/// - `#[collect_results]` or `#[collect_results(f)]`: Pass a [`TaskSet`] as the second
+///   parameter like `#[with_task_set]`, but once the body returns, wait for all of the tasks in
+///   the set instead of cancelling them, including the ones they spawn onto it in the meantime.
+///   This happens before the attributes that run at shutdown, like `#[await_spawned]`. With a
+///   function, it is called with the outputs of the tasks in the order in which they were
+///   spawned, like `#[collect_results(|outputs: Vec<usize>| println!("{outputs:?}"))]`. All of
+///   the tasks have the same output type, so tasks returning different values need an `enum`
+///   or a `Box<dyn Any + Send>` as their output, and the function can then tell them apart.
+/// - `#[with_services]`: Pass a [`ServiceSet`] for the executor as the second parameter, like
+///   `async fn main(ex: &Executor<'_>, services: &ServiceSet<'_, '_>)`. Once the body returns,
+///   the services are shut down one after the other in order of their priority, before the
+///   attributes that run at shutdown, like `#[await_spawned]`.
+/// - `#[max_concurrent_tasks(N)]`: Pass a [`TaskLimit`] for the executor as the second parameter,
+///   like `async fn main(ex: &Executor<'_>, limit: &TaskLimit<'_, '_>)`, whose `spawn` waits
+///   until fewer than `N` of its tasks are running before spawning another one. This gives simple
+///   admission control without threading a semaphore through the code by hand. Tasks spawned
+///   onto the executor directly don't count towards the limit. The executor has to be a
+///   thread-safe [`Executor`].
+/// - `#[max_queued_tasks(N)]`: Pass a [`QueueLimit`] for the executor as the second parameter,
+///   like `async fn main(ex: &Executor<'_>, queue: &QueueLimit<'_, '_>)`, whose `spawn` waits
+///   until fewer than `N` of its tasks are waiting to be polled for the first time. Unlike
+///   `#[max_concurrent_tasks]`, this doesn't limit how many tasks run at once, only how far the
+///   spawning code can get ahead of the worker threads, which bounds the memory of the queue.
+///   The executor has to be a thread-safe [`Executor`], see its documentation for the risk of
+///   deadlocks.
+/// - `#[manual_tick]`: Don't run the executor while blocking on the main future, so that tasks
+///   only run when the body says so. A [`Ticker`] for the executor is passed as the second
+///   parameter, like `async fn main(ex: &LocalExecutor<'_>, ticker: &Ticker<'_, '_>)`, whose
+///   methods run the tasks one at a time. This is meant for tests of the order in which tasks
+///   run, see its documentation for details. The executor has to be a [`LocalExecutor`].
+/// - `#[step]`: Don't run the executor while blocking on the main future, like
+///   `#[manual_tick]`, but with a [`StepExecutor`] as the executor, like
+///   `async fn test(ex: &StepExecutor<'_>)`, which the body steps through one task at a time.
+///   Unlike a [`Ticker`], it can run exactly the tasks that are ready at one point, to test
+///   every round of an interleaving, see its documentation for details.
+/// - `#[mock_time]`: Pass a [`MockClock`] as a parameter, either as the only one, like
+///   `async fn test(clock: &MockClock)`, or after the executor. The clock only moves forward when
+///   the body advances it, so code that waits on it runs without actually waiting. It doesn't
+///   affect the timers of `async-io` or real I/O, see its documentation for details. The type
+///   has to be imported and written as `MockClock`, without a path.
+/// - `#[init(path::to::function)]`: Call this function, which takes no arguments, before anything
+///   else happens. It is guaranteed to run before the configuration is evaluated, before any
+///   `#[around]` function, before the executor is created and before the number of CPUs is
+///   queried or any thread is spawned. This is the place to parse the environment or to set up
+///   logging, e.g. when the logging framework spawns threads of its own or should see the setup
+///   of the runtime. If this attribute is used multiple times, the functions are called in order.
+/// - `#[before_runtime(path::to::function)]`: Call this function, which takes no arguments, as
+///   the very first thing the generated function does, before every other attribute, including
+///   `#[init]`, `#[main_thread_name]` and `#[io_config]`, no matter in which order they are
+///   written. Nothing of the runtime exists yet at that point: no configuration, no executor
+///   and no threads, and [`std::thread::available_parallelism`] hasn't been called. This is the
+///   place for process-wide setup that has to come before any allocation-heavy work or thread,
+///   like configuring the global allocator. If this attribute is used multiple times, the
+///   functions are called in order. With `#[repeat]`, they are called for every iteration.
+/// - `#[worker_init(path::to::function)]`: Call this function on each worker thread of the thread
+///   pool when it starts, before it runs any tasks, as `function(worker_index)` with the index
+///   of the thread, like the one in its name. This is the place to set up thread-local state,
+///   like a per-thread `tracing` subscriber or an allocator arena, unlike `#[init]`, which only
+///   runs once on the current thread. The current thread isn't a worker thread, even with
+///   `#[main_thread_worker]`, so it has to be set up by `#[init]` if needed. Threads that are
+///   spawned again after `#[idle_timeout]` call it again, but threads restarted by
+///   `#[resilient_workers]` don't. It isn't called with `#[shared_executor]`, whose threads are
+///   shared with other executors, or with the `single-thread` feature. If this attribute is used
+///   multiple times, the functions are called in order.
+/// - `#[time_lifecycle]` or `#[time_lifecycle(path::to::function)]`: Measure how long spawning
+///   the worker threads takes when the pool starts, and how long stopping and joining them takes
+///   once the body completes. Once the threads are joined, the timings are printed to stderr, or
+///   passed to the function as [`LifecycleTimings`], e.g. to log them or to record them as
+///   metrics. This helps catching regressions in the startup cost of large pools. Nothing is
+///   measured without this attribute. It has no effect for executors that don't spawn threads
+///   or with `#[shared_executor]`, whose threads are never spawned or joined by the run.
+/// - `#[around(path::to::function)]`: Run the entire runtime, including setting up and tearing
+///   down the executor and its threads, inside of a function with the signature
+///   `fn<T>(f: impl FnOnce() -> T) -> T`. This can be used to set up thread-local state, start
+///   a profiler or enter a tracing span that covers the whole run. If this attribute is used
+///   multiple times, the first one is the outermost.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, Executor};
+/// use std::time::Instant;
+///
+/// fn timed<T>(f: impl FnOnce() -> T) -> T {
+///     let start = Instant::now();
+///     let result = f();
+///     println!("ran for {:?}", start.elapsed());
+///     result
+/// }
+///
+/// #[apply(main!)]
+/// #[around(timed)]
+/// async fn main(ex: &Executor<'_>) {
+///     ex.spawn(async { println!("Hello world!"); }).await;
+/// }
+/// ```
+/// - `#[block_on(path::to::function)]`: Block on the main future with this function instead of
+///   [`async_io::block_on`], e.g. with [`futures_lite::future::block_on`] or with a function
+///   provided by the platform. It needs the signature `fn<T>(f: impl Future<Output = T>) -> T`.
+///   This only applies to the current thread; the worker threads of the thread-safe [`Executor`]
+///   still block on their futures as usual. This crate and `async-executor` still need `std`, so
+///   this doesn't make the macros usable in `no_std` environments.
+/// - `#[reactor(MyReactor)]`: Block on futures with a type that implements [`Reactor`] instead of
+///   [`async_io::block_on`], both on the current thread and on the worker threads, and create the
+///   timers of `#[timeout]`, `#[await_spawned]` and `#[drain_timeout]` with it. This lets code
+///   that is built on another reactor run on the runtime, and lets tests control the time that
+///   these timers see. It takes precedence over `#[no_reactor]` and `#[spin_wait]`, while
+///   `#[block_on]` still applies to the current thread. See [`Reactor`] for an example.
+/// - `#[name = new_name]`: Name the generated function `new_name` instead of using the name of
+///   the `async fn`. This is mostly useful for [`test`], see its documentation for details.
+/// - `#[expected(value)]`: Assert that the value returned by the body is equal to `value`,
+///   instead of returning it. This is mostly useful for [`test`], see its documentation for
+///   details.
+/// - `#[report(path::to::function)]`: Call this function with a reference to the error when the
+///   body returns an `Err`, before the error is returned. The body has to return a `Result`. This
+///   is mostly useful for [`test`], see its documentation for details.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, Executor};
+///
+/// #[apply(main!)]
+/// #[shutdown_timeout(10s)]
+/// async fn main(ex: &Executor<'_>) {
+///     ex.spawn(async { println!("Hello world!"); }).await;
+/// }
+/// ```
 ///
 /// [`tokio::main`]: https://docs.rs/tokio/latest/tokio/attr.main.html
 /// [`Executor`]: https://docs.rs/smol/latest/smol/struct.Executor.html
 /// [`LocalExecutor`]: https://docs.rs/smol/latest/smol/struct.LocalExecutor.html
+/// [`Duration`]: std::time::Duration
+/// [`Config`]: crate::main_executor::Config
+/// [`worker_threads`]: crate::main_executor::worker_threads
+/// [`LifecycleTimings`]: crate::main_executor::LifecycleTimings
+/// [`Termination`]: std::process::Termination
+/// [`catch_unwind`]: std::panic::catch_unwind
+/// [`blocking`]: https://docs.rs/blocking
+/// [`async_compat::Compat`]: https://docs.rs/async-compat/latest/async_compat/struct.Compat.html
+/// [`futures_lite::future::block_on`]: https://docs.rs/futures-lite/latest/futures_lite/future/fn.block_on.html
+/// [`async_io::block_on`]: https://docs.rs/async-io/latest/async_io/fn.block_on.html
 #[macro_export]
 macro_rules! main {
-    (
-        $(#[$attr:meta])*
-        async fn $name:ident () $(-> $ret:ty)? $bl:block
-    ) => {
-        $(#[$attr])*
-        fn $name () $(-> $ret)? {
-            $crate::__private::block_on(async {
-                $bl
-            })
-        }
+    ($($tt:tt)*) => {
+        $crate::__main! { [] [] $($tt)* }
     };
+}
 
-    (
-        $(#[$post_attr:meta])*
-        async fn $name:ident ($ex:ident : & $exty:ty)
-        $(-> $ret:ty)? $bl:block
-    ) => {
-        $(#[$post_attr])*
-        fn $name () $(-> $ret)? {
-            <$exty as $crate::__private::MainExecutor>::with_main(|ex| {
-                $crate::__private::block_on(ex.run(async move {
-                    let $ex = ex;
-                    $bl
-                }))
-            })
-        }
+/// Like [`main`], but returns an error if the runtime can't be set up.
+///
+/// The generated function returns a `Result<T, `[`SetupError`]`>` instead of panicking when the
+/// runtime can't be set up, e.g. because the operating system refuses to spawn more threads. This
+/// lets the caller decide how to degrade, like falling back to a single-threaded runtime. Panics
+/// in the main future are still propagated as panics. Only the first worker thread is spawned
+/// before the body runs, unless `#[wait_for_workers]` or `#[fallback_local]` is used, so if one
+/// of the others can't be spawned, the error is returned once the body completes.
+///
+/// ```
+/// use smol_macros::{main, try_main, Executor, LocalExecutor};
+///
+/// try_main! {
+///     async fn compute(ex: &Executor<'_>) -> u32 {
+///         ex.spawn(async { 1 + 1 }).await
+///     }
+/// }
+///
+/// main! {
+///     async fn compute_local(ex: &LocalExecutor<'_>) -> u32 {
+///         ex.spawn(async { 1 + 1 }).await
+///     }
+/// }
+///
+/// fn main() {
+///     let result = compute().unwrap_or_else(|err| {
+///         eprintln!("{err}, falling back to a single thread");
+///         compute_local()
+///     });
+///     assert_eq!(result, 2);
+/// }
+/// ```
+///
+/// The threads of `#[shared_executor]` are only started once, so failing to start them still
+/// panics.
+#[macro_export]
+macro_rules! try_main {
+    ($($tt:tt)*) => {
+        $crate::__main! { [{try_main}] [] $($tt)* }
     };
-
-    (
-        $(#[$post_attr:meta])*
-        async fn $name:ident ($ex:ident : $exty:ty)
-        $(-> $ret:ty)? $bl:block
-    ) => {
-        $crate::main! {
-            $(#[$post_attr])*
-            async fn $name(ex: &$exty) $(-> $ret)? {
-                let $ex = ex.clone();
-                $bl
-            }
-        }
-    }
 }
 
-/// Wrap a test in an asynchronous executor.
+/// Like [`main`], but with defaults for build scripts.
 ///
-/// This is equivalent to the [`main`] macro, but adds the `#[test]` attribute.
+/// Build scripts run while cargo compiles other crates on every CPU, so a runtime that spawns a
+/// thread for each of them only competes with the build. This runs the executor on a single
+/// worker thread next to the current one, like `#[threads(1)]`, and on the current thread alone
+/// if that thread can't be spawned, like `#[fallback_local]`. No signal handlers are installed
+/// and nothing is printed, like with [`main`]. The attributes of [`main`] can be used as usual,
+/// and override these defaults, e.g. `#[threads(env = "NUM_JOBS")]` to use as many threads as
+/// cargo runs jobs, for work that is worth it.
 ///
-/// ## Examples
+/// The crate has to be listed in `[build-dependencies]` in `Cargo.toml` to be used by the build
+/// script.
 ///
 /// ```
-/// use macro_rules_attribute::apply;
-/// use smol_macros::test;
+/// use smol_macros::{build_main, Executor};
 ///
-/// #[apply(test!)]
-/// async fn do_test() {
-///     assert_eq!(1 + 1, 2);
+/// async fn fetch_schema() -> String {
+///     "type Query { answer: Int }".to_string()
+/// }
+///
+/// build_main! {
+///     async fn main(ex: &Executor<'_>) {
+///         let schema = ex.spawn(fetch_schema()).await;
+///         println!("cargo:rustc-env=SCHEMA_LEN={}", schema.len());
+///     }
 /// }
 /// ```
 #[macro_export]
-macro_rules! test {
-    // Special case to get around bug in macro engine.
+macro_rules! build_main {
+    ($($tt:tt)*) => {
+        $crate::__main! { [{threads(1)} {fallback_local}] [] $($tt)* }
+    };
+}
+
+/// Implementation detail of [`main`].
+///
+/// The first group collects the attributes recognized by this crate, the second collects the
+/// attributes that are forwarded onto the generated function.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __main {
+    // Replace the name of the generated function.
     (
-        $(#[$post_attr:meta])*
-        async fn $name:ident ($exname:ident : & $exty:ty)
-        $(-> $ret:ty)? $bl:block
+        @rename $new:ident [$($dir:tt)*] [$($attr:tt)*] [$($before:tt)*]
+        fn $name:ident
+        $($rest:tt)*
     ) => {
-        $crate::main! {
-            $(#[$post_attr])*
-            #[core::prelude::v1::test]
-            async fn $name($exname: &$exty) $(-> $ret)? $bl
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            $($before)* fn $new $($rest)*
         }
     };
-
     (
-        $(#[$post_attr:meta])*
-        async fn $name:ident ($($pname:ident : $pty:ty),* $(,)?)
-        $(-> $ret:ty)? $bl:block
+        @rename $new:ident [$($dir:tt)*] [$($attr:tt)*] [$($before:tt)*]
+        $next:tt
+        $($rest:tt)*
     ) => {
-        $crate::main! {
-            $(#[$post_attr])*
-            #[core::prelude::v1::test]
-            async fn $name($($pname: $pty),*) $(-> $ret)? $bl
+        $crate::__main! {
+            @rename $new [$($dir)*] [$($attr)*] [$($before)* $next]
+            $($rest)*
         }
     };
-}
-
-#[doc(hidden)]
-pub mod __private {
-    pub use async_io::block_on;
-    pub use std::rc::Rc;
-
-    use crate::{Executor, LocalExecutor};
-    use event_listener::Event;
-    use std::sync::atomic::{AtomicBool, Ordering};
-    use std::sync::Arc;
-    use std::thread;
-
-    /// Something that can be set up as an executor.
-    #[doc(hidden)]
-    pub trait MainExecutor: Sized {
-        /// Create this type and pass it into `main`.
-        fn with_main<T, F: FnOnce(&Self) -> T>(f: F) -> T;
-    }
-
-    impl MainExecutor for Arc<Executor<'_>> {
-        #[inline]
-        fn with_main<T, F: FnOnce(&Self) -> T>(f: F) -> T {
-            let ex = Arc::new(Executor::new());
-            with_thread_pool(&ex, || f(&ex))
-        }
-    }
-
-    impl MainExecutor for Executor<'_> {
-        #[inline]
-        fn with_main<T, F: FnOnce(&Self) -> T>(f: F) -> T {
-            let ex = Executor::new();
-            with_thread_pool(&ex, || f(&ex))
-        }
-    }
-
-    impl MainExecutor for Rc<LocalExecutor<'_>> {
-        #[inline]
-        fn with_main<T, F: FnOnce(&Self) -> T>(f: F) -> T {
-            f(&Rc::new(LocalExecutor::new()))
-        }
-    }
-
-    impl MainExecutor for LocalExecutor<'_> {
-        fn with_main<T, F: FnOnce(&Self) -> T>(f: F) -> T {
-            f(&LocalExecutor::new())
-        }
-    }
-
-    /// Run a function that takes an `Executor` inside of a thread pool.
-    #[inline]
-    fn with_thread_pool<T>(ex: &Executor<'_>, f: impl FnOnce() -> T) -> T {
-        let stopper = WaitForStop::new();
-
-        // Create a thread for each CPU.
-        thread::scope(|scope| {
-            let num_threads = thread::available_parallelism().map_or(1, |num| num.get());
-            for i in 0..num_threads {
-                let ex = &ex;
-                let stopper = &stopper;
-
-                thread::Builder::new()
-                    .name(format!("smol-macros-{i}"))
-                    .spawn_scoped(scope, || {
-                        block_on(ex.run(stopper.wait()));
-                    })
-                    .expect("failed to spawn thread");
-            }
 
-            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
-
-            stopper.stop();
-
-            match result {
-                Ok(value) => value,
-                Err(err) => std::panic::resume_unwind(err),
-            }
-        })
-    }
-
-    /// Wait for the executor to stop.
-    struct WaitForStop {
-        /// Whether or not we need to stop.
-        stopped: AtomicBool,
-
-        /// Wait for the stop.
-        events: Event,
-    }
-
-    impl WaitForStop {
-        /// Create a new wait for stop.
-        #[inline]
-        fn new() -> Self {
-            Self {
-                stopped: AtomicBool::new(false),
-                events: Event::new(),
-            }
+    // Attributes recognized by this crate.
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[name = $new:ident]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            @rename $new [$($dir)*] [$($attr)*] []
+            $($rest)*
         }
-
-        /// Wait for the event to stop.
-        #[inline]
-        async fn wait(&self) {
-            loop {
-                if self.stopped.load(Ordering::Relaxed) {
-                    return;
-                }
-
-                event_listener::listener!(&self.events => listener);
-
-                if self.stopped.load(Ordering::Acquire) {
-                    return;
-                }
-
-                listener.await;
-            }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[name = $new:literal]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!(
+            "functions can only be renamed to an identifier, e.g. `#[name = my_function]`"
+        );
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[shared_executor]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {shared_executor}] [$($attr)*]
+            $($rest)*
         }
-
-        /// Stop the waiter.
-        #[inline]
-        fn stop(&self) {
-            self.stopped.store(true, Ordering::SeqCst);
-            self.events.notify_additional(usize::MAX);
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[around $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {around $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[background $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {background $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[prelude $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {prelude $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[prewarm_blocking $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {prewarm_blocking $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[abort_on_panic]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {abort_on_panic}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[capture_backtrace]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {capture_backtrace}] [$($attr)*]
+            $($rest)*
         }
-    }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[await_spawned]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {await_spawned}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[drain_timeout $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {drain_timeout $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[tokio_compat]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {tokio_compat}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[tokio_bridge]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {tokio_bridge}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [{try_main} $($dir:tt)*] [$($attr:tt)*]
+        #[repeat $args:tt]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[repeat]` can't be used with `try_main!`");
+    };
+    (
+        [{display_errors} $($dir:tt)*] [$($attr:tt)*]
+        #[repeat $args:tt]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[repeat]` can't be used with `#[display_errors]`");
+    };
+    (
+        [{retry $retry:tt} $($dir:tt)*] [$($attr:tt)*]
+        #[repeat $args:tt]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[retry]` can't be used with `#[repeat]`");
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[repeat $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [{repeat $args} $($dir)*] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [{try_main} $($dir:tt)*] [$($attr:tt)*]
+        #[retry $args:tt]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[retry]` can't be used with `try_main!`");
+    };
+    (
+        [{repeat $repeat:tt} $($dir:tt)*] [$($attr:tt)*]
+        #[retry $args:tt]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[retry]` can't be used with `#[repeat]`");
+    };
+    (
+        [{expected $expected:tt} $($dir:tt)*] [$($attr:tt)*]
+        #[retry $args:tt]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[retry]` can't be used with `#[expected]`");
+    };
+    (
+        [{display_errors} $($dir:tt)*] [$($attr:tt)*]
+        #[retry $args:tt]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[retry]` can't be used with `#[display_errors]`");
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[retry $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [{retry $args} $($dir)*] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [{try_main} $($dir:tt)*] [$($attr:tt)*]
+        #[expected $args:tt]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[expected]` can't be used with `try_main!`");
+    };
+    (
+        [{repeat $repeat:tt} $($dir:tt)*] [$($attr:tt)*]
+        #[expected $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [{repeat $repeat} {expected $args} $($dir)*] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [{retry $retry:tt} $($dir:tt)*] [$($attr:tt)*]
+        #[expected $args:tt]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[retry]` can't be used with `#[expected]`");
+    };
+    (
+        [{display_errors} $($dir:tt)*] [$($attr:tt)*]
+        #[expected $args:tt]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[expected]` can't be used with `#[display_errors]`");
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[expected $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [{expected $args} $($dir)*] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [{try_main} $($dir:tt)*] [$($attr:tt)*]
+        #[display_errors]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[display_errors]` can't be used with `try_main!`");
+    };
+    (
+        [{repeat $repeat:tt} $($dir:tt)*] [$($attr:tt)*]
+        #[display_errors]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[repeat]` can't be used with `#[display_errors]`");
+    };
+    (
+        [{retry $retry:tt} $($dir:tt)*] [$($attr:tt)*]
+        #[display_errors]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[retry]` can't be used with `#[display_errors]`");
+    };
+    (
+        [{expected $expected:tt} $($dir:tt)*] [$($attr:tt)*]
+        #[display_errors]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[expected]` can't be used with `#[display_errors]`");
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[display_errors]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [{display_errors} $($dir)*] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[report $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {report $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[serial]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {serial("")}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[serial $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {serial $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[isolate_env]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {isolate_env}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[requires $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {requires $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[seed]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {seed}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[local]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {local}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[multi_thread $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {multi_thread $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[single_threaded $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {single_threaded $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[threads $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {threads $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[parallelism_fallback $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {parallelism_fallback $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[cpu_set $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {cpu_set $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [#[core::prelude::v1::test] $($attr:tt)*]
+        #[test_attr($($test:tt)*)]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [#[$($test)*] $($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[test_attr $args:tt]
+        $($rest:tt)*
+    ) => {
+        ::core::compile_error!("`#[test_attr]` can only be used once, and only with `test!`");
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[no_thread_names]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {no_thread_names}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[worker_roles $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {worker_roles $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[resilient_workers $($args:tt)?]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {resilient_workers $($args)?}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[main_poll_budget $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {main_poll_budget $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[reuse_main_thread]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {main_thread_worker}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[before_runtime $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {before_runtime $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[stop_check_interval $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {stop_check_interval $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[main_thread_worker]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {main_thread_worker}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[spin_wait]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {spin_wait}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[no_reactor]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {no_reactor}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[main_thread_name $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {main_thread_name $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[config $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {config $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[reactor_thread]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {reactor_thread}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[wait_for_workers]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {wait_for_workers}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[shutdown_on $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {shutdown_on $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[stop_on_panic]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {stop_on_panic}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[debug_on_panic]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {debug_on_panic}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[mock_time]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {mock_time}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[manual_tick]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {manual_tick}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[step]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {step}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[task_capacity $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {task_capacity $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[max_concurrent_tasks $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {max_concurrent_tasks $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[max_queued_tasks $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {max_queued_tasks $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[with_task_set]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {with_task_set}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[with_services]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {with_services}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[stream]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {stream}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[collect_results $($args:tt)?]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {collect_results $($args)?}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[io_config $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {io_config $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[time_lifecycle $($args:tt)?]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {time_lifecycle $($args)?}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[worker_init $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {worker_init $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[init $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {init $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[block_on $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {block_on $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[idle_timeout $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {idle_timeout $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[current_thread]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {current_thread}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[fallback_local]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {fallback_local}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[schedule $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {schedule $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[reactor $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {reactor $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[lazy_workers]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {lazy_workers}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[min_workers $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {min_workers $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[async_fn $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {async_fn $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[shards($shards:expr)]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {shards($shards)}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[panic_context]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {panic_context}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[boxed_main]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {boxed_main}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[timeout $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {timeout $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[expect_timeout $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {expect_timeout $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[shutdown_timeout $args:tt]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)* {shutdown_timeout $args}] [$($attr)*]
+            $($rest)*
+        }
+    };
+
+    // Any other attribute is forwarded onto the generated function.
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        #[$($post_attr:tt)*]
+        $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)* #[$($post_attr)*]]
+            $($rest)*
+        }
+    };
+
+    // Run an expression that evaluates to a future.
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        async fn $name:ident ($($ex:ident : $($exty:tt)+)?)
+        $(-> $ret:ty)? = $future:expr;
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            async fn $name($($ex : $($exty)+)?) $(-> $ret)? {
+                $future.await
+            }
+        }
+    };
+
+    // Delegate to an existing `async fn`.
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        fn $name:ident ($($ex:ident : $($exty:tt)+)?)
+        $(-> $ret:ty)? = $func:path;
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            async fn $name($($ex : $($exty)+)?) $(-> $ret)? {
+                $func($($ex)?).await
+            }
+        }
+    };
+
+    // Split the generics and the where clause off of the signature.
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        async fn $name:ident < $($rest:tt)*
+    ) => {
+        $crate::__signature! { [[$($dir)*] [$($attr)*] $name] [] [] $($rest)* }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        async fn $name:ident ($($params:tt)*) $($rest:tt)*
+    ) => {
+        $crate::__signature! { @where [[$($dir)*] [$($attr)*] $name] [] ($($params)*) $($rest)* }
+    };
+
+    // Give the generated function the visibility of the `async fn`. It comes after all of the
+    // attributes, so it is emitted right before `fn`, followed by `unsafe` if the function is.
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        $vis:vis async unsafe fn $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)* $vis unsafe]
+            async fn $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        $vis:vis async fn $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)* $vis]
+            async fn $($rest)*
+        }
+    };
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        $vis:vis fn $($rest:tt)*
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)* $vis]
+            fn $($rest)*
+        }
+    };
+
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        @signature $name:ident $gen:tt $wc:tt () [$($ret:ty)?] $bl:block
+    ) => {
+        $crate::__directive! { @fn [$($dir)*] [$($attr)*] $name $gen () $wc [$($ret)?] {
+            $($crate::__directive!(@requires $name $dir);)*
+            $($crate::__directive!(@before_runtime $dir);)*
+            $($crate::__directive!(@init $dir);)*
+
+            let config = $crate::__directive!(@base_config [$($dir)*]);
+            $(let config = $crate::__directive!(@config config $dir);)*
+
+            $($crate::__directive!(@requires_executor $dir);)*
+
+            $crate::__directive!(@around [$($dir)*] {
+                $crate::__directive!(@ok [$($dir)*] $crate::__directive!(
+                    @block_on config [$($dir)*] $crate::__directive!(@wrap [$($dir)*] async {
+                        $($crate::__directive!(@startup $dir);)*
+                        $crate::__directive!(@body $name [] [$($dir)*] $crate::__directive!(
+                            @stream [$($dir)*] async move { $bl }
+                        ))
+                        .await
+                    })
+                ))
+            })
+        }}
+
+        $crate::__directive! { @async_fn [$($dir)*] $gen () $wc [$($ret)?] {
+            $crate::__directive!(@stream [$($dir)*] async move $bl).await
+        }}
+    };
+
+    // A reference with a lifetime isn't parsed as a type after the `&`, so take a reference to it.
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        @signature $name:ident $gen:tt $wc:tt
+        ($ex:ident : & $lt:lifetime $exty:ty $(, $arg:ident : $argty:ty)* $(,)?)
+        [$($ret:ty)?] $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc (ex: & &$lt $exty $(, $arg: $argty)*) [$($ret)?] {
+                let $ex: &$lt $exty = *ex;
+                $bl
+            }
+        }
+    };
+
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        @signature $name:ident $gen:tt $wc:tt
+        ($ex:ident : & $exty:ty, $token:ident : CancellationToken $(,)?)
+        [$($ret:ty)?] $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc ($ex: &$exty) [$($ret)?] {
+                let $token: CancellationToken = $crate::CancellationToken::new();
+                let _cancel_on_exit = $token.clone().drop_guard();
+                $bl
+            }
+        }
+    };
+
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        @signature $name:ident $gen:tt $wc:tt
+        ($clock:ident : & MockClock $(,)?)
+        [$($ret:ty)?] $bl:block
+    ) => {
+        $crate::__directive! {
+            @mock_time [$($dir)*] [$($dir)*] [$($attr)*] $name $gen $wc $clock [$($ret)?] $bl
+        }
+    };
+
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        @signature $name:ident $gen:tt $wc:tt
+        ($ex:ident : & $exty:ty, $tasks:ident : & $tasksty:ty $(,)?)
+        [$($ret:ty)?] $bl:block
+    ) => {
+        $crate::__directive! {
+            @task_set [$($dir)*] [$($dir)*] [$($attr)*]
+            $name $gen $wc $ex [$exty] $tasks [$tasksty] [$($ret)?] $bl
+        }
+    };
+
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        @signature $name:ident $gen:tt $wc:tt
+        ($ex:ident : & $exty:ty $(, $arg:ident : $argty:ty)* $(,)?)
+        [$($ret:ty)?] $bl:block
+    ) => {
+        $crate::__directive! { @fn [$($dir)*] [$($attr)*] $name $gen ($($arg: $argty),*) $wc [$($ret)?] {
+            $($crate::__directive!(@requires $name $dir);)*
+            $($crate::__directive!(@before_runtime $dir);)*
+            $($crate::__directive!(@init $dir);)*
+
+            let config = $crate::__directive!(@base_config [$($dir)*]);
+            $(let config = $crate::__directive!(@config config $dir);)*
+
+            $($crate::__directive!(@requires_local $exty; $dir);)*
+            $($crate::__directive!(@requires_scheduled $exty; $dir);)*
+            $($crate::__directive!(@requires_step $exty; $dir);)*
+
+            $crate::__directive!(@around [$($dir)*] {
+                $crate::__directive!(@with_main $exty; $($dir)*)(&config, |ex| {
+                    $($crate::__directive!(@prelude ex $dir);)*
+
+                    $crate::__directive!(@block_on config [$($dir)*] $crate::__directive!(
+                        @wrap [$($dir)*] $crate::__directive!(@run ex [$($dir)*] async move {
+                            $($crate::__directive!(@startup $dir);)*
+                            let background = ($($crate::__directive!(@background ex $dir),)*);
+                            let result = $crate::__directive!(@body $name [$exty] [$($dir)*] $crate::__directive!(
+                                @stream [$($dir)*] async move {
+                                    let $ex: &$exty = ex;
+                                    $bl
+                                }
+                            ))
+                            .await;
+                            ::core::mem::drop(background);
+                            $($crate::__directive!(@shutdown ex $dir);)*
+                            result
+                        })
+                    ))
+                })
+            })
+        }}
+
+        $crate::__directive! {
+            @async_fn [$($dir)*] $gen ($ex: &$exty $(, $arg: $argty)*) $wc [$($ret)?] {
+                $crate::__directive!(@stream [$($dir)*] async move $bl).await
+            }
+        }
+    };
+
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        @signature $name:ident $gen:tt $wc:tt
+        ($ex:ident : Pin<& $exty:ty> $(, $arg:ident : $argty:ty)* $(,)?)
+        [$($ret:ty)?] $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc (ex: &$exty $(, $arg: $argty)*) [$($ret)?] {
+                let $ex: ::core::pin::Pin<&$exty> = ::core::pin::Pin::new(ex);
+                $bl
+            }
+        }
+    };
+
+    (
+        [$($dir:tt)*] [$($attr:tt)*]
+        @signature $name:ident $gen:tt $wc:tt
+        ($ex:ident : $exty:ty $(, $arg:ident : $argty:ty)* $(,)?)
+        [$($ret:ty)?] $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc (ex: &$exty $(, $arg: $argty)*) [$($ret)?] {
+                let $ex = ex.clone();
+                $bl
+            }
+        }
+    };
+}
+
+/// Implementation detail of [`main`].
+///
+/// Splits the generics and the where clause off of the signature of the function, which can't be
+/// matched directly since they don't have delimiters of their own.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __signature {
+    // Collect the generics, keeping track of nested angle brackets.
+    ([$($ctx:tt)*] [$($gen:tt)*] [] > $($rest:tt)*) => {
+        $crate::__signature! { @where [$($ctx)*] [$($gen)*] $($rest)* }
+    };
+    ([$($ctx:tt)*] [$($gen:tt)*] [< $($depth:tt)*] > $($rest:tt)*) => {
+        $crate::__signature! { [$($ctx)*] [$($gen)* >] [$($depth)*] $($rest)* }
+    };
+    ([$($ctx:tt)*] [$($gen:tt)*] $depth:tt >> $($rest:tt)*) => {
+        $crate::__signature! { [$($ctx)*] [$($gen)*] $depth > > $($rest)* }
+    };
+    ([$($ctx:tt)*] [$($gen:tt)*] [$($depth:tt)*] < $($rest:tt)*) => {
+        $crate::__signature! { [$($ctx)*] [$($gen)* <] [< $($depth)*] $($rest)* }
+    };
+    ([$($ctx:tt)*] [$($gen:tt)*] $depth:tt $next:tt $($rest:tt)*) => {
+        $crate::__signature! { [$($ctx)*] [$($gen)* $next] $depth $($rest)* }
+    };
+
+    // Collect the where clause, which ends at the body.
+    (@where $ctx:tt $gen:tt $params:tt $(-> $ret:ty)? $bl:block) => {
+        $crate::__signature! { @done $ctx $gen [] $params [$($ret)?] $bl }
+    };
+    (@where $ctx:tt $gen:tt $params:tt $(-> $ret:ty)? where $($rest:tt)*) => {
+        $crate::__signature! { @where_clause $ctx $gen [] $params [$($ret)?] $($rest)* }
+    };
+    (@where_clause $ctx:tt $gen:tt $wc:tt $params:tt $ret:tt $bl:block) => {
+        $crate::__signature! { @done $ctx $gen $wc $params $ret $bl }
+    };
+    (
+        @where_clause $ctx:tt $gen:tt [$($wc:tt)*] $params:tt $ret:tt
+        $next:tt $($rest:tt)+
+    ) => {
+        $crate::__signature! { @where_clause $ctx $gen [$($wc)* $next] $params $ret $($rest)+ }
+    };
+
+    (@done [[$($dir:tt)*] [$($attr:tt)*] $name:ident] $gen:tt $wc:tt $params:tt $ret:tt $bl:block) => {
+        $crate::__main! { [$($dir)*] [$($attr)*] @signature $name $gen $wc $params $ret $bl }
+    };
+}
+
+/// Expand a single attribute collected by [`main`] at a given stage of the generated function.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __directive {
+    // Create the configuration that the other attributes are applied to.
+    (@base_config [{config($base:expr)} $($rest:tt)*]) => {
+        $crate::__private::Config::clone(&$base)
+    };
+    (@base_config [$other:tt $($rest:tt)*]) => {
+        $crate::__directive!(@base_config [$($rest)*])
+    };
+    (@base_config []) => {
+        $crate::__private::Config::new()
+    };
+
+    // A duration literal like `10s`, which is checked while compiling.
+    (@duration $timeout:literal) => {{
+        const DURATION: ::core::time::Duration =
+            $crate::__private::parse_duration(::core::stringify!($timeout));
+        DURATION
+    }};
+
+    (@config $config:ident {shutdown_timeout($timeout:literal)}) => {
+        $config.shutdown_timeout($crate::__directive!(@duration $timeout))
+    };
+    (@config $config:ident {shutdown_timeout($timeout:expr)}) => {
+        $config.shutdown_timeout($timeout)
+    };
+    (@config $config:ident {idle_timeout($timeout:literal)}) => {
+        $config.idle_timeout($crate::__directive!(@duration $timeout))
+    };
+    (@config $config:ident {idle_timeout($timeout:expr)}) => {
+        $config.idle_timeout($timeout)
+    };
+    (@config $config:ident {min_workers($min:expr)}) => {
+        $config.min_workers($min)
+    };
+    (@config $config:ident {shards($shards:expr)}) => {
+        $config.shards($shards)
+    };
+    (@config $config:ident {reactor_thread}) => {
+        $config.reactor_thread()
+    };
+    (@config $config:ident {current_thread}) => {
+        $config.current_thread()
+    };
+    (@config $config:ident {fallback_local}) => {
+        $config.fallback_local()
+    };
+    (@config $config:ident {schedule(fifo)}) => {
+        $config.schedule($crate::Schedule::Fifo)
+    };
+    (@config $config:ident {schedule(lifo)}) => {
+        $config.schedule($crate::Schedule::Lifo)
+    };
+    (@config $config:ident {reactor($reactor:ty)}) => {
+        $config.reactor::<$reactor>()
+    };
+    (@config $config:ident {lazy_workers}) => {
+        $config.lazy_workers()
+    };
+    (@config $config:ident {wait_for_workers}) => {
+        $config.wait_for_workers()
+    };
+    (@config $config:ident {stop_on_panic}) => {
+        $config.stop_on_panic()
+    };
+    (@config $config:ident {debug_on_panic}) => {
+        $config.debug_on_panic()
+    };
+    (@config $config:ident {no_reactor}) => {
+        $config.no_reactor()
+    };
+    (@config $config:ident {spin_wait}) => {
+        $config.spin_wait()
+    };
+    (@config $config:ident {parallelism_fallback($cpus:expr)}) => {
+        $config.parallelism_fallback($cpus)
+    };
+    (@config $config:ident {threads(fraction = $fraction:expr, min = $min:expr $(,)?)}) => {
+        $config.threads_fraction($fraction, $min)
+    };
+    (@config $config:ident {threads(fraction = $fraction:expr $(,)?)}) => {
+        $config.threads_fraction($fraction, 1)
+    };
+    (@config $config:ident {threads(env = $var:expr $(,)?)}) => {
+        $config.threads_from_env($var)
+    };
+    (@config $config:ident {threads($threads:expr)}) => {
+        $config.threads($threads)
+    };
+    (@config $config:ident {cpu_set($($cpu:expr),* $(,)?)}) => {
+        $config.cpu_set([$($cpu),*])
+    };
+    (@config $config:ident {time_lifecycle}) => {
+        $config.time_lifecycle($crate::__private::print_timings)
+    };
+    (@config $config:ident {time_lifecycle($report:expr)}) => {
+        $config.time_lifecycle($report)
+    };
+    (@config $config:ident {worker_init($init:expr)}) => {
+        $config.worker_init($init)
+    };
+    (@config $config:ident {no_thread_names}) => {
+        $config.no_thread_names()
+    };
+    (@config $config:ident {worker_roles($($role:expr),* $(,)?)}) => {
+        $config.worker_roles([$($role),*])
+    };
+    (@config $config:ident {local}) => {
+        $config.local()
+    };
+    (@config $config:ident {resilient_workers}) => {
+        $config.resilient_workers($crate::__private::DEFAULT_WORKER_RESTARTS)
+    };
+    (@config $config:ident {resilient_workers($max_restarts:expr)}) => {
+        $config.resilient_workers($max_restarts)
+    };
+    (@config $config:ident {stop_check_interval($tasks:expr)}) => {
+        $config.stop_check_interval($tasks)
+    };
+    (@config $config:ident {main_thread_worker}) => {
+        $config.main_thread_worker()
+    };
+    (@config $config:ident {single_threaded($flag:expr)}) => {
+        $config.single_threaded($flag)
+    };
+    (@config $config:ident {multi_thread($($predicate:tt)*)}) => {
+        $config.single_threaded(!::core::cfg!($($predicate)*))
+    };
+    (@config $config:ident {tokio_bridge}) => {
+        $crate::__tokio_bridge!($config)
+    };
+    (@config $config:ident $other:tt) => {
+        $config
+    };
+
+    // Pass a task set as the second parameter, which requires `#[with_task_set]`.
+    (
+        @task_set [{manual_tick} $($rest:tt)*] [$($dir:tt)*] [$($attr:tt)*]
+        $name:ident $gen:tt $wc:tt $ex:ident [$exty:ty] $tasks:ident [$tasksty:ty] [$($ret:ty)?]
+        $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc ($ex: &$exty) [$($ret)?] {
+                let $tasks: &$tasksty = &$crate::Ticker::from($ex);
+                $bl
+            }
+        }
+    };
+    (
+        @task_set [{with_task_set} $($rest:tt)*] [$($dir:tt)*] [$($attr:tt)*]
+        $name:ident $gen:tt $wc:tt $ex:ident [$exty:ty] $tasks:ident [$tasksty:ty] [$($ret:ty)?]
+        $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc ($ex: &$exty) [$($ret)?] {
+                let $tasks: &$tasksty = &$crate::TaskSet::from($ex);
+                $bl
+            }
+        }
+    };
+    (
+        @task_set [{collect_results $($args:tt)?} $($rest:tt)*] [$($dir:tt)*] [$($attr:tt)*]
+        $name:ident $gen:tt $wc:tt $ex:ident [$exty:ty] $tasks:ident [$tasksty:ty] [$($ret:ty)?]
+        $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc ($ex: &$exty) [$($ret)?] {
+                let task_set = $crate::TaskSet::from($ex);
+                let result = {
+                    let $tasks: &$tasksty = &task_set;
+                    async move { $bl }.await
+                };
+                let outputs = task_set.join_all().await;
+                $crate::__directive!(@collect_results outputs $($args)?);
+                result
+            }
+        }
+    };
+    (
+        @task_set [{with_services} $($rest:tt)*] [$($dir:tt)*] [$($attr:tt)*]
+        $name:ident $gen:tt $wc:tt $ex:ident [$exty:ty] $tasks:ident [$tasksty:ty] [$($ret:ty)?]
+        $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc ($ex: &$exty) [$($ret)?] {
+                let services = $crate::ServiceSet::from($ex);
+                let result = {
+                    let $tasks: &$tasksty = &services;
+                    async move { $bl }.await
+                };
+                services.shutdown().await;
+                result
+            }
+        }
+    };
+    (
+        @task_set [{max_concurrent_tasks($max:expr)} $($rest:tt)*] [$($dir:tt)*] [$($attr:tt)*]
+        $name:ident $gen:tt $wc:tt $ex:ident [$exty:ty] $tasks:ident [$tasksty:ty] [$($ret:ty)?]
+        $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc ($ex: &$exty) [$($ret)?] {
+                let $tasks: &$tasksty = &$crate::TaskLimit::new($ex, $max);
+                $bl
+            }
+        }
+    };
+    (
+        @task_set [{max_queued_tasks($max:expr)} $($rest:tt)*] [$($dir:tt)*] [$($attr:tt)*]
+        $name:ident $gen:tt $wc:tt $ex:ident [$exty:ty] $tasks:ident [$tasksty:ty] [$($ret:ty)?]
+        $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc ($ex: &$exty) [$($ret)?] {
+                let $tasks: &$tasksty = &$crate::QueueLimit::new($ex, $max);
+                $bl
+            }
+        }
+    };
+    (
+        @task_set [{mock_time} $($rest:tt)*] [$($dir:tt)*] [$($attr:tt)*]
+        $name:ident $gen:tt $wc:tt $ex:ident [$exty:ty] $tasks:ident [$tasksty:ty] [$($ret:ty)?]
+        $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc ($ex: &$exty) [$($ret)?] {
+                let $tasks: &$tasksty = &$crate::MockClock::new();
+                $bl
+            }
+        }
+    };
+    (
+        @task_set [$other:tt $($rest:tt)*] [$($dir:tt)*] [$($attr:tt)*]
+        $name:ident $gen:tt $wc:tt $ex:ident [$exty:ty] $tasks:ident [$tasksty:ty] [$($ret:ty)?]
+        $bl:block
+    ) => {
+        $crate::__directive! {
+            @task_set [$($rest)*] [$($dir)*] [$($attr)*]
+            $name $gen $wc $ex [$exty] $tasks [$tasksty] [$($ret)?] $bl
+        }
+    };
+    (@task_set [] $($rest:tt)*) => {
+        ::core::compile_error!(
+            "a second parameter has to be a `CancellationToken`, or requires the \
+             `#[with_task_set]`, `#[collect_results]`, `#[with_services]`, \
+             `#[max_concurrent_tasks]`, `#[max_queued_tasks]`, `#[manual_tick]` or \
+             `#[mock_time]` attribute"
+        );
+    };
+
+    // Hand the outputs of the tasks of `#[collect_results]` to its function.
+    (@collect_results $outputs:ident ($collect:expr)) => {
+        ($collect)($outputs)
+    };
+    (@collect_results $outputs:ident) => {
+        ::core::mem::drop($outputs)
+    };
+
+    // Pass a mock clock as the only parameter, which requires `#[mock_time]`.
+    (
+        @mock_time [{mock_time} $($rest:tt)*] [$($dir:tt)*] [$($attr:tt)*]
+        $name:ident $gen:tt $wc:tt $clock:ident [$($ret:ty)?] $bl:block
+    ) => {
+        $crate::__main! {
+            [$($dir)*] [$($attr)*]
+            @signature $name $gen $wc () [$($ret)?] {
+                let $clock: &$crate::MockClock = &$crate::MockClock::new();
+                $bl
+            }
+        }
+    };
+    (
+        @mock_time [$other:tt $($rest:tt)*] [$($dir:tt)*] [$($attr:tt)*]
+        $name:ident $gen:tt $wc:tt $clock:ident [$($ret:ty)?] $bl:block
+    ) => {
+        $crate::__directive! {
+            @mock_time [$($rest)*] [$($dir)*] [$($attr)*] $name $gen $wc $clock [$($ret)?] $bl
+        }
+    };
+    (@mock_time [] $($rest:tt)*) => {
+        ::core::compile_error!("a `&MockClock` parameter requires the `#[mock_time]` attribute");
+    };
+
+    // Return early if the test needs a resource that isn't allowed.
+    (@requires $name:ident {requires($($resource:ident),+ $(,)?)}) => {
+        if !$crate::__private::requires(
+            ::core::concat!(::core::module_path!(), "::", ::core::stringify!($name)),
+            &[$(::core::stringify!($resource)),+],
+        ) {
+            return $crate::__private::Skipped::skipped();
+        }
+    };
+    (@requires $name:ident $other:tt) => {};
+
+    (@before_runtime {before_runtime($hook:expr)}) => {
+        ($hook)()
+    };
+    (@before_runtime $other:tt) => {};
+
+    // Run the setup that comes before everything else, in the order of the attributes.
+    (@init {init($init:expr)}) => {
+        ($init)()
+    };
+    (@init {main_thread_name($name:expr)}) => {
+        $crate::__private::set_thread_name($name)
+    };
+    (@init {io_config($($key:ident = $value:expr),* $(,)?)}) => {
+        $($crate::__directive!(@io_config $key $value);)*
+    };
+    (@init $other:tt) => {};
+
+    // Set the environment variable for an I/O setting.
+    (@io_config blocking_max_threads $value:expr) => {
+        $crate::__private::set_default_env("BLOCKING_MAX_THREADS", $value)
+    };
+    (@io_config $key:ident $value:expr) => {
+        ::core::compile_error!(::core::concat!(
+            "unknown `#[io_config]` key `",
+            ::core::stringify!($key),
+            "`, expected `blocking_max_threads`"
+        ))
+    };
+
+    // Wrap the entire run, with the first attribute being the outermost.
+    (@around [{around($around:path)} $($rest:tt)*] $run:block) => {
+        $around(|| $crate::__directive!(@around [$($rest)*] $run))
+    };
+    (@around [{abort_on_panic} $($rest:tt)*] $run:block) => {
+        $crate::__private::abort_on_panic(|| $crate::__directive!(@around [$($rest)*] $run))
+    };
+    (@around [{capture_backtrace} $($rest:tt)*] $run:block) => {
+        $crate::__private::capture_backtrace(|| $crate::__directive!(@around [$($rest)*] $run))
+    };
+    (@around [{serial($group:expr)} $($rest:tt)*] $run:block) => {
+        $crate::__private::serial($group, || $crate::__directive!(@around [$($rest)*] $run))
+    };
+    (@around [{isolate_env} $($rest:tt)*] $run:block) => {
+        $crate::__private::isolate_env(|| $crate::__directive!(@around [$($rest)*] $run))
+    };
+    (@around [{seed} $($rest:tt)*] $run:block) => {
+        $crate::__private::with_seed(|| $crate::__directive!(@around [$($rest)*] $run))
+    };
+    (@around [$other:tt $($rest:tt)*] $run:block) => {
+        $crate::__directive!(@around [$($rest)*] $run)
+    };
+    (@around [] $run:block) => {
+        $run
+    };
+
+    // Spawn tasks onto the executor before the main future starts.
+    (@prelude $ex:ident {prelude($future:expr)}) => {
+        $ex.spawn($future).detach()
+    };
+    (@prelude $ex:ident {task_capacity($capacity:expr)}) => {
+        ::core::mem::drop(
+            (0..$capacity)
+                .map(|_| $ex.spawn(::core::future::pending::<()>()))
+                .collect::<::std::vec::Vec<_>>(),
+        )
+    };
+    (@prelude $ex:ident $other:tt) => {};
+
+    // Run the executor on the current thread alongside the main future.
+    (@run $ex:ident [{manual_tick} $($rest:tt)*] $future:expr) => {
+        $future
+    };
+    (@run $ex:ident [{step} $($rest:tt)*] $future:expr) => {
+        $future
+    };
+    (@run $ex:ident [{main_poll_budget($budget:expr)} $($rest:tt)*] $future:expr) => {
+        $crate::__private::run_with_budget(|| $ex.tick(), $budget, $future)
+    };
+    (@run $ex:ident [$other:tt $($rest:tt)*] $future:expr) => {
+        $crate::__directive!(@run $ex [$($rest)*] $future)
+    };
+    (@run $ex:ident [] $future:expr) => {
+        $ex.run($future)
+    };
+
+    // Wrap the future that is blocked on.
+    (@wrap [{tokio_compat} $($rest:tt)*] $future:expr) => {
+        $crate::__tokio_compat!($crate::__directive!(@wrap [$($rest)*] $future))
+    };
+    (@wrap [$other:tt $($rest:tt)*] $future:expr) => {
+        $crate::__directive!(@wrap [$($rest)*] $future)
+    };
+    (@wrap [] $future:expr) => {
+        $future
+    };
+
+    // Wait for setup work before the body starts.
+    (@startup {prewarm_blocking($count:expr)}) => {
+        $crate::__private::prewarm_blocking($count).await
+    };
+    (@startup $other:tt) => {};
+
+    // Spawn tasks that are cancelled once the body completes.
+    (@background $ex:ident {background($future:expr)}) => {
+        $ex.spawn($future)
+    };
+    (@background $ex:ident $other:tt) => {
+        ()
+    };
+
+    // Wait for work to finish after the body completes.
+    (@shutdown $ex:ident {await_spawned}) => {
+        $crate::__private::wait_until_empty(|| $ex.is_empty()).await
+    };
+    (@shutdown $ex:ident {drain_timeout($timeout:literal)}) => {
+        $crate::__private::drain_with_timeout(
+            || $ex.is_empty(),
+            $crate::__directive!(@duration $timeout),
+        )
+        .await
+    };
+    (@shutdown $ex:ident {drain_timeout($timeout:expr)}) => {
+        $crate::__private::drain_with_timeout(|| $ex.is_empty(), $timeout).await
+    };
+    (@shutdown $ex:ident $other:tt) => {};
+
+    // Reject attributes that need an executor when there is none.
+    (@requires_executor {prelude $args:tt}) => {
+        ::core::compile_error!("`#[prelude]` requires an executor parameter")
+    };
+    (@requires_executor {background $args:tt}) => {
+        ::core::compile_error!("`#[background]` requires an executor parameter")
+    };
+    (@requires_executor {await_spawned}) => {
+        ::core::compile_error!("`#[await_spawned]` requires an executor parameter")
+    };
+    (@requires_executor {drain_timeout $args:tt}) => {
+        ::core::compile_error!("`#[drain_timeout]` requires an executor parameter")
+    };
+    (@requires_executor {task_capacity $args:tt}) => {
+        ::core::compile_error!("`#[task_capacity]` requires an executor parameter")
+    };
+    (@requires_executor {shards $args:tt}) => {
+        ::core::compile_error!("`#[shards]` requires an executor parameter")
+    };
+    (@requires_executor {schedule $args:tt}) => {
+        ::core::compile_error!("`#[schedule]` requires an executor parameter")
+    };
+    (@requires_executor {step}) => {
+        ::core::compile_error!("`#[step]` requires an executor parameter")
+    };
+    (@requires_executor $other:tt) => {};
+
+    // Reject thread-safe executors when the executor has to stay on the current thread.
+    (@requires_local $exty:ty; {local}) => {
+        $crate::__private::requires_local::<$exty>()
+    };
+    (@requires_local $exty:ty; {manual_tick}) => {
+        $crate::__private::requires_local::<$exty>()
+    };
+    (@requires_local $exty:ty; $other:tt) => {};
+
+    // Reject executors that can't change the order of their tasks.
+    (@requires_scheduled $exty:ty; {schedule $args:tt}) => {
+        $crate::__private::requires_scheduled::<$exty>()
+    };
+    (@requires_scheduled $exty:ty; $other:tt) => {};
+
+    // Reject executors that can't be stepped through by the body.
+    (@requires_step $exty:ty; {step}) => {
+        $crate::__private::requires_step::<$exty>()
+    };
+    (@requires_step $exty:ty; $other:tt) => {};
+
+    // The function that creates the executor and calls into `main`.
+    (@with_main $exty:ty; [$($shared:tt)?] [$($try:tt)?] {shared_executor} $($rest:tt)*) => {
+        $crate::__directive!(@with_main $exty; [shared] [$($try)?] $($rest)*)
+    };
+    (@with_main $exty:ty; [$($shared:tt)?] [$($try:tt)?] {shards $args:tt} $($rest:tt)*) => {
+        $crate::__directive!(@with_main $crate::__private::Shards<'_>; [$($shared)?] [$($try)?] $($rest)*)
+    };
+    (@with_main $exty:ty; [$($shared:tt)?] [$($try:tt)?] {try_main} $($rest:tt)*) => {
+        $crate::__directive!(@with_main $exty; [$($shared)?] [try] $($rest)*)
+    };
+    (@with_main $exty:ty; [$($shared:tt)?] [$($try:tt)?] $other:tt $($rest:tt)*) => {
+        $crate::__directive!(@with_main $exty; [$($shared)?] [$($try)?] $($rest)*)
+    };
+    (@with_main $exty:ty; [] []) => {
+        <$exty as $crate::__private::MainExecutor>::with_main
+    };
+    (@with_main $exty:ty; [shared] []) => {
+        <$exty as $crate::__private::SharedExecutor>::with_shared
+    };
+    (@with_main $exty:ty; [] [try]) => {
+        <$exty as $crate::__private::MainExecutor>::try_with_main
+    };
+    (@with_main $exty:ty; [shared] [try]) => {
+        <$exty as $crate::__private::SharedExecutor>::try_with_shared
+    };
+    (@with_main $exty:ty; $($dir:tt)*) => {
+        $crate::__directive!(@with_main $exty; [] [] $($dir)*)
+    };
+
+    // Wrap the body of the function.
+    (@body $name:ident $exty:tt [{boxed_main} $($rest:tt)*] $future:expr) => {
+        ::std::boxed::Box::pin($crate::__directive!(@body $name $exty [$($rest)*] $future))
+    };
+    (@body $name:ident [$($exty:ty)?] [{panic_context} $($rest:tt)*] $future:expr) => {
+        $crate::__private::with_panic_context(
+            $crate::__directive!(@type_name $($exty)?),
+            $crate::__directive!(@body $name [$($exty)?] [$($rest)*] $future),
+        )
+    };
+    (@body $name:ident $exty:tt [{report($report:path)} $($rest:tt)*] $future:expr) => {
+        $crate::__private::report_error($report, $crate::__directive!(@body $name $exty [$($rest)*] $future))
+    };
+    (@body $name:ident $exty:tt [{stop_on_panic} $($rest:tt)*] $future:expr) => {
+        $crate::__private::stop_on_panic($crate::__directive!(@body $name $exty [$($rest)*] $future))
+    };
+    (@body $name:ident $exty:tt [{shutdown_on($shutdown:expr)} $($rest:tt)*] $future:expr) => {
+        $crate::__private::or($crate::__directive!(@body $name $exty [$($rest)*] $future), $shutdown)
+    };
+    (@body $name:ident $exty:tt [{timeout($timeout:literal)} $($rest:tt)*] $future:expr) => {
+        $crate::__private::with_timeout(
+            ::core::concat!(::core::module_path!(), "::", ::core::stringify!($name)),
+            $crate::__directive!(@duration $timeout),
+            $crate::__directive!(@body $name $exty [$($rest)*] $future),
+        )
+    };
+    (@body $name:ident $exty:tt [{timeout($timeout:expr)} $($rest:tt)*] $future:expr) => {
+        $crate::__private::with_timeout(
+            ::core::concat!(::core::module_path!(), "::", ::core::stringify!($name)),
+            $timeout,
+            $crate::__directive!(@body $name $exty [$($rest)*] $future),
+        )
+    };
+    (@body $name:ident $exty:tt [{expect_timeout($timeout:literal)} $($rest:tt)*] $future:expr) => {
+        $crate::__private::expect_timeout(
+            ::core::concat!(::core::module_path!(), "::", ::core::stringify!($name)),
+            $crate::__directive!(@duration $timeout),
+            $crate::__directive!(@body $name $exty [$($rest)*] $future),
+        )
+    };
+    (@body $name:ident $exty:tt [{expect_timeout($timeout:expr)} $($rest:tt)*] $future:expr) => {
+        $crate::__private::expect_timeout(
+            ::core::concat!(::core::module_path!(), "::", ::core::stringify!($name)),
+            $timeout,
+            $crate::__directive!(@body $name $exty [$($rest)*] $future),
+        )
+    };
+    (@body $name:ident $exty:tt [$other:tt $($rest:tt)*] $future:expr) => {
+        $crate::__directive!(@body $name $exty [$($rest)*] $future)
+    };
+    (@body $name:ident $exty:tt [] $future:expr) => {
+        $future
+    };
+
+    // Drive the stream returned by the body to completion.
+    (@stream [{stream} $($rest:tt)*] $future:expr) => {
+        $crate::__private::drain_stream($future)
+    };
+    (@stream [$other:tt $($rest:tt)*] $future:expr) => {
+        $crate::__directive!(@stream [$($rest)*] $future)
+    };
+    (@stream [] $future:expr) => {
+        $future
+    };
+
+    // Name the type of the executor.
+    (@type_name $exty:ty) => {
+        ::core::any::type_name::<$exty>()
+    };
+    (@type_name) => {
+        "no executor"
+    };
+
+    // Block on the main future on the current thread.
+    (@block_on $config:ident [{block_on($block_on:expr)} $($rest:tt)*] $future:expr) => {
+        $crate::__private::enter_runtime(|| ($block_on)($future))
+    };
+    (@block_on $config:ident [$other:tt $($rest:tt)*] $future:expr) => {
+        $crate::__directive!(@block_on $config [$($rest)*] $future)
+    };
+    (@block_on $config:ident [] $future:expr) => {
+        $config.block_on($future)
+    };
+
+    // The generated function, which returns a `Result` for `try_main!`.
+    (@fn [{retry $args:tt} $($dir:tt)*] $attr:tt $name:ident $gen:tt $params:tt $wc:tt [] $body:block) => {
+        ::core::compile_error!("`#[retry]` requires the function to return a `Result`");
+    };
+    (
+        @fn [{retry(times = $times:expr, on = $on:ty)} $($dir:tt)*] [$($attr:tt)*]
+        $name:ident [$($gen:tt)*] ($($param:tt)*) [$($wc:tt)*] [$ret:ty] $body:block
+    ) => {
+        $($attr)*
+        fn $name<$($gen)*>($($param)*) -> $ret where $($wc)* {
+            $crate::__directive! { @fn [$($dir)*] [] attempt [] () [] [$ret] $body }
+            $crate::__private::retry::<$on, _, _>($times, attempt)
+        }
+    };
+    (@fn [{retry $args:tt} $($rest:tt)*] $($tt:tt)*) => {
+        ::core::compile_error!("`#[retry]` expects `#[retry(times = N, on = ErrorType)]`");
+    };
+    (
+        @fn [{repeat($times:expr)} $($dir:tt)*] [$($attr:tt)*]
+        $name:ident [$($gen:tt)*] ($($param:tt)*) [$($wc:tt)*] [$($ret:ty)?] $body:block
+    ) => {
+        $($attr)*
+        fn $name<$($gen)*>($($param)*) where $($wc)* {
+            $crate::__directive! { @fn [$($dir)*] [] iteration [] () [] [$($ret)?] $body }
+            $crate::__private::repeat($times, iteration);
+        }
+    };
+    (
+        @fn [{expected($expected:expr)} $($dir:tt)*] [$($attr:tt)*]
+        $name:ident [$($gen:tt)*] ($($param:tt)*) [$($wc:tt)*] [$ret:ty] $body:block
+    ) => {
+        $($attr)*
+        fn $name<$($gen)*>($($param)*) where $($wc)* {
+            let value: $ret = (move || -> $ret { $body })();
+            ::core::assert_eq!(value, $expected);
+        }
+    };
+    (@fn [{expected $args:tt} $($dir:tt)*] $attr:tt $name:ident $gen:tt $params:tt $wc:tt [] $body:block) => {
+        ::core::compile_error!("`#[expected]` requires the function to return a value");
+    };
+    (
+        @fn [{display_errors} $($dir:tt)*] [$($attr:tt)*]
+        $name:ident [$($gen:tt)*] ($($param:tt)*) [$($wc:tt)*] [$ret:ty] $body:block
+    ) => {
+        $($attr)*
+        fn $name<$($gen)*>($($param)*) -> impl ::std::process::Termination where $($wc)* {
+            $crate::__private::display_errors((move || -> $ret { $body })())
+        }
+    };
+    (@fn [{display_errors} $($dir:tt)*] $attr:tt $name:ident $gen:tt $params:tt $wc:tt [] $body:block) => {
+        ::core::compile_error!("`#[display_errors]` requires the function to return a `Result`");
+    };
+    (
+        @fn [{try_main} $($dir:tt)*] [$($attr:tt)*]
+        $name:ident [$($gen:tt)*] ($($param:tt)*) [$($wc:tt)*] [$($ret:ty)?] $body:block
+    ) => {
+        $($attr)*
+        fn $name<$($gen)*>($($param)*) -> ::core::result::Result<
+            $crate::__directive!(@ty $($ret)?),
+            $crate::SetupError,
+        >
+        where
+            $($wc)*
+        $body
+    };
+    (
+        @fn [$($dir:tt)*] [$($attr:tt)*]
+        $name:ident [$($gen:tt)*] ($($param:tt)*) [$($wc:tt)*] [$($ret:ty)?] $body:block
+    ) => {
+        $($attr)*
+        fn $name<$($gen)*>($($param)*) $(-> $ret)? where $($wc)* $body
+    };
+    (@ty $ret:ty) => {
+        $ret
+    };
+    (@ty) => {
+        ()
+    };
+
+    // Define an `async fn` with the same body, to call from code that is already async.
+    (
+        @async_fn [{async_fn($async_name:ident)} $($dir:tt)*]
+        [$($gen:tt)*] $params:tt [$($wc:tt)*] [$($ret:ty)?] $body:block
+    ) => {
+        async fn $async_name<$($gen)*> $params $(-> $ret)? where $($wc)* $body
+    };
+    (@async_fn [$other:tt $($dir:tt)*] $gen:tt $params:tt $wc:tt $ret:tt $body:block) => {
+        $crate::__directive! { @async_fn [$($dir)*] $gen $params $wc $ret $body }
+    };
+    (@async_fn [] $($rest:tt)*) => {};
+
+    // Wrap the result of a runtime that can't fail to set up.
+    (@ok [{try_main} $($rest:tt)*] $result:expr) => {
+        ::core::result::Result::Ok($result)
+    };
+    (@ok [$($dir:tt)*] $result:expr) => {
+        $result
+    };
+}
+
+/// Run a future inside of a tokio context.
+#[cfg(feature = "tokio-compat")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __tokio_compat {
+    ($future:expr) => {
+        $crate::__private::Compat::new($future)
+    };
+}
+
+/// Run a future inside of a tokio context.
+#[cfg(not(feature = "tokio-compat"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __tokio_compat {
+    ($future:expr) => {
+        ::core::compile_error!(
+            "`#[tokio_compat]` requires the `tokio-compat` feature of `smol-macros`"
+        )
+    };
+}
+
+/// Enter a tokio context on every thread of the runtime.
+#[cfg(feature = "tokio-compat")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __tokio_bridge {
+    ($config:expr) => {
+        $config.tokio_bridge()
+    };
+}
+
+/// Enter a tokio context on every thread of the runtime.
+#[cfg(not(feature = "tokio-compat"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __tokio_bridge {
+    ($config:expr) => {
+        ::core::compile_error!(
+            "`#[tokio_bridge]` requires the `tokio-compat` feature of `smol-macros`"
+        )
+    };
+}
+
+/// Wrap a test in an asynchronous executor.
+///
+/// This is equivalent to the [`main`] macro, but adds the `#[test]` attribute.
+///
+/// ## Examples
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::test;
+///
+/// #[apply(test!)]
+/// async fn do_test() {
+///     assert_eq!(1 + 1, 2);
+/// }
+/// ```
+///
+/// ## Test Names
+///
+/// The `#[name = new_name]` attribute renames the generated test, which makes tests generated
+/// by other macros readable in the `cargo test` output.
+///
+/// ```
+/// use smol_macros::test;
+///
+/// macro_rules! check_sum {
+///     ($name:ident, $a:expr, $b:expr, $sum:expr) => {
+///         test! {
+///             #[name = $name]
+///             async fn check_sum() {
+///                 assert_eq!($a + $b, $sum);
+///             }
+///         }
+///     };
+/// }
+///
+/// check_sum!(one_plus_one, 1, 1, 2);
+/// check_sum!(two_plus_two, 2, 2, 4);
+/// ```
+///
+/// The standard test harness reports tests by the path of their function, so the name has to
+/// be a valid identifier. Arbitrary strings such as `#[name = "adds two numbers"]` are rejected,
+/// since there is no way to pass them on to the harness.
+///
+/// ## Reproducible Randomness
+///
+/// The `#[seed]` attribute picks a seed for the test, available through [`test_seed`], that can
+/// be used to create a random number generator such as [`fastrand::Rng`]. The seed is read from
+/// the `SMOL_TEST_SEED` environment variable if it is set, and is chosen randomly otherwise. If
+/// the test panics, the seed is printed as `test failed with seed=...` so that the failure can be
+/// replayed by setting `SMOL_TEST_SEED`.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{test, test_seed};
+///
+/// #[apply(test!)]
+/// #[seed]
+/// async fn shuffle() {
+///     let seed = test_seed().unwrap();
+///     let mut items = (0..10).collect::<Vec<u32>>();
+///     fastrand::Rng::with_seed(seed).shuffle(&mut items);
+///
+///     // The same seed always results in the same order.
+///     let mut replay = (0..10).collect::<Vec<u32>>();
+///     fastrand::Rng::with_seed(seed).shuffle(&mut replay);
+///     assert_eq!(items, replay);
+/// }
+/// ```
+///
+/// The seed is only available on the thread running the test body, so it has to be passed to
+/// tasks that run on the executor's worker threads. Prefer a dedicated generator over seeding
+/// `fastrand`'s thread-local one, since the executor draws from that one as well. A test that
+/// fails by returning an `Err` does not print the seed, only one that panics.
+///
+/// ## Expected Values
+///
+/// The `#[expected(value)]` attribute asserts that the value returned by the test is equal to
+/// `value`, which is useful for tests that compute a single result. The generated test returns
+/// `()`. The attribute isn't called `#[expect]`, since that name is taken by lint expectations.
+/// Since there is no error left to print, it can't be combined with `#[display_errors]`; compare
+/// against an `Err` instead.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{test, Executor};
+///
+/// #[apply(test!)]
+/// #[expected(42)]
+/// async fn compute(ex: &Executor<'_>) -> i32 {
+///     ex.spawn(async { 6 * 7 }).await
+/// }
+/// ```
+///
+/// ## Expected Timeouts
+///
+/// To check that an operation doesn't complete too early, like a lock that is still held or a
+/// request that waits for a cancellation that never comes, the `#[expect_timeout(1s)]`
+/// attribute inverts `#[timeout]`: the test passes once the body has been running for this long,
+/// and the body is cancelled by dropping it. If the body completes earlier, the test fails, even
+/// if it returned an `Err`. The duration is given like for `#[timeout]`, and like there, a body
+/// that blocks its thread isn't stopped.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::test;
+///
+/// #[apply(test!)]
+/// #[expect_timeout(10ms)]
+/// async fn never_ready() {
+///     futures_lite::future::pending::<()>().await;
+/// }
+/// ```
+///
+/// ## Readable Errors
+///
+/// When a test returns an `Err`, the test harness prints it using its [`Debug`] implementation,
+/// which is hard to read for error types like `anyhow::Error`. With the `#[display_errors]`
+/// attribute, the error is printed using its [`Display`] implementation in the alternate form
+/// (`{:#}`) instead, which includes the chain of causes for `anyhow::Error`. The function has to
+/// return a `Result` whose error type implements [`Display`].
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::test;
+/// use std::num::ParseIntError;
+///
+/// #[apply(test!)]
+/// #[display_errors]
+/// async fn parse() -> Result<(), ParseIntError> {
+///     assert_eq!("2".parse::<u32>()?, 2);
+///     Ok(())
+/// }
+/// ```
+///
+/// ## Failure Reports
+///
+/// To print more than the error when a test fails, like logs captured while it ran, the
+/// `#[report(path::to::function)]` attribute calls a function with a reference to the error,
+/// before the test fails with it. The function is only called when the test returns an `Err`,
+/// so the test has to return a `Result`. With `#[display_errors]`, it is called with the
+/// original error.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::test;
+/// use std::num::ParseIntError;
+///
+/// fn dump_diagnostics(err: &ParseIntError) {
+///     eprintln!("parsing failed: {err}");
+/// }
+///
+/// #[apply(test!)]
+/// #[report(dump_diagnostics)]
+/// async fn parse() -> Result<(), ParseIntError> {
+///     assert_eq!("2".parse::<u32>()?, 2);
+///     Ok(())
+/// }
+/// ```
+///
+/// ## Stress Testing
+///
+/// Races often only show up once in a while. The `#[repeat(N)]` attribute runs the test `N`
+/// times within the same test function, to make them show up more reliably. Every iteration sets
+/// up a fresh executor, along with its thread pool, so that no tasks leak from one iteration into
+/// the next. Once an iteration panics or returns an `Err`, the remaining iterations are skipped
+/// and the number of the failing iteration is printed along with the failure.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{test, Executor};
+///
+/// #[apply(test!)]
+/// #[repeat(100)]
+/// async fn racy(ex: &Executor<'_>) {
+///     let (a, b) = (ex.spawn(async { 1 }), ex.spawn(async { 2 }));
+///     assert_eq!(a.await + b.await, 3);
+/// }
+/// ```
+///
+/// This composes with `#[expected]`, which then checks the value of every iteration, but not with
+/// `#[display_errors]`.
+///
+/// ## Retrying Flaky Tests
+///
+/// Tests against an external service may fail for reasons that have nothing to do with the code
+/// under test, like a dropped connection. With `#[retry(times = N, on = ErrorType)]`, a test that
+/// returns an `Err` holding an error of type `ErrorType` runs again, up to `N` times in total,
+/// with a fresh executor for every attempt. The error matches if it is of that type itself, or
+/// a `Box<dyn Error>` holding one. Other errors and panics fail the test right away, and once
+/// all attempts failed, the test fails with the last error. Unlike `#[repeat]`, which runs the
+/// test a fixed number of times, this only runs it again after a matching failure.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::test;
+/// use std::io;
+///
+/// #[apply(test!)]
+/// #[retry(times = 3, on = io::Error)]
+/// async fn fetch() -> Result<(), Box<dyn std::error::Error>> {
+///     let response = "200".parse::<u16>()?;
+///     assert_eq!(response, 200);
+///     Ok(())
+/// }
+/// ```
+///
+/// The test has to return a `Result` whose error implements [`Debug`](std::fmt::Debug), and
+/// this doesn't compose with `#[repeat]`, `#[expected]` or `#[display_errors]`.
+///
+/// ## Serial Tests
+///
+/// The test harness runs tests in parallel, which breaks tests that use a global resource, like
+/// an environment variable or a fixed port. Tests with the `#[serial("group")]` attribute take a
+/// process-wide lock for their group while they run, so that only one test of the group runs at
+/// a time, like with the `serial_test` crate. Tests without the attribute or in other groups
+/// still run in parallel with them. `#[serial]` without a group uses a default group of its own.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::test;
+///
+/// #[apply(test!)]
+/// #[serial("env")]
+/// async fn reads_env() {
+///     std::env::set_var("MY_SETTING", "1");
+///     assert_eq!(std::env::var("MY_SETTING").as_deref(), Ok("1"));
+///     std::env::remove_var("MY_SETTING");
+/// }
+/// ```
+///
+/// The lock is taken before the runtime is set up and released once it has been torn down.
+/// With `#[repeat]`, it is taken for every iteration on its own.
+///
+/// Tests that change environment variables can also use `#[isolate_env]`, which takes a snapshot
+/// of the environment before the runtime is set up and restores it once the runtime has been torn
+/// down, even if the test panics. Variables that the test set are removed again, and the ones it
+/// changed or removed get their old values back, while the ones that are unchanged are left
+/// alone. Since the environment is shared by the whole process, other tests still see the
+/// changes while the test runs, and the changes that they make to the environment in the
+/// meantime are undone as well. The attribute is best combined with `#[serial("env")]` on every
+/// test that touches the environment. The lock has to come first, so that the environment is
+/// restored before the next test of the group starts.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::test;
+///
+/// #[apply(test!)]
+/// #[serial("env")]
+/// #[isolate_env]
+/// async fn sets_env() {
+///     std::env::set_var("MY_SETTING", "2");
+///     assert_eq!(std::env::var("MY_SETTING").as_deref(), Ok("2"));
+/// }
+/// ```
+///
+/// ## Required Resources
+///
+/// Some tests need resources that aren't available everywhere, like a hermetic CI that blocks
+/// the network. Tests with the `#[requires(network)]` attribute are skipped unless the
+/// `SMOL_TEST_ALLOW` environment variable lists the resource, like
+/// `SMOL_TEST_ALLOW=network,filesystem`. A test can require several resources at once, like
+/// `#[requires(network, filesystem)]`, in which case all of them have to be allowed. The names
+/// are arbitrary identifiers, so `network` and `filesystem` are only conventions.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::test;
+///
+/// #[apply(test!)]
+/// #[requires(network)]
+/// async fn resolves_example() {
+///     use std::net::ToSocketAddrs;
+///
+///     let addrs = blocking::unblock(|| ("example.com", 80).to_socket_addrs()).await;
+///     assert!(addrs.unwrap().next().is_some());
+/// }
+/// ```
+///
+/// Since the test harness has no way to ignore a test once it has started, a skipped test returns
+/// right away, before the runtime is set up, and passes with a message on stderr. Tests that
+/// return a `Result` return `Ok(())`. With `#[repeat]`, every iteration is skipped on its own.
+///
+/// ## Captured Output
+///
+/// The test harness captures what a test prints and only shows it if the test fails. A thread
+/// spawned while a test runs prints into the capture of that test, for as long as the thread
+/// lives. The worker threads of the thread pool are spawned by the test and joined before it
+/// returns, so what the tasks print on them is shown along with the output of the test, as if
+/// the test were synchronous.
+///
+/// Threads that outlive the test keep printing into its capture instead of the capture of the
+/// test that is running, and there is no way to move them to the capture of another test. So
+/// `#[shared_executor]` trades the captured output of tasks for not spawning a thread pool for
+/// every test: the shared threads are started by the first test that uses them, and what tasks
+/// print on them later is lost, even if the test fails. What the body prints on the current
+/// thread is still shown. The same goes for threads that aren't spawned by this crate, like
+/// those of `blocking::unblock`, which are reused by the tests that run after the one that
+/// spawned them.
+///
+/// ## Custom Test Attributes
+///
+/// The generated function is marked with the standard `#[test]` attribute. The
+/// `#[test_attr(path::to::test)]` attribute replaces it with another one, for test frameworks
+/// that use their own attribute, like `#[test_attr(test_case)]` with
+/// `#![feature(custom_test_frameworks)]` or `#[test_attr(wasm_bindgen_test)]`. Anything that can
+/// go inside of `#[...]` is accepted.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{test, Executor};
+///
+/// #[apply(test!)]
+/// #[test_attr(core::prelude::v1::test)]
+/// async fn custom_attribute(ex: &Executor<'_>) {
+///     assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+/// }
+/// ```
+///
+/// Other attributes, like `#[ignore]` or `#[should_panic]`, are placed after `#[test]` on the
+/// generated function, and so is `#[cfg_attr(...)]`. This makes conditional attributes like
+/// `#[cfg_attr(miri, ignore)]` work as they would on a synchronous test. The attributes of this
+/// macro can't be applied conditionally this way, since they are only recognized when they are
+/// written out directly.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{test, Executor};
+///
+/// #[apply(test!)]
+/// #[cfg_attr(miri, ignore)]
+/// async fn too_slow_for_miri(ex: &Executor<'_>) {
+///     assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+/// }
+/// ```
+///
+/// [`fastrand::Rng`]: https://docs.rs/fastrand/latest/fastrand/struct.Rng.html
+/// [`Debug`]: std::fmt::Debug
+/// [`Display`]: std::fmt::Display
+#[macro_export]
+macro_rules! test {
+    ($($tt:tt)*) => {
+        $crate::__main! {
+            [] [#[core::prelude::v1::test]]
+            $($tt)*
+        }
+    };
+}
+
+/// Generate a test for every case in a table.
+///
+/// The `#[cases(...)]` attribute lists the cases as tuples, with one field for each parameter of
+/// the function. For every case, a [`test`] is generated that binds the fields to the parameters
+/// and runs the body. The tests are placed in a module named after the function, and are named
+/// after the index of their case, so the example below generates `add::case_0` and
+/// `add::case_1`. Other attributes are passed on to [`test`].
+///
+/// ```
+/// use smol_macros::cases;
+///
+/// cases! {
+///     #[cases((1, 2, 3), (2, 2, 4))]
+///     async fn add(a: i32, b: i32, expected: i32) {
+///         assert_eq!(a + b, expected);
+///     }
+/// }
+/// ```
+///
+/// The module imports everything from the module the macro is used in. At most 64 cases are
+/// supported, and the cases can't use an executor parameter.
+#[macro_export]
+macro_rules! cases {
+    (
+        #[cases($($case:tt),+ $(,)?)]
+        $(#[$($attr:tt)*])*
+        async fn $name:ident ($($arg:ident : $argty:ty),* $(,)?) $(-> $ret:ty)? $bl:block
+    ) => {
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $crate::__cases! {
+                [$(#[$($attr)*])*] [$($arg : $argty),*] [$($ret)?] $bl
+                [$($case)+]
+                [
+                    case_0 case_1 case_2 case_3 case_4 case_5 case_6 case_7
+                    case_8 case_9 case_10 case_11 case_12 case_13 case_14
+                    case_15 case_16 case_17 case_18 case_19 case_20 case_21
+                    case_22 case_23 case_24 case_25 case_26 case_27 case_28
+                    case_29 case_30 case_31 case_32 case_33 case_34 case_35
+                    case_36 case_37 case_38 case_39 case_40 case_41 case_42
+                    case_43 case_44 case_45 case_46 case_47 case_48 case_49
+                    case_50 case_51 case_52 case_53 case_54 case_55 case_56
+                    case_57 case_58 case_59 case_60 case_61 case_62 case_63
+                ]
+            }
+        }
+    };
+}
+
+/// Implementation detail of [`cases`].
+///
+/// Generates a test for the first case, naming it after the first unused name.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cases {
+    (
+        [$($attr:tt)*] [$($arg:ident : $argty:ty),*] [$($ret:ty)?] $bl:block
+        [($($value:expr),* $(,)?) $($case:tt)*]
+        [$test:ident $($names:ident)*]
+    ) => {
+        $crate::test! {
+            $($attr)*
+            async fn $test() $(-> $ret)? {
+                $(let $arg: $argty = $value;)*
+                $bl
+            }
+        }
+
+        $crate::__cases! {
+            [$($attr)*] [$($arg : $argty),*] [$($ret)?] $bl
+            [$($case)*]
+            [$($names)*]
+        }
+    };
+    (
+        [$($attr:tt)*] [$($arg:ident : $argty:ty),*] [$($ret:ty)?] $bl:block
+        [$case:tt $($cases:tt)*]
+        []
+    ) => {
+        ::core::compile_error!("too many cases, at most 64 are supported");
+    };
+    (
+        [$($attr:tt)*] [$($arg:ident : $argty:ty),*] [$($ret:ty)?] $bl:block
+        []
+        [$($names:ident)*]
+    ) => {};
+}
+
+/// Generate a blocking and an `async` test from the same body.
+///
+/// Libraries that offer both a blocking and an `async` API often test the same scenario against
+/// both. The body is written once as an `async fn` whose only parameter is a [`Strategy`], which
+/// decides which of the two APIs [`Strategy::call`] calls. Two tests are generated in a module
+/// named after the function: `blocking`, a plain `#[test]` that passes [`Blocking`], and
+/// `non_blocking`, a [`test`] that passes [`NonBlocking`]. The example below generates
+/// `read_back::blocking` and `read_back::non_blocking`.
+///
+/// ```
+/// use smol_macros::{dual_test, Strategy};
+///
+/// fn read(n: u32) -> u32 {
+///     n
+/// }
+///
+/// async fn read_async(n: u32) -> u32 {
+///     n
+/// }
+///
+/// dual_test! {
+///     async fn read_back(strategy: impl Strategy) {
+///         let n = strategy.call(|| read(1), || read_async(1)).await;
+///         assert_eq!(n, 1);
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// The parameter has to be an `impl Trait` of [`Strategy`]. Attributes are passed on
+/// to both tests, so only the ones that a plain `#[test]` supports can be used, like `#[ignore]`
+/// and `#[should_panic]`. The module imports everything from the module the macro is used in.
+#[macro_export]
+macro_rules! dual_test {
+    (
+        $(#[$($attr:tt)*])*
+        async fn $name:ident ($strategy:ident : impl $bound:path $(,)?) $(-> $ret:ty)? $bl:block
+    ) => {
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            async fn body($strategy: impl $bound) $(-> $ret)? $bl
+
+            #[core::prelude::v1::test]
+            $(#[$($attr)*])*
+            fn blocking() $(-> $ret)? {
+                $crate::__private::block_on(body($crate::Blocking))
+            }
+
+            $crate::test! {
+                $(#[$($attr)*])*
+                async fn non_blocking() $(-> $ret)? {
+                    body($crate::NonBlocking).await
+                }
+            }
+        }
+    };
+}
+
+/// Run a future on a runtime set up like the one of [`main`], as an expression.
+///
+/// `run!(future)` blocks on the future and returns its output, without creating an executor.
+/// `run!(|ex| future)` or `run!(ex => future)` creates a thread-safe [`Executor`] running on a
+/// thread pool, and binds a reference to it to `ex` while the future runs. Another executor can
+/// be chosen with a type annotation, like `run!(|ex: &LocalExecutor<'_>| future)`, in which case
+/// the same types as for [`main`] are supported.
+///
+/// This is useful in places where defining an `async fn` is inconvenient, like in build scripts
+/// or in a `fn main` that does other things too. The attributes of [`main`] aren't supported.
+///
+/// ## Examples
+///
+/// ```
+/// use smol_macros::run;
+///
+/// let answer = run!(|ex| async move {
+///     ex.spawn(async { 6 * 7 }).await
+/// });
+/// assert_eq!(answer, 42);
+///
+/// let greeting = run!(async { "hello" });
+/// assert_eq!(greeting, "hello");
+/// ```
+///
+/// ## Doc Examples
+///
+/// Examples in documentation are compiled as the body of a `fn main`, which can't be `async`.
+/// Wrapping the example in `run!` on hidden lines, which start with `#`, lets it use `.await`
+/// while readers only see the code that matters. Returning a `Result` from the future makes `?`
+/// work as well. The `smol-macros` crate then has to be a dev-dependency of the crate whose
+/// documentation it is.
+///
+/// ````text
+/// /// ```
+/// /// # smol_macros::run!(async {
+/// /// let contents = async_fs::read_to_string("Cargo.toml").await?;
+/// /// assert!(contents.contains("[package]"));
+/// /// # Ok::<_, std::io::Error>(()) }).unwrap();
+/// /// ```
+/// ````
+///
+/// The executor can be used in the same way. Only the two lines in the middle of this example
+/// are shown:
+///
+/// ```
+/// # smol_macros::run!(|ex| async move {
+/// let number = ex.spawn(async { "42".parse::<u32>() }).await?;
+/// assert_eq!(number, 42);
+/// # Ok::<_, std::num::ParseIntError>(()) }).unwrap();
+/// ```
+#[macro_export]
+macro_rules! run {
+    (|$ex:ident : & $exty:ty| $future:expr) => {{
+        let config = $crate::__private::Config::new();
+        <$exty as $crate::__private::MainExecutor>::with_main(&config, |ex| {
+            config.block_on(ex.run(async move {
+                let $ex = ex;
+                $future.await
+            }))
+        })
+    }};
+    (|$ex:ident| $future:expr) => {
+        $crate::run!(|$ex: &$crate::Executor<'_>| $future)
+    };
+    ($ex:ident => $future:expr) => {
+        $crate::run!(|$ex: &$crate::Executor<'_>| $future)
+    };
+    ($future:expr) => {
+        $crate::__private::Config::new().block_on($future)
+    };
+}
+
+/// Set up a runtime like [`main`] does, but drive it with an expression of your own.
+///
+/// [`run`] and [`main`] block on the main future with `block_on(ex.run(future))`. For programs
+/// that need to drive the executor differently, like polling other futures next to it or using
+/// their own `block_on`, `main_with_driver!(|ex| expr)` only creates the executor and its thread
+/// pool, and evaluates `expr` with a reference to the executor bound to `ex`. The thread pool is
+/// stopped once `expr` returns, and its value is returned. The same executor types as for
+/// [`main`] can be chosen with a type annotation, like `main_with_driver!(|ex: &LocalExecutor<'_>|
+/// expr)`, and a thread-safe [`Executor`] is used without one.
+///
+/// The current thread only runs the tasks of the executor if `expr` does so, like through
+/// [`Executor::run`], which a [`LocalExecutor`] always needs, since it has no worker threads.
+/// The attributes of [`main`] aren't supported.
+///
+/// ```
+/// use futures_lite::future;
+/// use smol_macros::main_with_driver;
+///
+/// let (answer, ticks) = main_with_driver!(|ex| {
+///     let task = ex.spawn(async { 6 * 7 });
+///     let mut ticks = 0;
+///     let answer = async_io::block_on(ex.run(async {
+///         // Poll other futures alongside the main one.
+///         let ticker = async {
+///             loop {
+///                 ticks += 1;
+///                 future::yield_now().await;
+///             }
+///         };
+///         future::or(task, ticker).await
+///     }));
+///     (answer, ticks)
+/// });
+/// assert_eq!(answer, 42);
+/// ```
+///
+/// [`Executor::run`]: https://docs.rs/async-executor/latest/async_executor/struct.Executor.html#method.run
+#[macro_export]
+macro_rules! main_with_driver {
+    (|$ex:ident : & $exty:ty| $drive:expr) => {{
+        let config = $crate::__private::Config::new();
+        <$exty as $crate::__private::MainExecutor>::with_main(&config, |$ex| $drive)
+    }};
+    (|$ex:ident| $drive:expr) => {
+        $crate::main_with_driver!(|$ex: &$crate::Executor<'_>| $drive)
+    };
+}
+
+/// Assert that the tasks of the runtime can run on several threads at once.
+///
+/// Tests of concurrent code can pass on a single thread without exercising what they are meant
+/// to, e.g. on a CI runner with a single CPU. Calling `assert_multithreaded!()` at the start of
+/// such a test makes it fail instead, unless the thread pool has at least two worker threads, as
+/// reported by [`worker_threads`]. A custom panic message can be passed like for [`assert!`].
+///
+/// ## Examples
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{assert_multithreaded, test, Executor};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// #[apply(test!)]
+/// async fn no_lost_updates(ex: &Executor<'_>) {
+///     assert_multithreaded!("no_lost_updates needs at least two worker threads");
+///
+///     let counter = AtomicUsize::new(0);
+///     let tasks = (0..100).map(|_| ex.spawn(async { counter.fetch_add(1, Ordering::SeqCst) }));
+///     for task in tasks.collect::<Vec<_>>() {
+///         task.await;
+///     }
+///     assert_eq!(counter.load(Ordering::SeqCst), 100);
+/// }
+/// ```
+///
+/// [`worker_threads`]: crate::main_executor::worker_threads
+#[macro_export]
+macro_rules! assert_multithreaded {
+    () => {{
+        let workers = $crate::main_executor::worker_threads();
+        if workers < 2 {
+            ::core::panic!(
+                "the test needs a multithreaded runtime, but it runs on {} worker thread(s); \
+                 this happens with a single CPU, a single-threaded executor or the \
+                 `single-thread` feature",
+                workers,
+            );
+        }
+    }};
+    ($($arg:tt)+) => {{
+        if $crate::main_executor::worker_threads() < 2 {
+            ::core::panic!($($arg)+);
+        }
+    }};
+}
+
+/// Spawn a fixed number of tasks that process the items received from a channel.
+///
+/// `spawn_workers!(ex, count = K, handler, receiver)` spawns `K` tasks onto `ex`. Each task
+/// repeatedly calls `receiver.recv().await` and passes the item to `handler(item).await`,
+/// stopping once the channel is closed. The handles to the tasks are returned in a `Vec`.
+///
+/// The receiver can be any cloneable type with an `async fn recv(&self) -> Result<T, E>` method,
+/// like [`async_channel::Receiver`]. The handler is cloned into each task, so it needs to be
+/// `Clone`.
+///
+/// ## Examples
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, spawn_workers, Executor};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// #[apply(main!)]
+/// async fn main(ex: &Executor<'_>) {
+///     let (sender, receiver) = async_channel::unbounded();
+///     let total = Arc::new(AtomicUsize::new(0));
+///
+///     let workers = spawn_workers!(ex, count = 4, {
+///         let total = total.clone();
+///         move |item: usize| {
+///             let total = total.clone();
+///             async move {
+///                 total.fetch_add(item, Ordering::SeqCst);
+///             }
+///         }
+///     }, receiver);
+///
+///     for i in 1..=10 {
+///         sender.send(i).await.unwrap();
+///     }
+///
+///     // Closing the channel stops the workers.
+///     drop(sender);
+///     for worker in workers {
+///         worker.await;
+///     }
+///
+///     assert_eq!(total.load(Ordering::SeqCst), 55);
+/// }
+/// ```
+///
+/// [`async_channel::Receiver`]: https://docs.rs/async-channel/latest/async_channel/struct.Receiver.html
+#[macro_export]
+macro_rules! spawn_workers {
+    ($ex:expr, count = $count:expr, $handler:expr, $receiver:expr $(,)?) => {{
+        let ex = &$ex;
+        let handler = $handler;
+        let receiver = $receiver;
+
+        (0..$count)
+            .map(|_| {
+                let handler = ::core::clone::Clone::clone(&handler);
+                let receiver = ::core::clone::Clone::clone(&receiver);
+                ex.spawn(async move {
+                    while let ::core::result::Result::Ok(item) = receiver.recv().await {
+                        handler(item).await;
+                    }
+                })
+            })
+            .collect::<::std::vec::Vec<_>>()
+    }};
+}
+
+mod abort;
+mod any_executor;
+mod backtrace;
+mod cancellation;
+#[cfg(all(
+    feature = "cgroup-quota",
+    target_os = "linux",
+    not(any(feature = "single-thread", target_family = "wasm"))
+))]
+mod cgroup;
+mod display;
+mod env;
+mod exit_reason;
+mod instrumented;
+mod lazy_executor;
+mod lifecycle;
+pub mod main_executor;
+mod mock_clock;
+mod pinned_executor;
+mod priority_executor;
+mod queue_limit;
+mod reactor;
+mod repeat;
+mod requires;
+mod scheduled_executor;
+mod seed;
+mod serial;
+mod service_set;
+mod shards;
+mod spawn_blocking;
+mod spawn_isolated;
+mod split_executor;
+mod step_executor;
+mod strategy;
+mod stream;
+mod task_limit;
+#[cfg(feature = "task-names")]
+mod task_names;
+mod task_set;
+#[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+mod thread_pool;
+mod ticker;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::abort::abort_on_panic;
+    pub use crate::backtrace::capture_backtrace;
+    pub use crate::display::{display_errors, report_error};
+    pub use crate::env::isolate_env;
+    pub use crate::lifecycle::print_timings;
+    pub use crate::main_executor::{
+        drain_with_timeout, enter_runtime, expect_timeout, parse_duration, prewarm_blocking,
+        requires_local, run_with_budget, set_default_env, set_thread_name, stop_on_panic,
+        wait_until_empty, with_panic_context, with_timeout, Config, MainExecutor, SharedExecutor,
+        DEFAULT_WORKER_RESTARTS,
+    };
+    pub use crate::repeat::{repeat, retry};
+    pub use crate::requires::{requires, Skipped};
+    pub use crate::scheduled_executor::requires_scheduled;
+    pub use crate::seed::with_seed;
+    pub use crate::serial::serial;
+    pub use crate::shards::Shards;
+    pub use crate::step_executor::requires_step;
+    pub use crate::stream::{drain_stream, Drained};
+    #[cfg(feature = "tokio-compat")]
+    pub use async_compat::Compat;
+    pub use async_io::block_on;
+    pub use futures_lite::future::or;
+    pub use std::rc::Rc;
 }
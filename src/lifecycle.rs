@@ -0,0 +1,188 @@
+//! Events for the lifecycle of a runtime running in the background.
+
+use async_channel::{Receiver, Sender};
+use futures_lite::Stream;
+use std::pin::Pin;
+use std::sync::{Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// A step in the lifecycle of a [`Runtime`].
+///
+/// [`Runtime`]: crate::main_executor::Runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifecycleEvent {
+    /// The worker thread with the given index is about to run tasks.
+    WorkerOnline(usize),
+
+    /// All of the worker threads are running tasks.
+    Started,
+
+    /// The worker threads have been told to stop.
+    Stopping,
+
+    /// All of the worker threads have exited.
+    Stopped,
+}
+
+/// How long the thread pool of a run took to start up and to shut down.
+///
+/// This is passed to the function given to [`Config::time_lifecycle`] once the worker threads
+/// have been joined.
+///
+/// [`Config::time_lifecycle`]: crate::main_executor::Config::time_lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LifecycleTimings {
+    /// The number of worker threads that were spawned when the pool started.
+    pub threads: usize,
+
+    /// How long spawning the worker threads took.
+    pub startup: Duration,
+
+    /// How long it took from telling the worker threads to stop until all of them were joined.
+    pub shutdown: Duration,
+}
+
+/// Print the timings of a thread pool to stderr, for `#[time_lifecycle]` without a function.
+#[doc(hidden)]
+pub fn print_timings(timings: LifecycleTimings) {
+    let threads = match timings.threads {
+        1 => "1 worker thread".to_string(),
+        threads => format!("{threads} worker threads"),
+    };
+    eprintln!(
+        "smol-macros: started {threads} in {:?}, stopped and joined them in {:?}",
+        timings.startup, timings.shutdown
+    );
+}
+
+/// The stream of [`LifecycleEvent`]s of a [`Runtime`], created by [`Runtime::start_with_events`].
+///
+/// The events are buffered until they are received, so none are missed if the stream is polled
+/// late. The stream ends after [`LifecycleEvent::Stopped`].
+///
+/// [`Runtime`]: crate::main_executor::Runtime
+/// [`Runtime::start_with_events`]: crate::main_executor::Runtime::start_with_events
+#[derive(Debug)]
+pub struct LifecycleEvents(Pin<Box<Receiver<LifecycleEvent>>>);
+
+impl Stream for LifecycleEvents {
+    type Item = LifecycleEvent;
+
+    #[inline]
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.as_mut().poll_next(cx)
+    }
+}
+
+/// Reports the lifecycle of the worker threads of a pool.
+#[derive(Debug)]
+pub(crate) struct Lifecycle {
+    /// Sends the events to the stream.
+    sender: Sender<LifecycleEvent>,
+
+    /// The state of the worker threads.
+    state: Mutex<State>,
+}
+
+/// The state of the worker threads of a pool.
+#[derive(Debug, Default)]
+struct State {
+    /// The number of worker threads that have been spawned.
+    spawned: usize,
+
+    /// Whether all of the worker threads have been spawned.
+    all_spawned: bool,
+
+    /// The number of worker threads that are running tasks.
+    online: usize,
+
+    /// The number of worker threads that have been spawned and haven't exited yet.
+    live: usize,
+
+    /// Whether the pool has been told to stop.
+    stopping: bool,
+}
+
+impl Lifecycle {
+    /// Create the reporter for a pool, along with its stream.
+    pub(crate) fn new() -> (Self, LifecycleEvents) {
+        let (sender, receiver) = async_channel::unbounded();
+        let lifecycle = Self {
+            sender,
+            state: Mutex::new(State::default()),
+        };
+        (lifecycle, LifecycleEvents(Box::pin(receiver)))
+    }
+
+    /// Record that a worker thread has been spawned.
+    #[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+    pub(crate) fn worker_spawned(&self) {
+        let mut state = self.state();
+        state.spawned += 1;
+        state.live += 1;
+    }
+
+    /// Record that all of the worker threads have been spawned.
+    pub(crate) fn all_spawned(&self) {
+        let mut state = self.state();
+        state.all_spawned = true;
+        if !state.stopping && state.online == state.spawned {
+            self.send(LifecycleEvent::Started);
+        }
+    }
+
+    /// Record that a worker thread is about to run tasks.
+    #[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+    pub(crate) fn worker_online(&self, index: usize) {
+        let mut state = self.state();
+        if state.stopping {
+            return;
+        }
+
+        self.send(LifecycleEvent::WorkerOnline(index));
+        state.online += 1;
+        if state.all_spawned && state.online == state.spawned {
+            self.send(LifecycleEvent::Started);
+        }
+    }
+
+    /// Record that a worker thread has exited.
+    #[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+    pub(crate) fn worker_exited(&self) {
+        let mut state = self.state();
+        state.live -= 1;
+        if state.live == 0 && state.stopping {
+            self.send(LifecycleEvent::Stopped);
+        }
+    }
+
+    /// Record that the pool has been told to stop.
+    pub(crate) fn stopping(&self) {
+        let mut state = self.state();
+        if state.stopping {
+            return;
+        }
+
+        state.stopping = true;
+        self.send(LifecycleEvent::Stopping);
+        if state.live == 0 {
+            self.send(LifecycleEvent::Stopped);
+        }
+    }
+
+    /// Lock the state, even if a worker thread panicked while holding it.
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Send an event, ending the stream after the last one.
+    fn send(&self, event: LifecycleEvent) {
+        // The stream may have been dropped, in which case nobody is interested in the events.
+        self.sender.try_send(event).ok();
+        if event == LifecycleEvent::Stopped {
+            self.sender.close();
+        }
+    }
+}
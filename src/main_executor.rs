@@ -0,0 +1,2191 @@
+//! Setting up executors for `main`.
+
+use crate::lifecycle::Lifecycle;
+use crate::reactor::{self, ReactorFns};
+use crate::requires::Skipped;
+use crate::shards::Shards;
+use crate::{
+    AnyExecutor, CancellationToken, DropGuard, Executor, InstrumentedExecutor, LazyExecutor,
+    LocalExecutor, PinnedExecutor, PriorityExecutor, Reactor, Schedule, ScheduledExecutor,
+    SplitExecutor, StepExecutor,
+};
+use async_executor::Task;
+use async_io::block_on;
+use futures_lite::future::{self, FutureExt};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::future::Future;
+use std::hint;
+use std::io;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+pub use crate::lifecycle::{LifecycleEvent, LifecycleEvents, LifecycleTimings};
+
+#[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+use crate::thread_pool::{
+    pinned_workers, with_pinned_pool, with_priority_pool, with_scheduled_pool, with_sharded_pool,
+    with_shared_pool, with_thread_pool, DetachedPool,
+};
+
+thread_local! {
+    /// Whether the current thread is running a runtime set up by this crate.
+    static IN_RUNTIME: Cell<bool> = const { Cell::new(false) };
+
+    /// Whether the current thread is polling a main future wrapped in [`stop_on_panic`].
+    static POLLING_MAIN: Cell<bool> = const { Cell::new(false) };
+
+    /// The number of threads running the thread pool that the current thread belongs to.
+    static WORKER_THREADS: Cell<usize> = const { Cell::new(0) };
+
+    /// The executor that the current thread runs, if it can be handed out.
+    static CURRENT_EXECUTOR: RefCell<Option<Arc<Executor<'static>>>> = const { RefCell::new(None) };
+
+    /// The ids of the worker threads of the thread pool that the current thread belongs to.
+    static WORKER_THREAD_IDS: RefCell<Option<WorkerThreadIds>> = const { RefCell::new(None) };
+}
+
+/// The ids of the worker threads of a thread pool, which change as threads start and exit.
+pub(crate) type WorkerThreadIds = Arc<Mutex<Vec<ThreadId>>>;
+
+/// Check whether the current thread is running inside of a runtime set up by this crate.
+///
+/// This is `true` on the thread blocking on the main future and on the worker threads of the
+/// thread pool, and `false` everywhere else. Libraries can use this to avoid blocking on a
+/// future from within a runtime that is already blocking the thread.
+///
+/// ```
+/// use smol_macros::{main, main_executor::in_runtime};
+///
+/// main! {
+///     async fn check() {
+///         assert!(in_runtime());
+///     }
+/// }
+///
+/// fn main() {
+///     assert!(!in_runtime());
+///     check();
+/// }
+/// ```
+#[inline]
+pub fn in_runtime() -> bool {
+    IN_RUNTIME.with(Cell::get)
+}
+
+/// Get the number of threads that run the tasks of the thread-safe executor.
+///
+/// This is the size of the thread pool that the current thread belongs to, counting the thread
+/// blocking on the main future if it is one of the workers. It is `0` outside of a thread pool,
+/// e.g. when running a [`LocalExecutor`] or with the `single-thread` feature, since the tasks
+/// are then only run by the current thread while it polls the main future.
+///
+/// ```
+/// use smol_macros::{main, main_executor::worker_threads, Executor};
+///
+/// main! {
+///     async fn check(ex: &Executor<'_>) {
+///         let workers = ex.spawn(async { worker_threads() }).await;
+///         assert_eq!(workers, worker_threads());
+///     }
+/// }
+///
+/// fn main() {
+///     assert_eq!(worker_threads(), 0);
+///     check();
+/// }
+/// ```
+#[inline]
+pub fn worker_threads() -> usize {
+    WORKER_THREADS.with(Cell::get)
+}
+
+/// Run a function with the current thread marked as belonging to a thread pool of this size.
+#[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+pub(crate) fn with_worker_threads<T>(workers: usize, f: impl FnOnce() -> T) -> T {
+    /// Restores the previous size once the function returns or panics.
+    struct Guard(usize);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            WORKER_THREADS.with(|workers| workers.set(self.0));
+        }
+    }
+
+    let _guard = Guard(WORKER_THREADS.with(|current| current.replace(workers)));
+    f()
+}
+
+/// Get the ids of the worker threads that run the tasks of the thread-safe executor.
+///
+/// This lists the threads of the thread pool that the current thread belongs to, in the order
+/// they started, including the thread blocking on the main future if it is one of the workers.
+/// Threads are added once they start and removed once they exit, so the list follows the pool as
+/// it grows and shrinks. Like [`worker_threads`], it is empty outside of a thread pool, and with
+/// `#[shared_executor]`, since the threads of the process-wide pool are shared by all runtimes.
+///
+/// Every thread pool has a list of its own. When several of them run at once, e.g. in parallel
+/// tests, each thread sees the list of its own pool, and the threads of the other pools aren't
+/// in it. This makes it suitable for checking whether code runs on the pool of the current
+/// runtime.
+///
+/// ```
+/// use smol_macros::{main, main_executor::worker_thread_ids, Executor};
+/// use std::thread;
+///
+/// fn on_worker_thread() -> bool {
+///     worker_thread_ids().contains(&thread::current().id())
+/// }
+///
+/// main! {
+///     async fn check(_ex: &Executor<'_>) {
+///         // The current thread blocks on the main future, and isn't one of the workers.
+///         assert!(!on_worker_thread());
+///     }
+/// }
+///
+/// fn main() {
+///     assert!(worker_thread_ids().is_empty());
+///     check();
+/// }
+/// ```
+pub fn worker_thread_ids() -> Vec<ThreadId> {
+    WORKER_THREAD_IDS.with(|ids| match &*ids.borrow() {
+        Some(ids) => ids.lock().unwrap_or_else(PoisonError::into_inner).clone(),
+        None => Vec::new(),
+    })
+}
+
+/// Run a function with the current thread marked as belonging to the thread pool with these
+/// worker threads.
+#[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+pub(crate) fn with_worker_thread_ids<T>(ids: WorkerThreadIds, f: impl FnOnce() -> T) -> T {
+    /// Restores the previous worker threads once the function returns or panics.
+    struct Guard(Option<WorkerThreadIds>);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            WORKER_THREAD_IDS.with(|ids| *ids.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _guard = Guard(WORKER_THREAD_IDS.with(|current| current.replace(Some(ids))));
+    f()
+}
+
+/// Get a handle to the executor that the current thread runs.
+///
+/// This lets code deep in the call stack spawn tasks without an executor being passed down to
+/// it. The handle is available on the thread blocking on the main future and on the worker
+/// threads, but only for executors that can be shared without borrowing from `main`: an
+/// `Arc<Executor<'static>>`, or any thread-safe executor with `#[shared_executor]`. Otherwise,
+/// and outside of a runtime, this returns `None`.
+///
+/// Since the executor is `'static`, the tasks spawned onto it can't borrow anything. They are
+/// dropped along with the executor once the main future completes, even if a handle is still
+/// around, so holding on to a handle doesn't keep the tasks running.
+///
+/// ```
+/// use smol_macros::{main, main_executor::current_executor, Executor};
+/// use std::sync::Arc;
+///
+/// fn spawn_job() {
+///     current_executor()
+///         .expect("not running on an executor")
+///         .spawn(async { println!("Hello world!") })
+///         .detach();
+/// }
+///
+/// main! {
+///     async fn run(_ex: &Arc<Executor<'_>>) {
+///         spawn_job();
+///     }
+/// }
+///
+/// fn main() {
+///     assert!(current_executor().is_none());
+///     run();
+/// }
+/// ```
+#[inline]
+pub fn current_executor() -> Option<Arc<Executor<'static>>> {
+    CURRENT_EXECUTOR.with(|current| current.borrow().clone())
+}
+
+/// Run a function with the current thread marked as running this executor.
+pub(crate) fn with_current_executor<T>(
+    ex: Option<Arc<Executor<'static>>>,
+    f: impl FnOnce() -> T,
+) -> T {
+    /// Restores the previous executor once the function returns or panics.
+    struct Guard(Option<Arc<Executor<'static>>>);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            CURRENT_EXECUTOR.with(|current| *current.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _guard = Guard(CURRENT_EXECUTOR.with(|current| current.replace(ex)));
+    f()
+}
+
+/// Check that the runtime can be set up with this configuration, without running anything.
+///
+/// This spawns the thread pool of the thread-safe [`Executor`], runs a task that waits on an
+/// `async-io` timer to start the reactor, and shuts everything down again. It is meant for
+/// catching environments where the runtime can't start, e.g. because of thread limits, before
+/// deploying a program to them.
+///
+/// ```
+/// use smol_macros::main_executor::{validate_runtime, Config};
+///
+/// validate_runtime(&Config::new().shutdown_timeout(std::time::Duration::from_secs(5)))
+///     .expect("the runtime can't be set up");
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if any of the worker threads can't be spawned. Failing to start the reactor
+/// panics, like it does everywhere else in `async-io`.
+pub fn validate_runtime(config: &Config) -> Result<(), SetupError> {
+    let ex = Executor::new();
+    with_thread_pool(&ex, config, || {
+        config.block_on(ex.run(ex.spawn(async_io::Timer::after(Duration::ZERO))));
+    })
+}
+
+/// Run an async test body on an executor of its own, like the [`test`] macro does.
+///
+/// This is meant for custom test harnesses, like `libtest-mimic`, that run tests without the
+/// `#[test]` attribute. The test gets a thread-safe [`Executor`] running on a thread pool that is
+/// set up for it alone and torn down once the body completes, so tests don't share any state
+/// through the runtime. The executor is passed in as an `Arc`, so that the body can own it, and
+/// is also available through [`current_executor`].
+///
+/// A panic in the body is propagated to the caller, so the harness can catch it and report the
+/// test as failed.
+///
+/// ```
+/// use smol_macros::main_executor::run_test;
+///
+/// let sum = run_test(|ex| async move {
+///     let task = ex.spawn(async { 1 + 2 });
+///     task.await
+/// });
+/// assert_eq!(sum, 3);
+/// ```
+///
+/// [`test`]: crate::test
+pub fn run_test<T, F: Future<Output = T>>(test: impl FnOnce(Arc<Executor<'static>>) -> F) -> T {
+    Config::new().run(test)
+}
+
+/// Adapt an async benchmark routine to the closure taken by benchmark harnesses, like
+/// `criterion` and `divan`.
+///
+/// Benchmarks measure the closure they are given, which has to be synchronous. This sets up a
+/// thread-safe [`Executor`] and its worker threads once, like [`main`] does, and returns a
+/// closure that blocks on a fresh future from the routine every time it is called. The worker
+/// threads are spawned before the closure is returned, so that their setup isn't measured, and
+/// they keep running between the iterations until the closure is dropped.
+///
+/// ```
+/// use smol_macros::main_executor::bench_on;
+///
+/// let mut routine = bench_on(|ex| async move { ex.spawn(async { 1 + 2 }).await });
+///
+/// // With `criterion`, this is `c.bench_function("spawn", |b| b.iter(&mut routine))`.
+/// for _ in 0..10 {
+///     assert_eq!(routine(), 3);
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if the worker threads can't be spawned.
+///
+/// [`main`]: crate::main
+pub fn bench_on<T, F: Future<Output = T>>(
+    routine: impl FnMut(Arc<Executor<'static>>) -> F,
+) -> impl FnMut() -> T {
+    Config::new().bench(routine)
+}
+
+/// Wait for a task, returning its panic as an error instead of propagating it.
+///
+/// A task that panics doesn't take down the thread running it. Instead, the panic is propagated
+/// to whoever awaits the [`Task`]. This catches it there, so that tests that make a task panic on
+/// purpose can assert on the payload, which is usually a `&str` or a `String`.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main_executor::catch_task_panic, test, Executor};
+///
+/// #[apply(test!)]
+/// async fn panicking_task(ex: &Executor<'_>) {
+///     let err = catch_task_panic(ex.spawn(async { panic!("oh no") }))
+///         .await
+///         .unwrap_err();
+///     assert_eq!(err.downcast_ref::<&str>(), Some(&"oh no"));
+/// }
+/// ```
+///
+/// Any future is accepted, not only tasks, so this also catches panics of the future itself.
+pub async fn catch_task_panic<T>(task: impl Future<Output = T>) -> Result<T, Box<dyn Any + Send>> {
+    AssertUnwindSafe(task).catch_unwind().await
+}
+
+/// Wait until the user presses Ctrl-C.
+///
+/// On Unix, this completes once the process receives `SIGINT`. On Windows, it completes once a
+/// Ctrl-C event reaches the console of the process, which is registered through
+/// `SetConsoleCtrlHandler`; Ctrl-Break and closing the console aren't covered. While the future
+/// is alive, Ctrl-C no longer terminates the process, so dropping the future restores the usual
+/// behavior for signals that arrive afterwards. The handler is only registered once the future is
+/// first polled.
+///
+/// This is meant for `#[shutdown_on]`, which polls it alongside the body and stops the body once
+/// it completes. It needs the `async-signal` feature, which raises the minimum supported Rust
+/// version to the one of the `async-signal` crate.
+///
+/// ```no_run
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, main_executor::ctrl_c, Executor};
+///
+/// #[apply(main!)]
+/// #[shutdown_on(ctrl_c())]
+/// async fn main(ex: &Executor<'_>) {
+///     ex.spawn(async {
+///         // Serve requests until Ctrl-C is pressed.
+///         futures_lite::future::pending::<()>().await
+///     })
+///     .await
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if the handler for Ctrl-C can't be registered.
+#[cfg(feature = "async-signal")]
+pub async fn ctrl_c() {
+    use async_signal::{Signal, Signals};
+    use futures_lite::StreamExt;
+
+    let mut signals = Signals::new([Signal::Int]).expect("failed to register a handler for Ctrl-C");
+    signals.next().await;
+}
+
+/// Spawn tasks onto the executor and count how many of them ran on each thread.
+///
+/// This is meant for tests that check whether tasks are really spread across the worker
+/// threads, together with [`assert_multithreaded`]. Each task blocks its thread for a moment
+/// before recording it, so that the other threads get the chance to pick up the remaining tasks
+/// instead of one thread running all of them back to back. The thread that awaits the tasks can
+/// run some of them too, if it runs the executor.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main_executor::observe_thread_distribution, test, Executor};
+///
+/// #[apply(test!)]
+/// async fn tasks_run_in_parallel(ex: &Executor<'_>) {
+///     smol_macros::assert_multithreaded!();
+///
+///     let distribution = observe_thread_distribution(ex, 16).await;
+///     assert_eq!(distribution.values().sum::<usize>(), 16);
+///     assert!(distribution.len() > 1);
+/// }
+/// ```
+///
+/// [`assert_multithreaded`]: crate::assert_multithreaded
+pub async fn observe_thread_distribution(
+    ex: &Executor<'_>,
+    task_count: usize,
+) -> HashMap<ThreadId, usize> {
+    let tasks = (0..task_count)
+        .map(|_| {
+            ex.spawn(async {
+                thread::sleep(Duration::from_millis(1));
+                thread::current().id()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut distribution = HashMap::new();
+    for task in tasks {
+        *distribution.entry(task.await).or_insert(0) += 1;
+    }
+    distribution
+}
+
+/// Spawn each future as a task onto the executor, and return the output of the first one to
+/// complete.
+///
+/// The other tasks are cancelled by dropping them as soon as there is a winner, so they don't
+/// keep running in the background. This is meant for hedged requests and similar patterns,
+/// where the same work is tried several ways and only the fastest answer matters. Since the
+/// futures run as tasks, they make progress on the worker threads even while the caller isn't
+/// polled. If the first task to complete panicked, the panic is propagated here.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main_executor::race_tasks, test, Executor};
+/// use std::time::Duration;
+///
+/// #[apply(test!)]
+/// async fn fastest_wins(ex: &Executor<'_>) {
+///     let winner = race_tasks(
+///         ex,
+///         [10, 1000].map(|millis| async move {
+///             async_io::Timer::after(Duration::from_millis(millis)).await;
+///             millis
+///         }),
+///     )
+///     .await;
+///     assert_eq!(winner, 10);
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if there are no futures, since there would be no output to return.
+pub async fn race_tasks<'ex, T, F>(ex: &Executor<'ex>, futures: impl IntoIterator<Item = F>) -> T
+where
+    T: Send + 'ex,
+    F: Future<Output = T> + Send + 'ex,
+{
+    let mut tasks = futures
+        .into_iter()
+        .map(|future| ex.spawn(future))
+        .collect::<Vec<_>>();
+    assert!(!tasks.is_empty(), "`race_tasks` needs at least one future");
+
+    // The losing tasks are dropped along with the list once this returns.
+    future::poll_fn(|cx| {
+        tasks
+            .iter_mut()
+            .find_map(|task| match Pin::new(task).poll(cx) {
+                Poll::Ready(output) => Some(output),
+                Poll::Pending => None,
+            })
+            .map_or(Poll::Pending, Poll::Ready)
+    })
+    .await
+}
+
+/// Run a future, panicking if it doesn't complete within the deadline.
+///
+/// Unlike the `#[timeout]` attribute, which limits the whole test, this checks that a single
+/// operation is fast, and returns its output so that the test can go on with it. The deadline
+/// is measured with the timers of the [`Reactor`] of the runtime, which are the ones of
+/// `async-io` unless `#[reactor]` picks another one. Use [`try_within`] to handle the timeout
+/// instead of panicking.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, main_executor::within, Executor};
+/// use std::time::Duration;
+///
+/// #[apply(main!)]
+/// async fn main(ex: &Executor<'_>) {
+///     let sum = within(Duration::from_secs(5), ex.spawn(async { 1 + 2 })).await;
+///     assert_eq!(sum, 3);
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if the future is still pending once the deadline elapses.
+pub async fn within<T>(deadline: Duration, future: impl Future<Output = T>) -> T {
+    match try_within(deadline, future).await {
+        Some(output) => output,
+        None => panic!("the future didn't complete within {deadline:?}"),
+    }
+}
+
+/// Run a future, returning `None` if it doesn't complete within the deadline.
+///
+/// The future is dropped once the deadline elapses. See [`within`] for details.
+pub async fn try_within<T>(deadline: Duration, future: impl Future<Output = T>) -> Option<T> {
+    future::or(async { Some(future.await) }, async {
+        reactor::sleep(deadline).await;
+        None
+    })
+    .await
+}
+
+/// Run a future that can spawn tasks borrowing from its surroundings, and wait for all of them
+/// to complete before returning.
+///
+/// This is like [`thread::scope`] for tasks. The closure is given a [`Scope`] to spawn the tasks
+/// with, and the future it returns is the body of the scope. The tasks can borrow anything that
+/// outlives the call to `scope`, including the locals of the function that awaits it. Once the
+/// body completes, the tasks that are still running are awaited too, and `scope` returns the
+/// output of the body.
+///
+/// Since a future can be leaked, tasks that borrow can't be handed to other threads without
+/// unsafe code. Instead, the scope has an executor of its own that it runs while it is awaited,
+/// so the tasks run concurrently with the body and each other, but on the thread that polls the
+/// scope, and not in parallel. Spawn the work that needs the thread pool onto the [`Executor`]
+/// as usual, and use a scope for the work that needs to borrow.
+///
+/// ```
+/// use smol_macros::{main, main_executor::scope};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// main! {
+///     async fn main() {
+///         let numbers = vec![1, 2, 3, 4];
+///         let total = AtomicUsize::new(0);
+///         let (numbers, total_ref) = (&numbers, &total);
+///
+///         scope(|s| async move {
+///             for chunk in numbers.chunks(2) {
+///                 s.spawn(async move {
+///                     total_ref.fetch_add(chunk.iter().sum(), Ordering::SeqCst);
+///                 })
+///                 .detach();
+///             }
+///         })
+///         .await;
+///
+///         assert_eq!(total.into_inner(), 10);
+///     }
+/// }
+/// ```
+///
+/// The body and the tasks are `async move` blocks that capture references, since the body can't
+/// lend out what it owns itself.
+///
+/// If the scope is dropped before it completes, e.g. because the body panicked, the function that
+/// awaits it returned early or a timeout fired, the tasks are cancelled along with it. They are
+/// dropped right away, before anything they borrow goes away, and never polled again.
+pub async fn scope<'env, T, F, Fut>(f: F) -> T
+where
+    F: FnOnce(Scope<'env>) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let ex = Arc::new(Executor::new());
+    let output = ex
+        .run(f(Scope {
+            ex: Arc::downgrade(&ex),
+        }))
+        .await;
+
+    // Detached tasks run until they complete too.
+    while !ex.is_empty() {
+        ex.tick().await;
+    }
+    output
+}
+
+/// A handle to spawn tasks that are awaited before [`scope`] returns.
+///
+/// The handle only holds a weak reference to the executor of the scope, so the tasks can keep a
+/// copy of it without keeping their own executor alive.
+#[derive(Debug, Clone)]
+pub struct Scope<'env> {
+    /// The executor of the scope.
+    ex: Weak<Executor<'env>>,
+}
+
+impl<'env> Scope<'env> {
+    /// Spawn a task onto the scope.
+    ///
+    /// Like any other task, it is cancelled if the returned [`Task`] is dropped. Call
+    /// [`Task::detach`] to let it run until the scope ends instead. A detached task that panics
+    /// only takes itself down, so await the task if its panic should reach the body.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scope has already ended, which can only happen if the handle outlived the
+    /// body of the scope.
+    pub fn spawn<T: Send + 'env>(&self, future: impl Future<Output = T> + Send + 'env) -> Task<T> {
+        self.ex
+            .upgrade()
+            .expect("the scope has already ended")
+            .spawn(future)
+    }
+}
+
+/// A thread pool running an executor that it shares ownership of.
+///
+/// The macros run the worker threads in a [`thread::scope`], so that they can borrow the
+/// executor and the configuration, and so that they are always joined before `main` returns.
+/// This pool spawns plain threads instead, each holding a clone of the `Arc`, so it isn't tied to
+/// the scope of a function call and can be kept in a `static` or a struct that outlives the
+/// caller. In exchange, the executor has to be `'static`. The differences to the scoped pool are:
+///
+/// - Dropping the pool tells the worker threads to stop, but doesn't wait for them. Use
+///   [`join`] to wait for them and to propagate their panics.
+/// - With [`Config::shutdown_timeout`], [`join`] gives up on the threads that haven't stopped in
+///   time and leaves them running, rather than aborting the process.
+/// - The current thread isn't one of the workers, so [`Config::main_thread_worker`] only affects
+///   the number of threads. The attributes that need to coordinate with the current thread,
+///   [`Config::idle_timeout`], [`Config::lazy_workers`], [`Config::reactor_thread`],
+///   [`Config::stop_on_panic`] and [`Config::wait_for_workers`], have no effect.
+///
+/// With the `single-thread` feature, no threads are spawned, and the executor only makes
+/// progress while something else runs it.
+///
+/// ```
+/// use smol_macros::main_executor::{Config, ThreadPool};
+/// use smol_macros::Executor;
+/// use std::sync::Arc;
+///
+/// let ex = Arc::new(Executor::new());
+/// let pool = ThreadPool::spawn(&ex, &Config::new()).expect("failed to spawn the thread pool");
+///
+/// let task = ex.spawn(async { 1 + 1 });
+/// # #[cfg(feature = "single-thread")]
+/// # let task = async_io::block_on(ex.run(task));
+/// # #[cfg(not(feature = "single-thread"))]
+/// let task = async_io::block_on(task);
+/// assert_eq!(task, 2);
+///
+/// pool.join();
+/// ```
+///
+/// [`thread::scope`]: std::thread::scope
+/// [`join`]: ThreadPool::join
+#[derive(Debug)]
+pub struct ThreadPool {
+    /// The worker threads.
+    #[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+    pool: DetachedPool,
+
+    /// Reports the lifecycle of the pool, which has no worker threads.
+    #[cfg(any(feature = "single-thread", target_family = "wasm"))]
+    lifecycle: Option<Arc<Lifecycle>>,
+
+    /// Cancelled once the pool is told to stop.
+    stop_token: CancellationToken,
+
+    /// Cancels the stop token once the pool is dropped.
+    _cancel_on_drop: DropGuard,
+}
+
+impl ThreadPool {
+    /// Spawn the worker threads for an executor.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the worker threads can't be spawned, in which case the threads
+    /// that were spawned already are stopped.
+    #[inline]
+    pub fn spawn(ex: &Arc<Executor<'static>>, config: &Config) -> Result<Self, SetupError> {
+        Self::spawn_with_lifecycle(ex, config, None)
+    }
+
+    /// Spawn the worker threads, reporting their lifecycle.
+    #[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+    fn spawn_with_lifecycle(
+        ex: &Arc<Executor<'static>>,
+        config: &Config,
+        lifecycle: Option<Arc<Lifecycle>>,
+    ) -> Result<Self, SetupError> {
+        let stop_token = CancellationToken::new();
+        DetachedPool::spawn(ex, config, lifecycle).map(|pool| Self {
+            pool,
+            _cancel_on_drop: stop_token.clone().drop_guard(),
+            stop_token,
+        })
+    }
+
+    /// Spawn the worker threads, reporting their lifecycle.
+    #[cfg(any(feature = "single-thread", target_family = "wasm"))]
+    fn spawn_with_lifecycle(
+        _ex: &Arc<Executor<'static>>,
+        _config: &Config,
+        lifecycle: Option<Arc<Lifecycle>>,
+    ) -> Result<Self, SetupError> {
+        if let Some(lifecycle) = &lifecycle {
+            lifecycle.all_spawned();
+        }
+        let stop_token = CancellationToken::new();
+        Ok(Self {
+            lifecycle,
+            _cancel_on_drop: stop_token.clone().drop_guard(),
+            stop_token,
+        })
+    }
+
+    /// Get a token that is cancelled once the pool is told to stop.
+    ///
+    /// The worker threads stop once they finish the task they are running, so long-running tasks
+    /// can wait for [`cancelled`] to wind down on their own. The token is cancelled by [`stop`],
+    /// [`join`] and dropping the pool.
+    ///
+    /// [`cancelled`]: CancellationToken::cancelled
+    /// [`stop`]: ThreadPool::stop
+    /// [`join`]: ThreadPool::join
+    #[inline]
+    pub fn stop_token(&self) -> CancellationToken {
+        self.stop_token.clone()
+    }
+
+    /// Tell the worker threads to stop once they finish the task they are running.
+    #[inline]
+    pub fn stop(&self) {
+        self.stop_token.cancel();
+
+        #[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+        self.pool.stop();
+
+        #[cfg(any(feature = "single-thread", target_family = "wasm"))]
+        if let Some(lifecycle) = &self.lifecycle {
+            lifecycle.stopping();
+        }
+    }
+
+    /// Stop the worker threads and wait for them to exit.
+    ///
+    /// If a worker thread panicked, the panic is propagated.
+    #[inline]
+    pub fn join(self) {
+        self.stop_token.cancel();
+
+        #[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+        self.pool.join();
+
+        #[cfg(any(feature = "single-thread", target_family = "wasm"))]
+        self.stop();
+    }
+}
+
+#[cfg(any(feature = "single-thread", target_family = "wasm"))]
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// A runtime that runs in the background, for programs that can't block on `main`.
+///
+/// GUI toolkits like `winit` own the main thread and run their own event loop on it, so the
+/// macros, which block the current thread until the main future completes, can't be used. This
+/// starts a thread-safe [`Executor`] on a [`ThreadPool`] and returns right away. Tasks are
+/// spawned through a [`RuntimeHandle`], which can be cloned and sent to other threads, and the
+/// event loop can run tasks on its own thread with [`RuntimeHandle::tick`], e.g. once per frame.
+///
+/// The worker threads stop once the runtime is dropped or [`shutdown`] is called. With the
+/// `single-thread` feature, no threads are spawned, and tasks only run while the event loop
+/// ticks the runtime.
+///
+/// ```
+/// use smol_macros::main_executor::{Config, Runtime};
+///
+/// let runtime = Runtime::start(&Config::new()).expect("failed to start the runtime");
+/// let handle = runtime.handle();
+/// let task = handle.spawn(async { 1 + 1 });
+///
+/// // Somewhere in the event loop, once per frame.
+/// while !task.is_finished() {
+///     handle.tick();
+/// }
+///
+/// assert_eq!(async_io::block_on(task), 2);
+/// runtime.shutdown();
+/// ```
+///
+/// [`shutdown`]: Runtime::shutdown
+#[derive(Debug)]
+pub struct Runtime {
+    /// The handle to the executor.
+    handle: RuntimeHandle,
+
+    /// The worker threads running the executor.
+    pool: ThreadPool,
+}
+
+impl Runtime {
+    /// Start the worker threads without blocking the current thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the worker threads can't be spawned.
+    pub fn start(config: &Config) -> Result<Self, SetupError> {
+        Self::start_inner(config, None)
+    }
+
+    /// Start the worker threads, along with a stream of their [`LifecycleEvent`]s.
+    ///
+    /// Each worker thread reports [`LifecycleEvent::WorkerOnline`] once it starts running tasks,
+    /// and [`LifecycleEvent::Started`] follows once all of them do. When the runtime is shut down
+    /// or dropped, [`LifecycleEvent::Stopping`] is reported, and [`LifecycleEvent::Stopped`] once
+    /// all worker threads exit, which ends the stream. Worker threads that come online after the
+    /// runtime started stopping aren't reported, and neither is `Started` then. Without worker
+    /// threads, like with the `single-thread` feature, the runtime is started right away.
+    ///
+    /// ```
+    /// use futures_lite::StreamExt;
+    /// use smol_macros::main_executor::{Config, LifecycleEvent, Runtime};
+    ///
+    /// let (runtime, mut events) =
+    ///     Runtime::start_with_events(&Config::new()).expect("failed to start the runtime");
+    /// async_io::block_on(async {
+    ///     while let Some(event) = events.next().await {
+    ///         if event == LifecycleEvent::Started {
+    ///             break;
+    ///         }
+    ///     }
+    /// });
+    ///
+    /// runtime.shutdown();
+    /// let rest: Vec<_> = async_io::block_on(events.collect());
+    /// assert_eq!(rest, [LifecycleEvent::Stopping, LifecycleEvent::Stopped]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the worker threads can't be spawned.
+    pub fn start_with_events(config: &Config) -> Result<(Self, LifecycleEvents), SetupError> {
+        let (lifecycle, events) = Lifecycle::new();
+        Self::start_inner(config, Some(Arc::new(lifecycle))).map(|runtime| (runtime, events))
+    }
+
+    /// Start the worker threads, reporting their lifecycle.
+    fn start_inner(config: &Config, lifecycle: Option<Arc<Lifecycle>>) -> Result<Self, SetupError> {
+        let ex = Arc::new(Executor::new());
+        let pool = ThreadPool::spawn_with_lifecycle(&ex, config, lifecycle)?;
+        Ok(Self {
+            handle: RuntimeHandle { ex },
+            pool,
+        })
+    }
+
+    /// Get a handle for spawning tasks onto the runtime.
+    #[inline]
+    pub fn handle(&self) -> RuntimeHandle {
+        self.handle.clone()
+    }
+
+    /// Get a token that is cancelled once the runtime is shut down or dropped.
+    ///
+    /// Tasks spawned onto the runtime can wait for [`cancelled`] to wind down on their own
+    /// instead of being dropped when the runtime stops.
+    ///
+    /// ```
+    /// use smol_macros::main_executor::{Config, Runtime};
+    ///
+    /// let runtime = Runtime::start(&Config::new()).expect("failed to start the runtime");
+    /// let token = runtime.stop_token();
+    /// runtime
+    ///     .handle()
+    ///     .spawn(async move {
+    ///         token.cancelled().await;
+    ///         println!("flushing before the runtime stops");
+    ///     })
+    ///     .detach();
+    ///
+    /// runtime.shutdown();
+    /// ```
+    ///
+    /// [`cancelled`]: crate::CancellationToken::cancelled
+    #[inline]
+    pub fn stop_token(&self) -> CancellationToken {
+        self.pool.stop_token()
+    }
+
+    /// Stop the worker threads and wait for them to exit.
+    ///
+    /// Tasks that haven't completed yet are dropped once the last handle is dropped. If a worker
+    /// thread panicked, the panic is propagated.
+    #[inline]
+    pub fn shutdown(self) {
+        self.pool.join();
+    }
+}
+
+/// A handle to a [`Runtime`] that can be used from any thread.
+#[derive(Debug, Clone)]
+pub struct RuntimeHandle {
+    /// The executor run by the runtime.
+    ex: Arc<Executor<'static>>,
+}
+
+impl RuntimeHandle {
+    /// Spawn a task onto the runtime.
+    #[inline]
+    pub fn spawn<T: Send + 'static>(
+        &self,
+        future: impl Future<Output = T> + Send + 'static,
+    ) -> Task<T> {
+        self.ex.spawn(future)
+    }
+
+    /// Run a single task on the current thread if one is ready, without blocking.
+    ///
+    /// Returns `true` if a task was run. While the task runs, the current thread counts as
+    /// running the runtime, so [`current_executor`] returns the executor of the runtime.
+    #[inline]
+    pub fn tick(&self) -> bool {
+        enter_runtime(|| with_current_executor(Some(self.ex.clone()), || self.ex.try_tick()))
+    }
+
+    /// Get the executor run by the runtime.
+    #[inline]
+    pub fn executor(&self) -> &Arc<Executor<'static>> {
+        &self.ex
+    }
+}
+
+/// A runtime for a [`LocalExecutor`] that is driven by someone else's loop.
+///
+/// The macros block the current thread until the main future completes. Game loops and
+/// `poll`-based main loops can't give up the thread like that, so this lets them run the tasks of
+/// a [`LocalExecutor`] a bit at a time instead, e.g. once per frame or whenever the loop wakes
+/// up. Nothing runs in the background: tasks only make progress while [`tick`] or
+/// [`run_until_stalled`] are called, and neither of them blocks.
+///
+/// ```
+/// use smol_macros::main_executor::LocalRuntime;
+/// use std::cell::Cell;
+/// use std::rc::Rc;
+///
+/// let runtime = LocalRuntime::new();
+/// let frames = Rc::new(Cell::new(0));
+/// let task = runtime.spawn({
+///     let frames = frames.clone();
+///     async move { frames.get() * 2 }
+/// });
+///
+/// // The main loop of the program.
+/// while !task.is_finished() {
+///     frames.set(frames.get() + 1);
+///     runtime.run_until_stalled();
+/// }
+///
+/// assert_eq!(async_io::block_on(task), 2);
+/// ```
+///
+/// [`tick`]: LocalRuntime::tick
+/// [`run_until_stalled`]: LocalRuntime::run_until_stalled
+#[derive(Debug, Default)]
+pub struct LocalRuntime {
+    /// The executor run by the loop.
+    ex: LocalExecutor<'static>,
+}
+
+impl LocalRuntime {
+    /// Create a runtime without any tasks.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawn a task onto the runtime.
+    #[inline]
+    pub fn spawn<T: 'static>(&self, future: impl Future<Output = T> + 'static) -> Task<T> {
+        self.ex.spawn(future)
+    }
+
+    /// Run a single task if one is ready, without blocking.
+    ///
+    /// Returns `true` if a task was run.
+    #[inline]
+    pub fn tick(&self) -> bool {
+        enter_runtime(|| self.ex.try_tick())
+    }
+
+    /// Run tasks until none of them are ready, without blocking.
+    ///
+    /// Returns the number of tasks that were run. Tasks that keep waking themselves up, like ones
+    /// that call [`yield_now`] in a loop, keep this from returning.
+    ///
+    /// [`yield_now`]: futures_lite::future::yield_now
+    pub fn run_until_stalled(&self) -> usize {
+        enter_runtime(|| {
+            let mut ran = 0;
+            while self.ex.try_tick() {
+                ran += 1;
+            }
+            ran
+        })
+    }
+
+    /// Check whether the runtime has no tasks left, including ones that aren't ready.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ex.is_empty()
+    }
+
+    /// Get the executor run by the runtime.
+    #[inline]
+    pub fn executor(&self) -> &LocalExecutor<'static> {
+        &self.ex
+    }
+}
+
+/// Run a function with the current thread marked as running the runtime.
+#[doc(hidden)]
+pub fn enter_runtime<T>(f: impl FnOnce() -> T) -> T {
+    /// Restores the previous state once the function returns or panics.
+    struct Guard(bool);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            IN_RUNTIME.with(|in_runtime| in_runtime.set(self.0));
+        }
+    }
+
+    let _guard = Guard(IN_RUNTIME.with(|in_runtime| in_runtime.replace(true)));
+    f()
+}
+
+/// Set the name of the current thread, as seen by the operating system.
+///
+/// Linux truncates the name to 15 bytes. If the name contains a nul byte or can't be set, the
+/// thread keeps its name.
+#[doc(hidden)]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn set_thread_name(name: &str) {
+    if let Ok(name) = std::ffi::CString::new(name) {
+        rustix::thread::set_name(&name).ok();
+    }
+}
+
+/// Set the name of the current thread, as seen by the operating system.
+///
+/// Renaming a running thread isn't supported on this platform, so the thread keeps its name.
+#[doc(hidden)]
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn set_thread_name(_name: &str) {}
+
+/// Mark the current thread as polling the main future while the future is polled.
+///
+/// With [`Config::stop_on_panic`], a panic on the current thread while this is the case stops
+/// the worker threads right away.
+#[doc(hidden)]
+pub async fn stop_on_panic<T>(future: impl Future<Output = T>) -> T {
+    /// Restores the previous state once the future is polled or panics.
+    struct Guard(bool);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            POLLING_MAIN.with(|polling| polling.set(self.0));
+        }
+    }
+
+    futures_lite::pin!(future);
+    future::poll_fn(|cx| {
+        let _guard = Guard(POLLING_MAIN.with(|polling| polling.replace(true)));
+        future.as_mut().poll(cx)
+    })
+    .await
+}
+
+/// Run a future, panicking if it doesn't complete within the timeout.
+#[doc(hidden)]
+pub async fn with_timeout<T>(name: &str, timeout: Duration, future: impl Future<Output = T>) -> T {
+    let start = Instant::now();
+    future
+        .or(async {
+            reactor::sleep(timeout).await;
+            panic!("`{name}` timed out after {:?}", start.elapsed())
+        })
+        .await
+}
+
+/// Run a future, panicking if it completes within the timeout, for `#[expect_timeout]`.
+#[doc(hidden)]
+pub async fn expect_timeout<T: Skipped>(
+    name: &str,
+    timeout: Duration,
+    future: impl Future<Output = T>,
+) -> T {
+    let start = Instant::now();
+    let completed = future::or(
+        async {
+            future.await;
+            true
+        },
+        async {
+            reactor::sleep(timeout).await;
+            false
+        },
+    );
+    if completed.await {
+        panic!(
+            "`{name}` completed after {:?}, but was expected to run for at least {timeout:?}",
+            start.elapsed()
+        );
+    }
+    T::skipped()
+}
+
+/// Run a future, adding the configuration of the runtime to the message if it panics.
+#[doc(hidden)]
+pub async fn with_panic_context<T>(executor: &str, future: impl Future<Output = T>) -> T {
+    let err = match AssertUnwindSafe(future).catch_unwind().await {
+        Ok(value) => return value,
+        Err(err) => err,
+    };
+
+    let context = match worker_threads() {
+        0 => format!("runtime: {executor} on the current thread only"),
+        1 => format!("runtime: {executor} on 1 worker thread"),
+        threads => format!("runtime: {executor} on {threads} worker threads"),
+    };
+    let message = match err.downcast_ref::<&str>() {
+        Some(message) => Some(*message),
+        None => err.downcast_ref::<String>().map(String::as_str),
+    };
+    match message {
+        Some(message) => std::panic::resume_unwind(Box::new(format!("{message}\n{context}"))),
+        None => {
+            eprintln!("{context}");
+            std::panic::resume_unwind(err)
+        }
+    }
+}
+
+/// Check whether the current thread is polling a main future wrapped in [`stop_on_panic`].
+#[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+#[inline]
+pub(crate) fn polling_main() -> bool {
+    POLLING_MAIN.with(Cell::get)
+}
+
+/// Something that can be set up as an executor.
+///
+/// The executor parameter of [`main`] and [`test`] can be any type that implements this trait,
+/// including types defined outside of this crate. Besides implementing this trait, the type
+/// needs a `run` method like [`Executor::run`], which may also be reached through [`Deref`].
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::main_executor::{Config, MainExecutor};
+/// use smol_macros::{test, LocalExecutor};
+/// use std::ops::Deref;
+///
+/// /// An executor that counts how often it was set up.
+/// struct CountingExecutor(LocalExecutor<'static>);
+///
+/// impl Deref for CountingExecutor {
+///     type Target = LocalExecutor<'static>;
+///
+///     fn deref(&self) -> &Self::Target {
+///         &self.0
+///     }
+/// }
+///
+/// impl MainExecutor for CountingExecutor {
+///     fn with_main<T, F: FnOnce(&Self) -> T>(_config: &Config, f: F) -> T {
+///         println!("setting up an executor");
+///         f(&CountingExecutor(LocalExecutor::new()))
+///     }
+/// }
+///
+/// #[apply(test!)]
+/// async fn custom(ex: &CountingExecutor) {
+///     assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+/// }
+/// ```
+///
+/// [`main`]: crate::main
+/// [`test`]: crate::test
+/// [`Deref`]: std::ops::Deref
+pub trait MainExecutor: Sized {
+    /// Create this type and pass it into `main`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the executor can't be set up, e.g. because its threads can't be spawned.
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T;
+
+    /// Create this type and pass it into `main`, returning an error if it can't be set up.
+    #[inline]
+    fn try_with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        Ok(Self::with_main(config, f))
+    }
+}
+
+impl MainExecutor for Arc<Executor<'static>> {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        Self::try_with_main(config, f).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[inline]
+    fn try_with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        let ex = Arc::new(Executor::new());
+        with_current_executor(Some(ex.clone()), || {
+            with_thread_pool(&ex, config, || f(&ex))
+        })
+    }
+}
+
+impl MainExecutor for &'static Executor<'static> {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        Self::try_with_main(config, f).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// The executor is leaked on purpose, so that it lives until the process exits.
+    #[inline]
+    fn try_with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        let ex: &'static Arc<Executor<'static>> = Box::leak(Box::new(Arc::new(Executor::new())));
+        with_current_executor(Some(ex.clone()), || {
+            with_thread_pool(ex, config, || f(&&**ex))
+        })
+    }
+}
+
+impl MainExecutor for Executor<'_> {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        Self::try_with_main(config, f).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[inline]
+    fn try_with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        let ex = Executor::new();
+        with_thread_pool(&ex, config, || f(&ex))
+    }
+}
+
+/// An executor that can run on the process-wide thread pool.
+pub trait SharedExecutor: Sized {
+    /// Create this type and pass it into `main`, running it on the shared thread pool.
+    fn with_shared<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T;
+
+    /// Create this type and pass it into `main`, returning an error if it can't be set up.
+    ///
+    /// The shared thread pool is only started once, and failing to start it still panics.
+    #[inline]
+    fn try_with_shared<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        Ok(Self::with_shared(config, f))
+    }
+}
+
+impl SharedExecutor for Arc<Executor<'static>> {
+    #[inline]
+    fn with_shared<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        let ex = Arc::new(Executor::new());
+        with_shared_pool(&ex, config, || f(&ex))
+    }
+}
+
+impl SharedExecutor for Executor<'static> {
+    #[inline]
+    fn with_shared<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        let ex = Arc::new(Executor::new());
+        with_shared_pool(&ex, config, || f(&ex))
+    }
+}
+
+impl MainExecutor for Rc<LocalExecutor<'_>> {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(_config: &Config, f: F) -> T {
+        f(&Rc::new(LocalExecutor::new()))
+    }
+}
+
+impl MainExecutor for LocalExecutor<'_> {
+    fn with_main<T, F: FnOnce(&Self) -> T>(_config: &Config, f: F) -> T {
+        f(&LocalExecutor::new())
+    }
+}
+
+impl MainExecutor for StepExecutor<'_> {
+    fn with_main<T, F: FnOnce(&Self) -> T>(_config: &Config, f: F) -> T {
+        f(&StepExecutor::new())
+    }
+}
+
+impl MainExecutor for AnyExecutor<'_> {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        Self::try_with_main(config, f).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[inline]
+    fn try_with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        let ex = if config.local || config.single_threaded {
+            AnyExecutor::Local(LocalExecutor::new())
+        } else {
+            AnyExecutor::Executor(Executor::new())
+        };
+
+        match &ex {
+            AnyExecutor::Executor(inner) => with_thread_pool(inner, config, || f(&ex)),
+            AnyExecutor::Local(_) => Ok(f(&ex)),
+        }
+    }
+}
+
+impl MainExecutor for InstrumentedExecutor<'_> {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        Self::try_with_main(config, f).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[inline]
+    fn try_with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        let ex = InstrumentedExecutor::new();
+        with_thread_pool(&ex.ex, config, || f(&ex))
+    }
+}
+
+impl MainExecutor for SplitExecutor<'_> {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        Self::try_with_main(config, f).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[inline]
+    fn try_with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        // Only the worker threads run the background executor.
+        let mut config = config.clone();
+        config.current_thread = false;
+        config.fallback_local = false;
+
+        let ex = SplitExecutor::new();
+        with_thread_pool(&ex.background, &config, || f(&ex))
+    }
+}
+
+impl MainExecutor for PriorityExecutor<'_> {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        Self::try_with_main(config, f).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[inline]
+    fn try_with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        let ex = PriorityExecutor::new();
+        with_priority_pool(&ex.high, &ex.low, config, || f(&ex))
+    }
+}
+
+impl MainExecutor for LazyExecutor {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        let ex = LazyExecutor::new(config);
+
+        /// Joins the thread pool once the function returns or panics.
+        struct Join<'a>(&'a LazyExecutor);
+
+        impl Drop for Join<'_> {
+            fn drop(&mut self) {
+                // Worker panics can't be propagated while unwinding, so the pool is only stopped.
+                if !thread::panicking() {
+                    self.0.join();
+                }
+            }
+        }
+
+        with_current_executor(Some(ex.ex.clone()), || {
+            let _join = Join(&ex);
+            f(&ex)
+        })
+    }
+}
+
+impl MainExecutor for PinnedExecutor<'_> {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        Self::try_with_main(config, f).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[inline]
+    fn try_with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        let ex = PinnedExecutor::new(pinned_workers(config));
+        with_pinned_pool(&ex.shared, &ex.workers, config, || f(&ex))
+    }
+}
+
+impl MainExecutor for ScheduledExecutor<'_> {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        Self::try_with_main(config, f).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[inline]
+    fn try_with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        let ex = ScheduledExecutor::new(config.schedule);
+        with_scheduled_pool(&ex, config, || f(&ex))
+    }
+}
+
+impl MainExecutor for Shards<'_> {
+    #[inline]
+    fn with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> T {
+        Self::try_with_main(config, f).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[inline]
+    fn try_with_main<T, F: FnOnce(&Self) -> T>(config: &Config, f: F) -> Result<T, SetupError> {
+        let ex = Shards::new(config.shards.unwrap_or(1));
+        with_sharded_pool(&ex.executors, config, || f(&ex))
+    }
+}
+
+/// An executor that only runs on the thread that sets it up.
+///
+/// This is required of the executor when `main` has the `#[local]` attribute.
+pub trait LocalMainExecutor: MainExecutor {}
+
+impl LocalMainExecutor for LocalExecutor<'_> {}
+impl LocalMainExecutor for Rc<LocalExecutor<'_>> {}
+impl LocalMainExecutor for AnyExecutor<'_> {}
+impl LocalMainExecutor for StepExecutor<'_> {}
+
+/// Check that an executor only runs on the thread that sets it up.
+#[doc(hidden)]
+#[inline]
+pub fn requires_local<T: LocalMainExecutor>() {}
+
+/// An error that occurred while setting up the runtime.
+#[derive(Debug)]
+pub struct SetupError {
+    /// The error returned when spawning a worker thread.
+    error: io::Error,
+}
+
+impl SetupError {
+    /// Create an error for a worker thread that couldn't be spawned.
+    #[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+    #[inline]
+    pub(crate) fn spawn_thread(error: io::Error) -> Self {
+        Self { error }
+    }
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to spawn thread: {}", self.error)
+    }
+}
+
+impl error::Error for SetupError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// How to choose the number of threads to run the executor on.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+    any(feature = "single-thread", target_family = "wasm"),
+    allow(dead_code)
+)]
+pub(crate) enum Threads {
+    /// A fixed number of threads.
+    Count(usize),
+
+    /// A fraction of the available CPUs, but at least some number of threads.
+    Fraction { fraction: f64, min: usize },
+}
+
+/// Configuration for the runtime, built from the attributes passed to `main`.
+///
+/// A configuration can also be built once and shared, e.g. by a crate that the binaries of a
+/// workspace depend on. [`main`] and [`test`] start from it with `#[config(expr)]`, and code
+/// that sets up the runtime without the macros passes it to [`run`](Config::run),
+/// [`Runtime::start`] or [`ThreadPool::spawn`].
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::main_executor::Config;
+/// use smol_macros::{main, Executor};
+/// use std::time::Duration;
+///
+/// fn runtime_config() -> Config {
+///     Config::new().threads(2).shutdown_timeout(Duration::from_secs(10))
+/// }
+///
+/// #[apply(main!)]
+/// #[config(runtime_config())]
+/// async fn main(ex: &Executor<'_>) {
+///     assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+///
+///     let answer = blocking::unblock(|| runtime_config().run(|ex| async move {
+///         ex.spawn(async { 6 * 7 }).await
+///     }))
+///     .await;
+///     assert_eq!(answer, 42);
+/// }
+/// ```
+///
+/// [`main`]: crate::main
+/// [`test`]: crate::test
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// How long to wait for the worker threads to stop.
+    pub(crate) shutdown_timeout: Option<Duration>,
+
+    /// Block on futures without driving the `async-io` reactor.
+    no_reactor: bool,
+
+    /// Busy-wait instead of parking the thread while blocking on futures.
+    spin_wait: bool,
+
+    /// Enter a tokio runtime context on every thread that blocks on futures.
+    #[cfg(feature = "tokio-compat")]
+    tokio_bridge: bool,
+
+    /// The CPUs to pin the worker threads to, one thread per CPU.
+    pub(crate) cpu_set: Option<Vec<usize>>,
+
+    /// The number of threads to run the executor on, if not one per CPU.
+    pub(crate) threads: Option<Threads>,
+
+    /// The number of CPUs to assume if it can't be determined.
+    pub(crate) parallelism_fallback: Option<usize>,
+
+    /// The names of the worker threads, with the last one repeated for the remaining threads.
+    pub(crate) worker_roles: Vec<String>,
+
+    /// Leave the threads of the pool unnamed.
+    pub(crate) no_thread_names: bool,
+
+    /// The functions called on each worker thread when it starts, with the index of the thread.
+    pub(crate) worker_init: Vec<fn(usize)>,
+
+    /// How many times panicked worker threads are restarted in total.
+    pub(crate) max_worker_restarts: Option<usize>,
+
+    /// Count the current thread as one of the worker threads.
+    pub(crate) main_thread_worker: bool,
+
+    /// How long a worker thread may be idle before it exits.
+    pub(crate) idle_timeout: Option<Duration>,
+
+    /// How many tasks a worker thread runs at most before checking whether the pool stopped.
+    pub(crate) stop_check_interval: Option<usize>,
+
+    /// The number of worker threads that are kept running even when idle.
+    pub(crate) min_workers: Option<usize>,
+
+    /// Always use a thread-local executor for [`AnyExecutor`].
+    pub(crate) local: bool,
+
+    /// Stop the worker threads as soon as the main future panics.
+    pub(crate) stop_on_panic: bool,
+
+    /// Spawn a thread that only drives the `async-io` reactor.
+    pub(crate) reactor_thread: bool,
+
+    /// Wait for the worker threads to start before running the main future.
+    pub(crate) wait_for_workers: bool,
+
+    /// Start with the minimum number of worker threads, and spawn more once tasks pile up.
+    pub(crate) lazy_workers: bool,
+
+    /// Run the thread-safe executor on the current thread only.
+    pub(crate) current_thread: bool,
+
+    /// Run the thread-safe executor on the current thread only if a worker can't be spawned.
+    pub(crate) fallback_local: bool,
+
+    /// Block on futures and create the timers of the runtime with this reactor.
+    pub(crate) reactor: Option<ReactorFns>,
+
+    /// Report the state of the runtime when the main future panics.
+    pub(crate) debug_on_panic: bool,
+
+    /// Called with how long the thread pool took to start up and shut down.
+    pub(crate) time_lifecycle: Option<fn(LifecycleTimings)>,
+
+    /// Use a thread-local executor for [`AnyExecutor`].
+    pub(crate) single_threaded: bool,
+
+    /// The number of executors to split the worker threads between.
+    pub(crate) shards: Option<usize>,
+
+    /// The order in which [`ScheduledExecutor`] runs its ready tasks.
+    pub(crate) schedule: Schedule,
+}
+
+impl Config {
+    /// Create the default configuration.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum amount of time to wait for worker threads to stop.
+    #[inline]
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    /// Block on futures without driving the `async-io` reactor.
+    #[inline]
+    pub fn no_reactor(mut self) -> Self {
+        self.no_reactor = true;
+        self
+    }
+
+    /// Busy-wait instead of parking the thread while blocking on futures.
+    #[inline]
+    pub fn spin_wait(mut self) -> Self {
+        self.spin_wait = true;
+        self
+    }
+
+    /// Enter a tokio runtime context on the current thread and every worker thread while they
+    /// block on futures, so that tokio timers and I/O work in the main future and all tasks.
+    ///
+    /// The runtime is the one that [`async_compat`] starts in the background, or the one of the
+    /// current thread if it already is in a tokio context. This needs the `tokio-compat`
+    /// feature.
+    #[cfg(feature = "tokio-compat")]
+    #[inline]
+    pub fn tokio_bridge(mut self) -> Self {
+        self.tokio_bridge = true;
+        self
+    }
+
+    /// Run the executor on this many threads instead of one per CPU.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is zero.
+    #[inline]
+    pub fn threads(mut self, threads: usize) -> Self {
+        assert!(threads >= 1, "the number of threads must be at least 1");
+        self.threads = Some(Threads::Count(threads));
+        self
+    }
+
+    /// Run the executor on a fraction of the available CPUs, rounded, but on at least `min`
+    /// threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fraction` isn't greater than 0 and at most 1, or if `min` is zero.
+    #[inline]
+    pub fn threads_fraction(mut self, fraction: f64, min: usize) -> Self {
+        assert!(
+            fraction > 0.0 && fraction <= 1.0,
+            "the fraction of threads must be greater than 0 and at most 1, got {fraction}"
+        );
+        assert!(min >= 1, "the minimum number of threads must be at least 1");
+        self.threads = Some(Threads::Fraction { fraction, min });
+        self
+    }
+
+    /// Run the executor on the number of threads in an environment variable, if it is set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the variable is set, but isn't a number of at least 1.
+    #[inline]
+    pub fn threads_from_env(self, var: &str) -> Self {
+        match std::env::var(var) {
+            Ok(threads) => match threads.trim().parse() {
+                Ok(threads) if threads >= 1 => self.threads(threads),
+                _ => panic!("invalid number of threads in `{var}`: `{threads}`"),
+            },
+            Err(_) => self,
+        }
+    }
+
+    /// Assume this many CPUs if the number of available CPUs can't be determined, instead of 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cpus` is zero.
+    #[inline]
+    pub fn parallelism_fallback(mut self, cpus: usize) -> Self {
+        assert!(cpus >= 1, "the fallback number of CPUs must be at least 1");
+        self.parallelism_fallback = Some(cpus);
+        self
+    }
+
+    /// Spawn one worker thread for each of these CPUs, pinned to that CPU.
+    #[inline]
+    pub fn cpu_set(mut self, cpus: impl IntoIterator<Item = usize>) -> Self {
+        self.cpu_set = Some(cpus.into_iter().collect());
+        self
+    }
+
+    /// Name the worker threads after their roles.
+    ///
+    /// The first threads are named after the roles in order. The remaining threads are named
+    /// after the last role, followed by an index.
+    #[inline]
+    pub fn worker_roles(mut self, roles: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.worker_roles = roles.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Leave the threads of the pool unnamed, instead of allocating a name for each of them.
+    #[inline]
+    pub fn no_thread_names(mut self) -> Self {
+        self.no_thread_names = true;
+        self
+    }
+
+    /// Call a function on each worker thread when it starts, with the index of the thread.
+    ///
+    /// The function is called before the thread runs any tasks, and calling this again adds
+    /// another function that is called after the previous ones.
+    #[inline]
+    pub fn worker_init(mut self, init: fn(usize)) -> Self {
+        self.worker_init.push(init);
+        self
+    }
+
+    /// Restart the loop of a worker thread that panics, up to a total of `max_restarts` times.
+    #[inline]
+    pub fn resilient_workers(mut self, max_restarts: usize) -> Self {
+        self.max_worker_restarts = Some(max_restarts);
+        self
+    }
+
+    /// Spawn one less worker thread, since the current thread also runs the executor.
+    #[inline]
+    pub fn main_thread_worker(mut self) -> Self {
+        self.main_thread_worker = true;
+        self
+    }
+
+    /// Let worker threads exit once they have been idle for this long.
+    ///
+    /// Threads are spawned again when more tasks come in, up to the usual size of the pool.
+    #[inline]
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Check whether the pool stopped after running at most this many tasks on a worker thread.
+    ///
+    /// By default, a worker thread runs up to 200 tasks in a row while others are ready before
+    /// it checks, so it may take a while to notice a stop if the tasks take long. A lower
+    /// interval makes stopping more responsive, but the worker threads don't hand tasks to each
+    /// other through their local queues then, which costs some throughput under load.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tasks` is zero.
+    #[inline]
+    pub fn stop_check_interval(mut self, tasks: usize) -> Self {
+        assert!(
+            tasks >= 1,
+            "the stop check interval must be at least 1 task"
+        );
+        self.stop_check_interval = Some(tasks);
+        self
+    }
+
+    /// Start with as many worker threads as [`Config::min_workers`], and only spawn the others
+    /// once tasks pile up.
+    #[inline]
+    pub fn lazy_workers(mut self) -> Self {
+        self.lazy_workers = true;
+        self
+    }
+
+    /// Run the thread-safe executor on the current thread only, without spawning a thread pool.
+    #[inline]
+    pub fn current_thread(mut self) -> Self {
+        self.current_thread = true;
+        self
+    }
+
+    /// Run the thread-safe executor on the current thread only if any of the worker threads
+    /// can't be spawned, instead of failing to set up the runtime.
+    ///
+    /// The threads that were spawned already are stopped and joined first.
+    #[inline]
+    pub fn fallback_local(mut self) -> Self {
+        self.fallback_local = true;
+        self
+    }
+
+    /// Block on futures and create the timers of the runtime with a [`Reactor`] other than the
+    /// one of `async-io`.
+    ///
+    /// This takes precedence over [`Config::no_reactor`] and [`Config::spin_wait`].
+    #[inline]
+    pub fn reactor<R: Reactor>(mut self) -> Self {
+        self.reactor = Some(ReactorFns::new::<R>());
+        self
+    }
+
+    /// Keep at least this many worker threads running when idle threads exit.
+    #[inline]
+    pub fn min_workers(mut self, min: usize) -> Self {
+        self.min_workers = Some(min);
+        self
+    }
+
+    /// Stop the worker threads as soon as the main future panics, before it unwinds.
+    ///
+    /// This only applies while the main future is polled through [`stop_on_panic`].
+    #[inline]
+    pub fn stop_on_panic(mut self) -> Self {
+        self.stop_on_panic = true;
+        self
+    }
+
+    /// Spawn a thread that only drives the `async-io` reactor, besides the worker threads.
+    #[inline]
+    pub fn reactor_thread(mut self) -> Self {
+        self.reactor_thread = true;
+        self
+    }
+
+    /// Print whether tasks were left on the executor and how many worker threads were running
+    /// when the main future panics, before the panic is propagated.
+    #[inline]
+    pub fn debug_on_panic(mut self) -> Self {
+        self.debug_on_panic = true;
+        self
+    }
+
+    /// Measure how long spawning the worker threads and stopping and joining them takes, and
+    /// call a function with the [`LifecycleTimings`] once they are joined.
+    ///
+    /// Nothing is measured without this.
+    #[inline]
+    pub fn time_lifecycle(mut self, report: fn(LifecycleTimings)) -> Self {
+        self.time_lifecycle = Some(report);
+        self
+    }
+
+    /// Wait until all worker threads are about to run the executor before running the main
+    /// future.
+    #[inline]
+    pub fn wait_for_workers(mut self) -> Self {
+        self.wait_for_workers = true;
+        self
+    }
+
+    /// Always run [`AnyExecutor`] as a thread-local executor on the current thread.
+    #[inline]
+    pub fn local(mut self) -> Self {
+        self.local = true;
+        self
+    }
+
+    /// Choose whether [`AnyExecutor`] runs a thread-local executor on the current thread.
+    #[inline]
+    pub fn single_threaded(mut self, single_threaded: bool) -> Self {
+        self.single_threaded = single_threaded;
+        self
+    }
+
+    /// Split the worker threads between this many executors, passed to `main` as a slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    #[inline]
+    pub fn shards(mut self, shards: usize) -> Self {
+        assert!(shards >= 1, "the number of shards must be at least 1");
+        self.shards = Some(shards);
+        self
+    }
+
+    /// Set the order in which [`ScheduledExecutor`] runs its ready tasks.
+    #[inline]
+    pub fn schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Block on a future on the current thread.
+    #[inline]
+    pub fn block_on<T>(&self, future: impl Future<Output = T>) -> T {
+        #[cfg(feature = "tokio-compat")]
+        let tokio = self.tokio_bridge.then(tokio_handle);
+        #[cfg(feature = "tokio-compat")]
+        let _tokio = tokio.as_ref().map(tokio::runtime::Handle::enter);
+
+        enter_runtime(|| {
+            if let Some(reactor) = self.reactor {
+                reactor.block_on(future)
+            } else if self.spin_wait {
+                spin_block_on(future)
+            } else if self.no_reactor {
+                future::block_on(future)
+            } else {
+                block_on(future)
+            }
+        })
+    }
+
+    /// Set up a runtime with this configuration and block on a future, like [`main`] with
+    /// `#[config]` does.
+    ///
+    /// The future is created by a function that is given the executor as an `Arc`, so that the
+    /// future can own it, like with [`run_test`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the worker threads can't be spawned.
+    ///
+    /// [`main`]: crate::main
+    #[inline]
+    pub fn run<T, F: Future<Output = T>>(
+        &self,
+        main: impl FnOnce(Arc<Executor<'static>>) -> F,
+    ) -> T {
+        <Arc<Executor<'static>> as MainExecutor>::with_main(self, |ex| {
+            self.block_on(ex.run(main(ex.clone())))
+        })
+    }
+
+    /// Set up a runtime with this configuration for a benchmark, like [`bench_on`] does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the worker threads can't be spawned.
+    pub fn bench<T, F: Future<Output = T>>(
+        &self,
+        mut routine: impl FnMut(Arc<Executor<'static>>) -> F,
+    ) -> impl FnMut() -> T {
+        let config = self.clone();
+        let ex = Arc::new(Executor::new());
+        let pool = (!config.current_thread)
+            .then(|| ThreadPool::spawn(&ex, &config).unwrap_or_else(|err| panic!("{err}")));
+
+        move || {
+            // The pool is only kept alive by the closure, and stopped once it is dropped.
+            let _pool = &pool;
+            with_current_executor(Some(ex.clone()), || {
+                config.block_on(ex.run(routine(ex.clone())))
+            })
+        }
+    }
+}
+
+/// Get the handle of the tokio runtime that `async-compat` runs futures in.
+#[cfg(feature = "tokio-compat")]
+fn tokio_handle() -> tokio::runtime::Handle {
+    future::block_on(async_compat::Compat::new(async {
+        tokio::runtime::Handle::current()
+    }))
+}
+
+/// Block on a future by spinning until it is woken, instead of parking the thread.
+fn spin_block_on<T>(future: impl Future<Output = T>) -> T {
+    /// Records that the future was woken.
+    struct Spinner(AtomicBool);
+
+    impl Wake for Spinner {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.store(true, Ordering::Release);
+        }
+    }
+
+    let spinner = Arc::new(Spinner(AtomicBool::new(false)));
+    let waker = Waker::from(spinner.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    futures_lite::pin!(future);
+    loop {
+        if let Poll::Ready(value) = future.as_mut().poll(&mut cx) {
+            return value;
+        }
+
+        while !spinner.0.swap(false, Ordering::Acquire) {
+            hint::spin_loop();
+        }
+    }
+}
+
+/// Start up threads in the `blocking` thread pool by running trivial tasks on it.
+#[doc(hidden)]
+pub async fn prewarm_blocking(count: usize) {
+    let tasks = (0..count).map(|_| blocking::unblock(|| ()));
+    for task in tasks.collect::<Vec<_>>() {
+        task.await;
+    }
+}
+
+/// Set an environment variable read by the I/O runtime, unless it is already set.
+#[doc(hidden)]
+pub fn set_default_env(name: &str, value: impl fmt::Display) {
+    if std::env::var_os(name).is_none() {
+        std::env::set_var(name, value.to_string());
+    }
+}
+
+/// Wait until an executor has no unfinished tasks left.
+///
+/// The executor doesn't notify anyone when it becomes empty, so this polls it periodically.
+#[doc(hidden)]
+pub async fn wait_until_empty(is_empty: impl Fn() -> bool) {
+    while !is_empty() {
+        reactor::sleep(Duration::from_millis(1)).await;
+    }
+}
+
+/// Wait until an executor has no unfinished tasks left, but at most for the timeout.
+#[doc(hidden)]
+pub async fn drain_with_timeout(is_empty: impl Fn() -> bool, timeout: Duration) {
+    if timeout.is_zero() {
+        return;
+    }
+
+    wait_until_empty(is_empty).or(reactor::sleep(timeout)).await
+}
+
+/// The number of restarts allowed by `#[resilient_workers]` without an explicit cap.
+#[doc(hidden)]
+pub const DEFAULT_WORKER_RESTARTS: usize = 16;
+
+/// Run an executor alongside a future, polling the future again after every `budget` tasks.
+#[doc(hidden)]
+pub async fn run_with_budget<T, F: Future<Output = ()>>(
+    tick: impl Fn() -> F,
+    budget: usize,
+    future: impl Future<Output = T>,
+) -> T {
+    let run = async {
+        loop {
+            for _ in 0..budget.max(1) {
+                tick().await;
+            }
+
+            // Let the main future be polled again.
+            future::yield_now().await;
+        }
+    };
+
+    future.or(run).await
+}
+
+/// Parse a duration literal like `500ms` or `10s`.
+///
+/// This is a `const fn` so that the macros can evaluate it in a `const` item, which turns an
+/// invalid literal into a compile error instead of a panic once `main` runs.
+#[doc(hidden)]
+pub const fn parse_duration(literal: &str) -> Duration {
+    let bytes = literal.as_bytes();
+    let mut value: u64 = 0;
+    let mut digits = 0;
+    let mut i = 0;
+    while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'_') {
+        if bytes[i] != b'_' {
+            value = match value.checked_mul(10) {
+                Some(value) => match value.checked_add((bytes[i] - b'0') as u64) {
+                    Some(value) => value,
+                    None => panic!("the duration is too long"),
+                },
+                None => panic!("the duration is too long"),
+            };
+            digits += 1;
+        }
+        i += 1;
+    }
+    if digits == 0 {
+        panic!("invalid duration, expected an integer followed by one of: ns, us, ms, s, m, h");
+    }
+
+    // The unit is one or two characters long.
+    let unit = match bytes.len() - i {
+        1 => (bytes[i], 0),
+        2 => (bytes[i], bytes[i + 1]),
+        _ => (0, 0),
+    };
+    let secs = match unit {
+        (b'n', b's') => return Duration::from_nanos(value),
+        (b'u', b's') => return Duration::from_micros(value),
+        (b'm', b's') => return Duration::from_millis(value),
+        (b's', 0) => 1,
+        (b'm', 0) => 60,
+        (b'h', 0) => 60 * 60,
+        _ => panic!("invalid duration unit, expected one of: ns, us, ms, s, m, h"),
+    };
+    match value.checked_mul(secs) {
+        Some(secs) => Duration::from_secs(secs),
+        None => panic!("the duration is too long"),
+    }
+}
+
+/// Run a function that takes an `Executor` on the current thread.
+///
+/// The current thread drives the executor while it blocks on the main future, so no other
+/// threads are needed.
+#[cfg(any(feature = "single-thread", target_family = "wasm"))]
+#[inline]
+fn with_thread_pool<T>(
+    _ex: &Executor<'_>,
+    _config: &Config,
+    f: impl FnOnce() -> T,
+) -> Result<T, SetupError> {
+    Ok(f())
+}
+
+/// Run a function that takes a `PriorityExecutor` on the current thread.
+#[cfg(any(feature = "single-thread", target_family = "wasm"))]
+#[inline]
+fn with_priority_pool<T>(
+    _high: &Executor<'_>,
+    _low: &Executor<'_>,
+    _config: &Config,
+    f: impl FnOnce() -> T,
+) -> Result<T, SetupError> {
+    Ok(f())
+}
+
+/// Get the number of worker threads that a `PinnedExecutor` needs an executor for.
+#[cfg(any(feature = "single-thread", target_family = "wasm"))]
+#[inline]
+fn pinned_workers(_config: &Config) -> usize {
+    0
+}
+
+/// Run a function that takes a `PinnedExecutor` on the current thread.
+#[cfg(any(feature = "single-thread", target_family = "wasm"))]
+#[inline]
+fn with_pinned_pool<T>(
+    _shared: &Executor<'_>,
+    _workers: &[Executor<'_>],
+    _config: &Config,
+    f: impl FnOnce() -> T,
+) -> Result<T, SetupError> {
+    Ok(f())
+}
+
+/// Run a function that takes a `ScheduledExecutor` on the current thread.
+#[cfg(any(feature = "single-thread", target_family = "wasm"))]
+#[inline]
+fn with_scheduled_pool<T>(
+    _ex: &ScheduledExecutor<'_>,
+    _config: &Config,
+    f: impl FnOnce() -> T,
+) -> Result<T, SetupError> {
+    Ok(f())
+}
+
+/// Run a function that takes `Shards` on the current thread.
+#[cfg(any(feature = "single-thread", target_family = "wasm"))]
+#[inline]
+fn with_sharded_pool<T>(
+    _shards: &[Executor<'_>],
+    _config: &Config,
+    f: impl FnOnce() -> T,
+) -> Result<T, SetupError> {
+    Ok(f())
+}
+
+/// Run a function that takes an `Executor` on the current thread.
+#[cfg(any(feature = "single-thread", target_family = "wasm"))]
+#[inline]
+fn with_shared_pool<T>(ex: &Arc<Executor<'static>>, _config: &Config, f: impl FnOnce() -> T) -> T {
+    with_current_executor(Some(ex.clone()), f)
+}
@@ -0,0 +1,140 @@
+//! A clock for testing time-dependent code without waiting.
+
+use event_listener::Event;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// A virtual clock that only moves forward when it is told to.
+///
+/// Code that waits on the clock through [`sleep`] or [`sleep_until`] waits until [`advance`]
+/// moves the clock past its deadline, however much time actually passed. This makes timeouts and
+/// retries testable in an instant, and without depending on how busy the machine is. All clones
+/// share the same time.
+///
+/// With the `#[mock_time]` attribute, [`test`] creates a clock and passes it in as a parameter,
+/// either as the only one or after the executor.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{test, Executor, MockClock};
+/// use std::time::Duration;
+///
+/// #[apply(test!)]
+/// #[mock_time]
+/// async fn retries_after_a_minute(ex: &Executor<'_>, clock: &MockClock) {
+///     let retry_at = clock.now() + Duration::from_secs(60);
+///     let task = ex.spawn({
+///         let clock = clock.clone();
+///         async move {
+///             clock.sleep_until(retry_at).await;
+///             "retried"
+///         }
+///     });
+///
+///     clock.advance(Duration::from_secs(60));
+///     assert_eq!(task.await, "retried");
+/// }
+/// ```
+///
+/// Only code that waits on this clock is affected. Timers of `async-io`, [`Instant::now`] and
+/// real I/O keep using the time of the system, so the code under test has to take the clock as a
+/// parameter or through some other abstraction. Advancing the clock only wakes the tasks that
+/// wait on it, so a body that advances it and then checks on them has to let them run first,
+/// like by awaiting them. A spawned task may only start after the body advanced the clock, so
+/// deadlines that tasks wait for should be computed before spawning them, like in the example.
+///
+/// [`sleep`]: MockClock::sleep
+/// [`sleep_until`]: MockClock::sleep_until
+/// [`advance`]: MockClock::advance
+/// [`test`]: crate::test
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    /// The state shared between all clones.
+    inner: Arc<Inner>,
+}
+
+/// The state of a clock.
+#[derive(Debug)]
+struct Inner {
+    /// The time the clock started at.
+    start: Instant,
+
+    /// How far the clock has been advanced.
+    elapsed: Mutex<Duration>,
+
+    /// Notified whenever the clock is advanced.
+    advanced: Event,
+}
+
+impl MockClock {
+    /// Create a clock that starts at the current time.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                start: Instant::now(),
+                elapsed: Mutex::new(Duration::ZERO),
+                advanced: Event::new(),
+            }),
+        }
+    }
+
+    /// Get the current time of the clock.
+    #[inline]
+    pub fn now(&self) -> Instant {
+        self.inner.start + self.elapsed()
+    }
+
+    /// Get how far the clock has been advanced since it was created.
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        *self
+            .inner
+            .elapsed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Move the clock forward, waking up everything that waits for a time that has now passed.
+    pub fn advance(&self, duration: Duration) {
+        *self
+            .inner
+            .elapsed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) += duration;
+        self.inner.advanced.notify_additional(usize::MAX);
+    }
+
+    /// Wait until the clock has been advanced by this much from its current time.
+    ///
+    /// The deadline is fixed when this is called, not when the future is first polled.
+    #[inline]
+    pub fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send + '_ {
+        self.sleep_until(self.now() + duration)
+    }
+
+    /// Wait until the clock has been advanced to this time.
+    pub async fn sleep_until(&self, deadline: Instant) {
+        loop {
+            if self.now() >= deadline {
+                return;
+            }
+
+            event_listener::listener!(&self.inner.advanced => listener);
+
+            if self.now() >= deadline {
+                return;
+            }
+
+            listener.await;
+        }
+    }
+}
+
+impl Default for MockClock {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
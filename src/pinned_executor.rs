@@ -0,0 +1,127 @@
+//! An executor whose tasks can be pinned to one of the worker threads.
+
+use crate::Executor;
+use async_executor::Task;
+use std::future::Future;
+
+/// A thread-safe executor with an extra executor for each worker thread, which only that thread
+/// runs.
+///
+/// [`Executor`] balances its tasks between the worker threads, so a task may run on a different
+/// thread every time it is woken. For work that benefits from staying on one thread, like a task
+/// that keeps a large table warm in the cache of its CPU, [`spawn_on_worker`] spawns a task onto
+/// the executor of a single worker thread instead. When used as the executor in [`main`], each
+/// worker thread runs its own executor along with the shared one, preferring its own tasks, and
+/// the tasks spawned with [`spawn`] still run on any thread, like with [`Executor`].
+///
+/// Pinning only pays off if the tasks are expensive enough for the cache to matter, and the
+/// work is spread evenly between the threads, since a busy thread can't hand its pinned tasks
+/// to an idle one. As long as a thread has pinned tasks that are ready, it doesn't run shared
+/// ones, so the shared tasks only get the threads that are left. Idle worker threads don't exit
+/// with `#[idle_timeout]`, and the pool doesn't start lazily with `#[lazy_workers]`, since each
+/// executor needs its thread. For most programs, a plain [`Executor`] is faster and simpler.
+///
+/// The pinned futures still have to be [`Send`], since they are spawned from other threads. To
+/// run futures that aren't, spawn a task onto the worker that creates a [`LocalExecutor`] and
+/// runs them on it.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, PinnedExecutor};
+///
+/// #[apply(main!)]
+/// async fn main(ex: &PinnedExecutor<'_>) {
+///     let tasks = (0..ex.workers())
+///         .map(|index| ex.spawn_on_worker(index, async { std::thread::current().id() }))
+///         .collect::<Vec<_>>();
+///     for task in tasks {
+///         assert_ne!(task.await, std::thread::current().id());
+///     }
+/// }
+/// ```
+///
+/// [`main`]: crate::main
+/// [`spawn`]: PinnedExecutor::spawn
+/// [`spawn_on_worker`]: PinnedExecutor::spawn_on_worker
+/// [`LocalExecutor`]: crate::LocalExecutor
+#[derive(Debug, Default)]
+pub struct PinnedExecutor<'a> {
+    /// The executor run by all threads.
+    pub(crate) shared: Executor<'a>,
+
+    /// The executors that are each run by a single worker thread.
+    pub(crate) workers: Vec<Executor<'a>>,
+}
+
+impl<'a> PinnedExecutor<'a> {
+    /// Create a shared executor with an executor for each of this many worker threads.
+    #[inline]
+    pub fn new(workers: usize) -> Self {
+        Self {
+            shared: Executor::new(),
+            workers: (0..workers).map(|_| Executor::new()).collect(),
+        }
+    }
+
+    /// Get the executor run by all threads.
+    #[inline]
+    pub fn shared(&self) -> &Executor<'a> {
+        &self.shared
+    }
+
+    /// Get the number of worker threads that tasks can be pinned to.
+    ///
+    /// This is `0` if the executor isn't run by a thread pool, like with the `single-thread`
+    /// feature or `#[current_thread]`.
+    #[inline]
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Spawn a task onto the shared executor, which runs it on any thread.
+    #[inline]
+    pub fn spawn<T: Send + 'a>(&self, future: impl Future<Output = T> + Send + 'a) -> Task<T> {
+        self.shared.spawn(future)
+    }
+
+    /// Spawn a task that only runs on the worker thread with this index.
+    ///
+    /// The index wraps around the number of [`workers`](PinnedExecutor::workers), so tasks can
+    /// be spread out by e.g. the hash of a key. Without any worker threads, the task is spawned
+    /// onto the shared executor.
+    #[inline]
+    pub fn spawn_on_worker<T: Send + 'a>(
+        &self,
+        index: usize,
+        future: impl Future<Output = T> + Send + 'a,
+    ) -> Task<T> {
+        match self.workers.len() {
+            0 => self.shared.spawn(future),
+            workers => self.workers[index % workers].spawn(future),
+        }
+    }
+
+    /// Run the shared executor until the future completes.
+    #[inline]
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+        self.shared.run(future).await
+    }
+
+    /// Wait for a single task of the shared executor to be ready and run it.
+    #[inline]
+    pub async fn tick(&self) {
+        self.shared.tick().await
+    }
+
+    /// Attempt to run a single task of the shared executor, returning `true` if one was run.
+    #[inline]
+    pub fn try_tick(&self) -> bool {
+        self.shared.try_tick()
+    }
+
+    /// Check whether there are any tasks on any of the executors that still need to be run.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.shared.is_empty() && self.workers.iter().all(Executor::is_empty)
+    }
+}
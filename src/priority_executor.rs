@@ -0,0 +1,128 @@
+//! A pair of executors, one of which is preferred over the other.
+
+use crate::Executor;
+use async_executor::Task;
+use futures_lite::future::{self, FutureExt};
+use std::future::Future;
+
+/// A thread-safe executor with a high-priority and a low-priority lane.
+///
+/// When used as the executor in [`main`], the worker threads of the thread pool run the tasks of
+/// both lanes, but always prefer the tasks of the [`high`] lane: a task of the [`low`] lane only
+/// runs once no high-priority task is ready to run. This is useful for keeping latency-sensitive
+/// work, like answering requests, responsive while the pool is busy with batch work.
+///
+/// Since `async-executor` doesn't have priorities of its own, each lane is a separate
+/// [`Executor`], and the tasks are run one at a time without the local queues of
+/// [`Executor::run`]. This makes the lanes slower than a single executor when there is a lot of
+/// contention. As long as high-priority tasks keep coming in, low-priority tasks don't run at
+/// all, so the high lane should be reserved for work that leaves the pool idle once in a while.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, PriorityExecutor};
+///
+/// #[apply(main!)]
+/// async fn main(ex: &PriorityExecutor<'_>) {
+///     let report = ex.low().spawn(async { (0..1000u64).sum::<u64>() });
+///     let ping = ex.high().spawn(async { "pong" });
+///
+///     assert_eq!(ping.await, "pong");
+///     assert_eq!(report.await, 499500);
+/// }
+/// ```
+///
+/// [`main`]: crate::main
+/// [`high`]: PriorityExecutor::high
+/// [`low`]: PriorityExecutor::low
+#[derive(Debug, Default)]
+pub struct PriorityExecutor<'a> {
+    /// The executor whose tasks are preferred.
+    pub(crate) high: Executor<'a>,
+
+    /// The executor whose tasks run when no preferred task is ready.
+    pub(crate) low: Executor<'a>,
+}
+
+impl<'a> PriorityExecutor<'a> {
+    /// Create a new pair of executors.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the executor for high-priority tasks.
+    #[inline]
+    pub fn high(&self) -> &Executor<'a> {
+        &self.high
+    }
+
+    /// Get the executor for low-priority tasks.
+    #[inline]
+    pub fn low(&self) -> &Executor<'a> {
+        &self.low
+    }
+
+    /// Spawn a task onto the high-priority lane.
+    #[inline]
+    pub fn spawn<T: Send + 'a>(&self, future: impl Future<Output = T> + Send + 'a) -> Task<T> {
+        self.high.spawn(future)
+    }
+
+    /// Run both lanes until the future completes, preferring the high-priority tasks.
+    #[inline]
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+        run_lanes(&self.high, &self.low, future).await
+    }
+
+    /// Wait for a single task to be ready and run it, preferring the high-priority tasks.
+    #[inline]
+    pub async fn tick(&self) {
+        tick_lanes(&self.high, &self.low).await
+    }
+
+    /// Attempt to run a single task, preferring the high-priority tasks, returning `true` if one
+    /// was run.
+    #[inline]
+    pub fn try_tick(&self) -> bool {
+        try_tick_lanes(&self.high, &self.low)
+    }
+
+    /// Check whether there are any tasks in either lane that still need to be run.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.high.is_empty() && self.low.is_empty()
+    }
+}
+
+/// Run the tasks of two executors until the future completes, preferring the first one.
+pub(crate) async fn run_lanes<T>(
+    high: &Executor<'_>,
+    low: &Executor<'_>,
+    future: impl Future<Output = T>,
+) -> T {
+    future
+        .or(async {
+            loop {
+                // Let the reactor and the future make progress once in a while, like
+                // `Executor::run` does.
+                for _ in 0..200 {
+                    tick_lanes(high, low).await;
+                }
+                future::yield_now().await;
+            }
+        })
+        .await
+}
+
+/// Wait for a task of either executor to be ready and run it, preferring the first one.
+pub(crate) async fn tick_lanes(high: &Executor<'_>, low: &Executor<'_>) {
+    if !try_tick_lanes(high, low) {
+        high.tick().or(low.tick()).await;
+    }
+}
+
+/// Run a task of either executor if one is ready, preferring the first one.
+pub(crate) fn try_tick_lanes(high: &Executor<'_>, low: &Executor<'_>) -> bool {
+    high.try_tick() || low.try_tick()
+}
@@ -0,0 +1,103 @@
+//! Limiting the number of tasks that are waiting to start.
+
+use crate::Executor;
+use async_executor::Task;
+use async_lock::Semaphore;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Spawns tasks onto an executor, with at most a given number of them queued at the same time.
+///
+/// A task is queued from the moment it is spawned until it is polled for the first time. Once
+/// the limit is reached, [`spawn`] waits for one of the queued tasks to start before spawning
+/// another one, so that code spawning tasks faster than the worker threads start them is slowed
+/// down instead of piling up tasks in memory. Tasks spawned onto the executor directly don't
+/// count towards the limit.
+///
+/// This is different from [`TaskLimit`](crate::TaskLimit), which limits the number of tasks that
+/// are running, from the moment they are spawned until they complete. A queue limit lets any
+/// number of tasks run at once, as long as the worker threads keep up with starting them.
+///
+/// With the `#[max_queued_tasks(N)]` attribute, [`main`] creates a limit for the executor and
+/// passes it in as the second parameter.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, Executor, QueueLimit};
+///
+/// #[apply(main!)]
+/// #[max_queued_tasks(4)]
+/// async fn main(ex: &Executor<'_>, queue: &QueueLimit<'_, '_>) {
+///     let mut tasks = Vec::new();
+///     for i in 0..16 {
+///         tasks.push(queue.spawn(async move { i * 2 }).await);
+///     }
+///
+///     for task in tasks {
+///         task.await;
+///     }
+/// }
+/// ```
+///
+/// Waiting in [`spawn`] doesn't block the thread, so the queued tasks still start while the
+/// spawning code waits, even when tasks spawn more tasks through the limit. It deadlocks if
+/// nothing runs the executor in the meantime, like when tasks wait for a full queue with a
+/// blocking call such as `future::block_on` on every thread that runs the executor, since the
+/// queued tasks then never start.
+///
+/// [`spawn`]: QueueLimit::spawn
+/// [`main`]: crate::main
+#[derive(Debug)]
+pub struct QueueLimit<'a, 'ex> {
+    /// The executor to spawn the tasks onto.
+    ex: &'a Executor<'ex>,
+
+    /// A permit for every task that can still be queued.
+    permits: Arc<Semaphore>,
+}
+
+impl<'a, 'ex> QueueLimit<'a, 'ex> {
+    /// Create a limit of this many queued tasks for an executor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the limit is zero, since no task could ever be spawned.
+    #[inline]
+    pub fn new(ex: &'a Executor<'ex>, max_queued: usize) -> Self {
+        assert!(max_queued > 0, "the queue limit must be at least 1");
+        Self {
+            ex,
+            permits: Arc::new(Semaphore::new(max_queued)),
+        }
+    }
+
+    /// Wait until fewer tasks than the limit are queued, then spawn a task.
+    ///
+    /// The task counts towards the limit until it is polled for the first time or cancelled.
+    pub async fn spawn<T: Send + 'ex>(
+        &self,
+        future: impl Future<Output = T> + Send + 'ex,
+    ) -> Task<T> {
+        let permit = self.permits.acquire_arc().await;
+        self.ex.spawn(async move {
+            drop(permit);
+            future.await
+        })
+    }
+
+    /// Spawn a task if fewer tasks than the limit are queued.
+    ///
+    /// Returns the future back if the limit is reached.
+    pub fn try_spawn<T: Send + 'ex, F: Future<Output = T> + Send + 'ex>(
+        &self,
+        future: F,
+    ) -> Result<Task<T>, F> {
+        match self.permits.try_acquire_arc() {
+            Some(permit) => Ok(self.ex.spawn(async move {
+                drop(permit);
+                future.await
+            })),
+            None => Err(future),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Blocking on futures and waiting for timers with a reactor other than `async-io`.
+
+use std::cell::Cell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// The reactor that threads of the runtime block on futures with, and that timers wait on.
+///
+/// By default, the runtime blocks on futures with [`async_io::block_on`], which drives the
+/// `async-io` reactor, and waits for timers with [`async_io::Timer`]. With the
+/// `#[reactor(MyReactor)]` attribute, [`main`] and [`test`] use the functions of `MyReactor`
+/// instead, both on the current thread and on the worker threads. This lets code that is built
+/// on a different reactor run on the runtime, and lets tests make time deterministic with a
+/// reactor whose timers are under their control. The default is available as [`AsyncIo`].
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{test, Reactor};
+/// use std::future::Future;
+/// use std::pin::Pin;
+/// use std::time::Duration;
+///
+/// /// Blocks without a reactor, and lets timers elapse right away.
+/// struct NoWait;
+///
+/// impl Reactor for NoWait {
+///     type Sleep = futures_lite::future::Ready<()>;
+///
+///     fn block_on<T>(future: impl Future<Output = T>) -> T {
+///         futures_lite::future::block_on(future)
+///     }
+///
+///     fn sleep(_duration: Duration) -> Self::Sleep {
+///         futures_lite::future::ready(())
+///     }
+/// }
+///
+/// #[apply(test!)]
+/// #[reactor(NoWait)]
+/// #[timeout(1h)]
+/// #[should_panic(expected = "timed out")]
+/// async fn hangs() {
+///     futures_lite::future::pending::<()>().await;
+/// }
+/// ```
+///
+/// Only the timers of the runtime itself go through the reactor, which are the ones of
+/// `#[timeout]` and of waiting for spawned tasks with `#[await_spawned]` or `#[drain_timeout]`.
+/// `#[idle_timeout]` and `#[shutdown_timeout]` keep using the time of the system, and so do
+/// timers and I/O that the body creates itself.
+///
+/// [`main`]: crate::main
+/// [`test`]: crate::test
+/// [`async_io::Timer`]: https://docs.rs/async-io/latest/async_io/struct.Timer.html
+/// [`async_io::block_on`]: https://docs.rs/async-io/latest/async_io/fn.block_on.html
+pub trait Reactor {
+    /// The future returned by [`sleep`](Reactor::sleep).
+    type Sleep: Future<Output = ()> + Send + 'static;
+
+    /// Block the current thread on a future, driving the reactor while it waits.
+    fn block_on<T>(future: impl Future<Output = T>) -> T;
+
+    /// Create a future that completes once this much time has passed.
+    fn sleep(duration: Duration) -> Self::Sleep;
+}
+
+/// The default [`Reactor`], which is the one of `async-io`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncIo;
+
+impl Reactor for AsyncIo {
+    type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    #[inline]
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        async_io::block_on(future)
+    }
+
+    #[inline]
+    fn sleep(duration: Duration) -> Self::Sleep {
+        Box::pin(async move {
+            async_io::Timer::after(duration).await;
+        })
+    }
+}
+
+/// The functions of a [`Reactor`], with the types erased so that they fit into the
+/// configuration.
+#[derive(Clone, Copy)]
+pub(crate) struct ReactorFns {
+    /// The name of the reactor type.
+    name: &'static str,
+
+    /// Blocks on a future without an output.
+    block_on: fn(Pin<&mut dyn Future<Output = ()>>),
+
+    /// Creates a timer.
+    sleep: fn(Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+impl ReactorFns {
+    /// Erase the types of a reactor.
+    pub(crate) fn new<R: Reactor>() -> Self {
+        Self {
+            name: std::any::type_name::<R>(),
+            block_on: |future| R::block_on(future),
+            sleep: |duration| Box::pin(R::sleep(duration)),
+        }
+    }
+
+    /// Block on a future with the reactor, which is used for the timers of the runtime meanwhile.
+    pub(crate) fn block_on<T>(self, future: impl Future<Output = T>) -> T {
+        /// Restores the previous reactor once dropped.
+        struct Guard(Option<ReactorFns>);
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                CURRENT.with(|current| current.set(self.0));
+            }
+        }
+
+        let _guard = Guard(CURRENT.with(|current| current.replace(Some(self))));
+
+        let mut output = None;
+        {
+            let future = async {
+                output = Some(future.await);
+            };
+            futures_lite::pin!(future);
+            (self.block_on)(future);
+        }
+        output.expect("the reactor returned before the future completed")
+    }
+}
+
+impl fmt::Debug for ReactorFns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ReactorFns").field(&self.name).finish()
+    }
+}
+
+thread_local! {
+    /// The reactor that the current thread blocks on futures with, if it isn't the default one.
+    static CURRENT: Cell<Option<ReactorFns>> = const { Cell::new(None) };
+}
+
+/// Wait for this much time to pass, according to the reactor of the current thread.
+pub(crate) fn sleep(duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    match CURRENT.with(Cell::get) {
+        Some(reactor) => (reactor.sleep)(duration),
+        None => AsyncIo::sleep(duration),
+    }
+}
@@ -0,0 +1,75 @@
+//! Running tests repeatedly.
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/// The value returned by a single iteration of a test.
+pub trait Iteration {
+    /// Panic if the iteration failed.
+    fn check(self, iteration: usize, times: usize);
+}
+
+impl Iteration for () {
+    #[inline]
+    fn check(self, _iteration: usize, _times: usize) {}
+}
+
+impl<T, E: fmt::Debug> Iteration for Result<T, E> {
+    #[inline]
+    fn check(self, iteration: usize, times: usize) {
+        if let Err(err) = self {
+            panic!("iteration {iteration} of {times} failed: {err:?}");
+        }
+    }
+}
+
+/// Run a test the given number of times, stopping at the first iteration that fails.
+pub fn repeat<T: Iteration>(times: usize, mut f: impl FnMut() -> T) {
+    for iteration in 1..=times {
+        match panic::catch_unwind(AssertUnwindSafe(&mut f)) {
+            Ok(output) => output.check(iteration, times),
+            Err(err) => {
+                eprintln!("iteration {iteration} of {times} panicked");
+                panic::resume_unwind(err);
+            }
+        }
+    }
+}
+
+/// Run a test up to the given number of times, as long as it fails with an error of type `T`.
+///
+/// Other errors and panics fail the test right away, and the last error is returned once all
+/// attempts failed.
+pub fn retry<T, U, E>(times: usize, mut f: impl FnMut() -> Result<U, E>) -> Result<U, E>
+where
+    T: Error + 'static,
+    E: Any + fmt::Debug,
+{
+    let times = times.max(1);
+    for attempt in 1.. {
+        match f() {
+            Err(err) if attempt < times && is_error::<T>(&err) => {
+                eprintln!("attempt {attempt} of {times} failed, retrying: {err:?}");
+            }
+            output => return output,
+        }
+    }
+    unreachable!()
+}
+
+/// Check whether an error is of type `T`, or a boxed error of type `T`.
+fn is_error<T: Error + 'static>(err: &dyn Any) -> bool {
+    if err.is::<T>() {
+        return true;
+    }
+    if let Some(err) = err.downcast_ref::<Box<dyn Error + Send + Sync>>() {
+        return err.is::<T>();
+    }
+    if let Some(err) = err.downcast_ref::<Box<dyn Error + Send>>() {
+        return err.is::<T>();
+    }
+    err.downcast_ref::<Box<dyn Error>>()
+        .map_or(false, |err| err.is::<T>())
+}
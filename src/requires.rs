@@ -0,0 +1,49 @@
+//! Skipping tests that need resources which aren't allowed.
+
+use std::env;
+
+/// The environment variable listing the resources that tests are allowed to use.
+const ALLOW_VAR: &str = "SMOL_TEST_ALLOW";
+
+/// The value returned by a test that passed without running its body to completion, because
+/// it was skipped or was expected to time out.
+pub trait Skipped {
+    /// The value that counts as a success.
+    fn skipped() -> Self;
+}
+
+impl Skipped for () {
+    #[inline]
+    fn skipped() -> Self {}
+}
+
+impl<T: Skipped, E> Skipped for Result<T, E> {
+    #[inline]
+    fn skipped() -> Self {
+        Ok(T::skipped())
+    }
+}
+
+/// Tell whether the test may run, printing a message if it has to be skipped.
+pub fn requires(name: &str, resources: &[&str]) -> bool {
+    let allowed = env::var(ALLOW_VAR).unwrap_or_default();
+    let missing = resources
+        .iter()
+        .filter(|resource| {
+            !allowed
+                .split(',')
+                .any(|allowed| allowed.trim() == **resource)
+        })
+        .copied()
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() {
+        return true;
+    }
+
+    eprintln!(
+        "skipping `{name}`: it requires {}, add it to `{ALLOW_VAR}` to run it",
+        missing.join(", ")
+    );
+    false
+}
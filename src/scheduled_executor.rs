@@ -0,0 +1,270 @@
+//! An executor that runs its ready tasks in a chosen order.
+
+use crate::Executor;
+use async_executor::Task;
+use event_listener::Event;
+use futures_lite::future::{self, FutureExt};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// The order in which the ready tasks of a [`ScheduledExecutor`] run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Schedule {
+    /// Run the task that became ready first, like [`Executor`] does.
+    #[default]
+    Fifo,
+
+    /// Run the task that became ready last.
+    Lifo,
+}
+
+/// A thread-safe executor that runs its ready tasks in a chosen order.
+///
+/// [`Executor`] always runs the task that became ready first. This executor can run the task
+/// that became ready last instead, which is chosen with the `#[schedule(lifo)]` attribute when
+/// used in [`main`]. Running the most recent task first keeps the data it just touched in the
+/// cache, and lets a task that spawns work and waits for it get its answer sooner, at the cost
+/// of fairness: as long as new tasks keep becoming ready, the older ones wait. Without the
+/// attribute, or with `#[schedule(fifo)]`, the tasks run in the same order as with [`Executor`].
+///
+/// Since `async-executor` has no way to reorder its queue, the tasks are spawned onto an inner
+/// [`Executor`], but their wake-ups go to a queue of this executor first, which hands them to
+/// the inner one a single task at a time. A newly spawned task is queued the same way before it
+/// runs for the first time. This makes each wake-up more expensive than with a plain
+/// [`Executor`], and the local queues of [`Executor::run`] aren't used.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, ScheduledExecutor};
+///
+/// #[apply(main!)]
+/// #[schedule(lifo)]
+/// async fn main(ex: &ScheduledExecutor<'_>) {
+///     let task = ex.spawn(async { 1 + 2 });
+///     assert_eq!(task.await, 3);
+/// }
+/// ```
+///
+/// [`main`]: crate::main
+#[derive(Debug)]
+pub struct ScheduledExecutor<'a> {
+    /// The executor that runs the tasks once they are taken off the queue.
+    inner: Executor<'a>,
+
+    /// The tasks that are ready to run, in the order they became ready.
+    queue: Arc<ReadyQueue>,
+}
+
+impl Default for ScheduledExecutor<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self::new(Schedule::default())
+    }
+}
+
+impl<'a> ScheduledExecutor<'a> {
+    /// Create a new executor running its tasks in this order.
+    #[inline]
+    pub fn new(schedule: Schedule) -> Self {
+        Self {
+            inner: Executor::new(),
+            queue: Arc::new(ReadyQueue {
+                schedule,
+                wakers: Mutex::new(VecDeque::new()),
+                ready: Event::new(),
+            }),
+        }
+    }
+
+    /// Get the order in which the ready tasks run.
+    #[inline]
+    pub fn schedule(&self) -> Schedule {
+        self.queue.schedule
+    }
+
+    /// Spawn a task onto the executor.
+    #[inline]
+    pub fn spawn<T: Send + 'a>(&self, future: impl Future<Output = T> + Send + 'a) -> Task<T> {
+        self.inner.spawn(Ordered {
+            future: Box::pin(future),
+            queued: false,
+            hook: Arc::new(Hook {
+                queue: self.queue.clone(),
+                waker: Mutex::new(None),
+            }),
+        })
+    }
+
+    /// Run the executor until the future completes.
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+        future
+            .or(async {
+                loop {
+                    // Let the reactor and the future make progress once in a while, like
+                    // `Executor::run` does.
+                    for _ in 0..200 {
+                        self.tick().await;
+                    }
+                    future::yield_now().await;
+                }
+            })
+            .await
+    }
+
+    /// Wait for a single task to be ready and run it.
+    pub async fn tick(&self) {
+        loop {
+            if self.try_tick() {
+                return;
+            }
+
+            let listener = self.queue.ready.listen();
+            if self.try_tick() {
+                return;
+            }
+
+            // The inner executor only has tasks of its own while they are being handed over.
+            let ran = async {
+                listener.await;
+                false
+            }
+            .or(async {
+                self.inner.tick().await;
+                true
+            })
+            .await;
+            if ran {
+                return;
+            }
+        }
+    }
+
+    /// Attempt to run a single task, returning `true` if one was run.
+    #[inline]
+    pub fn try_tick(&self) -> bool {
+        // Queue the newly spawned tasks before picking one, so that they are ordered too.
+        if self.inner.try_tick() {
+            return true;
+        }
+
+        match self.queue.pop() {
+            Some(waker) => {
+                waker.wake();
+                self.inner.try_tick()
+            }
+            None => false,
+        }
+    }
+
+    /// Check whether there are any tasks that still need to be run.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+/// The wake-ups of the tasks that are ready to run.
+#[derive(Debug)]
+struct ReadyQueue {
+    /// The order in which the tasks are taken off the queue.
+    schedule: Schedule,
+
+    /// The wakers of the inner executor for each of the ready tasks.
+    wakers: Mutex<VecDeque<Waker>>,
+
+    /// Notified when a task is queued.
+    ready: Event,
+}
+
+impl ReadyQueue {
+    /// Queue a task that is ready to run.
+    fn push(&self, waker: Waker) {
+        self.wakers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push_back(waker);
+        self.ready.notify_additional(1);
+    }
+
+    /// Take the next task to run off the queue.
+    fn pop(&self) -> Option<Waker> {
+        let mut wakers = self.wakers.lock().unwrap_or_else(PoisonError::into_inner);
+        match self.schedule {
+            Schedule::Fifo => wakers.pop_front(),
+            Schedule::Lifo => wakers.pop_back(),
+        }
+    }
+}
+
+/// The waker handed to a task, which queues it instead of waking it right away.
+struct Hook {
+    /// The queue the task goes to.
+    queue: Arc<ReadyQueue>,
+
+    /// The waker of the inner executor, if the task isn't queued already.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Wake for Hook {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let waker = self
+            .waker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(waker) = waker {
+            self.queue.push(waker);
+        }
+    }
+}
+
+/// A future spawned onto the inner executor, whose wake-ups go through the queue.
+struct Ordered<F> {
+    /// The spawned future.
+    future: Pin<Box<F>>,
+
+    /// Whether the task went through the queue before its first poll.
+    queued: bool,
+
+    /// The waker handed to the spawned future.
+    hook: Arc<Hook>,
+}
+
+impl<F: Future> Future for Ordered<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        *self
+            .hook
+            .waker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(cx.waker().clone());
+
+        if !self.queued {
+            self.queued = true;
+            self.hook.wake_by_ref();
+            return Poll::Pending;
+        }
+
+        let waker = Waker::from(self.hook.clone());
+        self.future.as_mut().poll(&mut Context::from_waker(&waker))
+    }
+}
+
+/// An executor whose tasks run in the order given by `#[schedule]`.
+#[doc(hidden)]
+pub trait Scheduled {}
+
+impl Scheduled for ScheduledExecutor<'_> {}
+
+/// Check that an executor runs its tasks in the order given by `#[schedule]`.
+#[doc(hidden)]
+#[inline]
+pub fn requires_scheduled<T: Scheduled>() {}
@@ -0,0 +1,58 @@
+//! Seeds for reproducible tests.
+
+use std::cell::Cell;
+use std::env;
+use std::thread;
+
+thread_local! {
+    /// The seed of the test running on this thread.
+    static SEED: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// The environment variable used to replay a seed.
+const SEED_VAR: &str = "SMOL_TEST_SEED";
+
+/// Get the seed of the test running on the current thread.
+///
+/// This returns `None` unless it is called from the body of a [`test`] using the `#[seed]`
+/// attribute. See the documentation of [`test`] for more details.
+///
+/// [`test`]: crate::test
+#[inline]
+pub fn test_seed() -> Option<u64> {
+    SEED.with(|seed| seed.get())
+}
+
+/// Run a function with a test seed set, reporting the seed if it panics.
+pub fn with_seed<T>(f: impl FnOnce() -> T) -> T {
+    let seed = match env::var(SEED_VAR) {
+        Ok(seed) => match seed.trim().parse() {
+            Ok(seed) => seed,
+            Err(_) => panic!("{SEED_VAR} must be an unsigned integer, got `{seed}`"),
+        },
+        Err(_) => fastrand::u64(..),
+    };
+
+    /// Restores the previous seed and reports the seed on panic.
+    struct Guard {
+        seed: u64,
+        previous: Option<u64>,
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            if thread::panicking() {
+                eprintln!("test failed with seed={} ({SEED_VAR})", self.seed);
+            }
+
+            SEED.with(|seed| seed.set(self.previous));
+        }
+    }
+
+    let _guard = Guard {
+        seed,
+        previous: SEED.with(|previous| previous.replace(Some(seed))),
+    };
+
+    f()
+}
@@ -0,0 +1,22 @@
+//! Running tests that share a resource one at a time.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Run a function while holding the lock of a group, so that no other function of the same group
+/// runs at the same time.
+pub fn serial<T>(group: &str, f: impl FnOnce() -> T) -> T {
+    static GROUPS: Mutex<Option<HashMap<String, Arc<Mutex<()>>>>> = Mutex::new(None);
+
+    let lock = GROUPS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_or_insert_with(HashMap::new)
+        .entry(group.to_owned())
+        .or_default()
+        .clone();
+
+    // A test that fails while holding the lock poisons it, which doesn't affect the others.
+    let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+    f()
+}
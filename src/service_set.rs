@@ -0,0 +1,175 @@
+//! Long-running services that are shut down in a defined order.
+
+use crate::task_set::ExecutorRef;
+use crate::{AnyExecutor, CancellationToken, Executor, LocalExecutor};
+use async_executor::Task;
+use std::future::Future;
+use std::mem;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A set of long-running services, which are shut down one after the other in order of their
+/// priority.
+///
+/// Services like an HTTP server, a metrics exporter and a database pool often depend on each
+/// other, so they have to stop in a defined order: the server stops taking requests before the
+/// pool it uses is closed. Each service is spawned as a task that gets a [`CancellationToken`]
+/// of its own, and should return once it is cancelled. [`shutdown`] cancels the services with
+/// the lowest priority first, and waits for each of them to return before cancelling the next
+/// one. Services with the same priority are shut down in the reverse order of registration,
+/// like local variables are dropped.
+///
+/// With the `#[with_services]` attribute, [`main`] creates a service set for the executor,
+/// passes it in as the second parameter, and shuts it down once the body returns. Like with
+/// [`TaskSet`](crate::TaskSet), the services need to be `Send` even if the executor is a
+/// [`LocalExecutor`].
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, Executor, ServiceSet};
+/// use std::sync::{Arc, Mutex};
+///
+/// #[apply(main!)]
+/// #[with_services]
+/// async fn main(ex: &Executor<'_>, services: &ServiceSet<'_, '_>) {
+///     let stopped = Arc::new(Mutex::new(Vec::new()));
+///     for (priority, name) in [(2, "database"), (0, "http"), (1, "metrics")] {
+///         let stopped = stopped.clone();
+///         services.spawn(priority, move |token| async move {
+///             token.cancelled().await;
+///             stopped.lock().unwrap().push(name);
+///         });
+///     }
+///
+///     services.shutdown().await;
+///     assert_eq!(*stopped.lock().unwrap(), ["http", "metrics", "database"]);
+/// }
+/// ```
+///
+/// [`main`]: crate::main
+/// [`shutdown`]: ServiceSet::shutdown
+#[derive(Debug)]
+pub struct ServiceSet<'a, 'ex> {
+    /// The executor to spawn the services onto.
+    ex: ExecutorRef<'a, 'ex>,
+
+    /// The services that haven't been shut down yet, in the order they were registered.
+    services: Mutex<Vec<Service>>,
+}
+
+/// A service that is running in the background.
+#[derive(Debug)]
+struct Service {
+    /// Services with a lower priority are shut down first.
+    priority: u32,
+
+    /// Tells the service to shut down.
+    token: CancellationToken,
+
+    /// The task running the service.
+    task: Task<()>,
+}
+
+impl<'a, 'ex> ServiceSet<'a, 'ex> {
+    /// Create an empty set for an executor.
+    #[inline]
+    fn with_executor(ex: ExecutorRef<'a, 'ex>) -> Self {
+        Self {
+            ex,
+            services: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Spawn a service with this shutdown priority, passing it the token that tells it to shut
+    /// down.
+    pub fn spawn<F>(&self, priority: u32, service: impl FnOnce(CancellationToken) -> F)
+    where
+        F: Future<Output = ()> + Send + 'ex,
+    {
+        let token = CancellationToken::new();
+        let task = self.ex.spawn(service(token.clone()));
+        self.lock().push(Service {
+            priority,
+            token,
+            task,
+        });
+    }
+
+    /// Shut down the services in order of their priority, waiting for each of them to return
+    /// before cancelling the next one.
+    ///
+    /// Services that are spawned onto the set while shutting down are shut down as well, once
+    /// the ones before them have returned. If a service panics, the panic is propagated and the
+    /// remaining services are cancelled once the set is dropped, without waiting for them.
+    pub async fn shutdown(&self) {
+        loop {
+            let mut services = mem::take(&mut *self.lock());
+            if services.is_empty() {
+                return;
+            }
+
+            // The sort is stable, so reversing first keeps the later services ahead of the
+            // earlier ones with the same priority.
+            services.reverse();
+            services.sort_by_key(|service| service.priority);
+            for service in services {
+                service.token.cancel();
+                service.task.await;
+            }
+        }
+    }
+
+    /// Get the number of services that haven't been shut down yet.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Check whether the set is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Lock the services, ignoring poisoning since pushing a service can't leave the `Vec` in a
+    /// bad state.
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, Vec<Service>> {
+        self.services.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<'a, 'ex> From<&'a Executor<'ex>> for ServiceSet<'a, 'ex> {
+    #[inline]
+    fn from(ex: &'a Executor<'ex>) -> Self {
+        Self::with_executor(ExecutorRef::Executor(ex))
+    }
+}
+
+impl<'a, 'ex> From<&'a Arc<Executor<'ex>>> for ServiceSet<'a, 'ex> {
+    #[inline]
+    fn from(ex: &'a Arc<Executor<'ex>>) -> Self {
+        Self::with_executor(ExecutorRef::Executor(ex))
+    }
+}
+
+impl<'a, 'ex> From<&'a LocalExecutor<'ex>> for ServiceSet<'a, 'ex> {
+    #[inline]
+    fn from(ex: &'a LocalExecutor<'ex>) -> Self {
+        Self::with_executor(ExecutorRef::Local(ex))
+    }
+}
+
+impl<'a, 'ex> From<&'a Rc<LocalExecutor<'ex>>> for ServiceSet<'a, 'ex> {
+    #[inline]
+    fn from(ex: &'a Rc<LocalExecutor<'ex>>) -> Self {
+        Self::with_executor(ExecutorRef::Local(ex))
+    }
+}
+
+impl<'a, 'ex> From<&'a AnyExecutor<'ex>> for ServiceSet<'a, 'ex> {
+    #[inline]
+    fn from(ex: &'a AnyExecutor<'ex>) -> Self {
+        Self::with_executor(ExecutorRef::Any(ex))
+    }
+}
@@ -0,0 +1,75 @@
+//! Several executors that split the thread pool between them.
+
+use crate::Executor;
+use async_executor::Task;
+use std::future::Future;
+use std::ops::Deref;
+
+/// The executors passed to `main` as a slice when it has the `#[shards]` attribute.
+///
+/// Each worker thread runs one of the shards, and the current thread runs the first one while it
+/// blocks on the main future. Tasks spawned by the other attributes, like `#[prelude]`, go to the
+/// first shard too.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Shards<'a> {
+    /// The executors, at least one.
+    pub(crate) executors: Vec<Executor<'a>>,
+}
+
+impl<'a> Shards<'a> {
+    /// Create this many executors.
+    #[inline]
+    pub(crate) fn new(shards: usize) -> Self {
+        Self {
+            executors: (0..shards.max(1)).map(|_| Executor::new()).collect(),
+        }
+    }
+
+    /// Spawn a task onto the first shard.
+    #[inline]
+    pub fn spawn<T: Send + 'a>(&self, future: impl Future<Output = T> + Send + 'a) -> Task<T> {
+        self.executors[0].spawn(future)
+    }
+
+    /// Run the first shard until the future completes.
+    ///
+    /// Without a thread pool, the other shards are run on the current thread as well.
+    #[inline]
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+        #[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+        {
+            self.executors[0].run(future).await
+        }
+
+        #[cfg(any(feature = "single-thread", target_family = "wasm"))]
+        {
+            let mut future: std::pin::Pin<Box<dyn Future<Output = T> + '_>> = Box::pin(future);
+            for ex in &self.executors {
+                future = Box::pin(ex.run(future));
+            }
+            future.await
+        }
+    }
+
+    /// Wait for a single task of the first shard to be ready and run it.
+    #[inline]
+    pub async fn tick(&self) {
+        self.executors[0].tick().await
+    }
+
+    /// Check whether none of the shards have tasks that still need to be run.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.executors.iter().all(Executor::is_empty)
+    }
+}
+
+impl<'a> Deref for Shards<'a> {
+    type Target = [Executor<'a>];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.executors
+    }
+}
@@ -0,0 +1,70 @@
+//! Running blocking code on the worker threads of an executor.
+
+use crate::main_executor::worker_threads;
+use crate::Executor;
+use async_executor::Task;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Spawn a task that runs a blocking function on one of the worker threads of the executor.
+///
+/// This is meant for short blocking calls, like reading a small file, in programs whose worker
+/// threads are mostly idle. Unlike [`blocking::unblock`], the function runs on a thread that
+/// already exists, without handing it over to the thread pool of the `blocking` crate and waking
+/// up one of its threads.
+///
+/// A worker thread that runs the function can't run other tasks or drive the reactor, so at most
+/// one less than [`worker_threads`] functions run on the worker threads at the same time, which
+/// keeps one of them free for everything else. The count is shared by all executors in the
+/// process. Functions beyond that, and all of them if the executor isn't run by a thread pool,
+/// like with the `single-thread` feature, are run by [`blocking::unblock`] instead. Calls that may
+/// block for a long time, or many at once, should use [`blocking::unblock`] directly.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, spawn_blocking_on, Executor};
+///
+/// #[apply(main!)]
+/// async fn main(ex: &Executor<'_>) {
+///     let len = spawn_blocking_on(ex, || std::fs::read("Cargo.toml").map(|data| data.len()))
+///         .await
+///         .unwrap();
+///     assert!(len > 0);
+/// }
+/// ```
+///
+/// [`blocking::unblock`]: https://docs.rs/blocking/latest/blocking/fn.unblock.html
+pub fn spawn_blocking_on<T: Send + 'static>(
+    ex: &Executor<'_>,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Task<T> {
+    ex.spawn(async move {
+        match Running::enter(worker_threads()) {
+            Some(_running) => f(),
+            None => ::blocking::unblock(f).await,
+        }
+    })
+}
+
+/// The number of functions that block a worker thread right now.
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a function that blocks a worker thread, until it is dropped.
+struct Running;
+
+impl Running {
+    /// Count another function, unless it would block all of this many worker threads.
+    fn enter(workers: usize) -> Option<Self> {
+        RUNNING
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| {
+                (running + 1 < workers).then(|| running + 1)
+            })
+            .ok()
+            .map(|_| Self)
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
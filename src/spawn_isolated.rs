@@ -0,0 +1,51 @@
+//! Running tasks whose panics are returned as errors.
+
+use crate::Executor;
+use async_executor::Task;
+use futures_lite::future::FutureExt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::thread;
+
+/// Spawn a task that returns an error instead of panicking if the future panics.
+///
+/// Tasks spawned onto an [`Executor`] never unwind the worker thread that runs them: the panic
+/// is caught and resumed in the code awaiting the task, so a task that awaits a panicking one,
+/// like `main`, panics in turn. The task spawned here returns the payload of the panic as an
+/// `Err` instead, like [`JoinHandle::join`] does for threads, so that e.g. a plugin host can log
+/// a failed plugin and keep running the others. The panic hook still runs when the panic
+/// happens, so the message is printed as usual.
+///
+/// Catching the panic needs the future to be [`UnwindSafe`], which most futures aren't, since
+/// they hold references or other types that are only unwind safe if they are shared. The future
+/// is wrapped in [`AssertUnwindSafe`] to allow them all, so it is up to the caller to make sure
+/// that the state the future shares with other tasks is still usable after a panic. Most of the
+/// time this means not sharing state behind a `RefCell` or in the middle of an update, since a
+/// panic can leave it half done. A [`Mutex`] that was locked during the panic is poisoned, which
+/// is what tells the other tasks about it. Panics with `panic = "abort"` abort the process as
+/// usual, and can't be caught at all.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, spawn_isolated, Executor};
+///
+/// #[apply(main!)]
+/// async fn main(ex: &Executor<'_>) {
+///     let plugin = spawn_isolated(ex, async { panic!("the plugin crashed") });
+///     assert!(plugin.await.is_err());
+///
+///     let plugin = spawn_isolated(ex, async { 1 + 1 });
+///     assert_eq!(plugin.await.unwrap(), 2);
+/// }
+/// ```
+///
+/// [`JoinHandle::join`]: std::thread::JoinHandle::join
+/// [`UnwindSafe`]: std::panic::UnwindSafe
+/// [`Mutex`]: std::sync::Mutex
+#[inline]
+pub fn spawn_isolated<'a, T: Send + 'a>(
+    ex: &Executor<'a>,
+    future: impl Future<Output = T> + Send + 'a,
+) -> Task<thread::Result<T>> {
+    ex.spawn(AssertUnwindSafe(future).catch_unwind())
+}
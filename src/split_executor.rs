@@ -0,0 +1,87 @@
+//! A pair of executors, one for the current thread and one for the thread pool.
+
+use crate::{Executor, LocalExecutor};
+use std::future::Future;
+
+/// A foreground executor for the current thread and a background executor for the thread pool.
+///
+/// When used as the executor in [`main`], the worker threads of the thread pool only run the
+/// tasks of the [`background`] executor, and the current thread only runs the main future and
+/// the tasks of the [`foreground`] executor. This keeps the foreground tasks responsive no matter
+/// how busy the thread pool is, and since they never leave the current thread, they don't need
+/// to be `Send`. In turn, the background tasks are never run by the current thread, so they can't
+/// delay the main future either. When no threads are spawned, e.g. with the `single-thread`
+/// feature, the current thread runs both executors.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, SplitExecutor};
+/// use std::rc::Rc;
+///
+/// #[apply(main!)]
+/// async fn main(ex: &SplitExecutor<'_>) {
+///     let heavy = ex.background().spawn(async { (0..1000u64).sum::<u64>() });
+///
+///     // Foreground tasks can hold on to values that aren't `Send`.
+///     let local = Rc::new(1);
+///     let light = ex.foreground().spawn(async move { *local + 1 });
+///
+///     assert_eq!(heavy.await + light.await, 499502);
+/// }
+/// ```
+///
+/// [`main`]: crate::main
+/// [`background`]: SplitExecutor::background
+/// [`foreground`]: SplitExecutor::foreground
+#[derive(Debug, Default)]
+pub struct SplitExecutor<'a> {
+    /// The executor run by the current thread.
+    foreground: LocalExecutor<'a>,
+
+    /// The executor run by the thread pool.
+    pub(crate) background: Executor<'a>,
+}
+
+impl<'a> SplitExecutor<'a> {
+    /// Create a new pair of executors.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the executor whose tasks are run by the current thread.
+    #[inline]
+    pub fn foreground(&self) -> &LocalExecutor<'a> {
+        &self.foreground
+    }
+
+    /// Get the executor whose tasks are run by the thread pool.
+    #[inline]
+    pub fn background(&self) -> &Executor<'a> {
+        &self.background
+    }
+
+    /// Run the foreground executor until the future completes.
+    ///
+    /// The background executor is left to the thread pool.
+    #[cfg(not(any(feature = "single-thread", target_family = "wasm")))]
+    #[inline]
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+        self.foreground.run(future).await
+    }
+
+    /// Run both executors until the future completes.
+    ///
+    /// There is no thread pool, so the current thread runs the background executor as well.
+    #[cfg(any(feature = "single-thread", target_family = "wasm"))]
+    #[inline]
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+        self.foreground.run(self.background.run(future)).await
+    }
+
+    /// Wait for a single foreground task to be ready and run it.
+    #[inline]
+    pub async fn tick(&self) {
+        self.foreground.tick().await
+    }
+}
@@ -0,0 +1,212 @@
+//! An executor that is driven one task at a time.
+
+use crate::LocalExecutor;
+use async_executor::Task;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// A thread-local executor whose tasks only run when the body steps through them.
+///
+/// With the `#[step]` attribute, [`test`] doesn't run the executor while it blocks on the body,
+/// so spawned tasks only make progress when the body calls [`step_one`] or [`run_ready`]. This
+/// is like `#[manual_tick]` with a [`Ticker`](crate::Ticker), but the executor also keeps track
+/// of which tasks are ready, so [`run_ready`] can run exactly the tasks that were ready when it
+/// was called. Tasks that they wake up or spawn wait for the next step, which makes every round
+/// of an interleaving visible to the test.
+///
+/// Tasks run in the order in which they became ready, and every step polls a single task once.
+/// Without `#[step]`, the executor runs its tasks like a [`LocalExecutor`] while blocking on the
+/// body.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{test, StepExecutor};
+/// use std::cell::RefCell;
+///
+/// #[apply(test!)]
+/// #[step]
+/// async fn interleaving(ex: &StepExecutor<'_>) {
+///     let log = RefCell::new(Vec::new());
+///     ex.spawn(async {
+///         log.borrow_mut().push("a1");
+///         futures_lite::future::yield_now().await;
+///         log.borrow_mut().push("a2");
+///     })
+///     .detach();
+///     ex.spawn(async { log.borrow_mut().push("b") }).detach();
+///     assert_eq!(ex.ready(), 2);
+///
+///     assert!(ex.step_one());
+///     assert_eq!(*log.borrow(), ["a1"]);
+///
+///     // The first task yielded, so it is ready again, but only after the second one.
+///     assert_eq!(ex.run_ready(), 2);
+///     assert_eq!(*log.borrow(), ["a1", "b", "a2"]);
+///     assert_eq!(ex.run_ready(), 0);
+/// }
+/// ```
+///
+/// [`test`]: crate::test
+/// [`step_one`]: StepExecutor::step_one
+/// [`run_ready`]: StepExecutor::run_ready
+#[derive(Debug, Default)]
+pub struct StepExecutor<'a> {
+    /// The executor that runs the tasks.
+    ex: LocalExecutor<'a>,
+
+    /// The number of tasks that are ready to run.
+    ready: Arc<AtomicUsize>,
+}
+
+impl<'a> StepExecutor<'a> {
+    /// Create a new executor.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawn a task onto the executor.
+    ///
+    /// The task is ready to run right away, but only runs once the executor is stepped.
+    #[inline]
+    pub fn spawn<T: 'a>(&self, future: impl Future<Output = T> + 'a) -> Task<T> {
+        let hook = Arc::new(Hook {
+            ready: self.ready.clone(),
+            scheduled: AtomicBool::new(false),
+            waker: Mutex::new(None),
+        });
+        hook.schedule();
+        self.ex.spawn(Counted {
+            future: Box::pin(future),
+            hook,
+        })
+    }
+
+    /// Get the number of tasks that are ready to run.
+    ///
+    /// A task that is cancelled while it is ready still counts until a step drops it.
+    #[inline]
+    pub fn ready(&self) -> usize {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    /// Poll the task that became ready first, returning `true` if one was ready.
+    #[inline]
+    pub fn step_one(&self) -> bool {
+        self.ex.try_tick()
+    }
+
+    /// Poll each of the tasks that are ready right now once, returning how many were polled.
+    ///
+    /// Unlike [`Ticker::run_until_idle`](crate::Ticker::run_until_idle), tasks that become
+    /// ready while this runs are left for the next step, so this always returns.
+    pub fn run_ready(&self) -> usize {
+        let ready = self.ready();
+        (0..ready).take_while(|_| self.step_one()).count()
+    }
+
+    /// Run the executor until the future completes, like a [`LocalExecutor`].
+    #[inline]
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+        self.ex.run(future).await
+    }
+
+    /// Check whether there are any tasks that still need to be run.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ex.is_empty()
+    }
+}
+
+/// The waker handed to a task, which counts it as ready before waking it.
+struct Hook {
+    /// The number of ready tasks of the executor.
+    ready: Arc<AtomicUsize>,
+
+    /// Whether the task is counted as ready.
+    scheduled: AtomicBool,
+
+    /// The waker of the inner executor, from the last time the task was polled.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Hook {
+    /// Count the task as ready, unless it is already.
+    #[inline]
+    fn schedule(&self) -> bool {
+        let scheduled = !self.scheduled.swap(true, Ordering::SeqCst);
+        if scheduled {
+            self.ready.fetch_add(1, Ordering::SeqCst);
+        }
+        scheduled
+    }
+}
+
+impl Wake for Hook {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if self.schedule() {
+            let waker = self
+                .waker
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// A future spawned onto the executor, which is counted as ready while it is scheduled.
+struct Counted<F> {
+    /// The spawned future.
+    future: Pin<Box<F>>,
+
+    /// The waker handed to the spawned future.
+    hook: Arc<Hook>,
+}
+
+impl<F: Future> Future for Counted<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.hook.scheduled.swap(false, Ordering::SeqCst) {
+            self.hook.ready.fetch_sub(1, Ordering::SeqCst);
+        }
+        *self
+            .hook
+            .waker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(cx.waker().clone());
+
+        let waker = Waker::from(self.hook.clone());
+        self.future.as_mut().poll(&mut Context::from_waker(&waker))
+    }
+}
+
+impl<F> Drop for Counted<F> {
+    fn drop(&mut self) {
+        // A task that is cancelled while it is ready is dropped by a step instead of polled.
+        if self.hook.scheduled.swap(false, Ordering::SeqCst) {
+            self.hook.ready.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// An executor that is stepped through by the body with `#[step]`.
+#[doc(hidden)]
+pub trait Stepped {}
+
+impl Stepped for StepExecutor<'_> {}
+
+/// Check that an executor can be stepped through with `#[step]`.
+#[doc(hidden)]
+#[inline]
+pub fn requires_step<T: Stepped>() {}
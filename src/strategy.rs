@@ -0,0 +1,122 @@
+//! Writing a test once for both the blocking and the `async` API of a library.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Whether a test generated by [`dual_test`] calls the blocking or the `async` API.
+///
+/// The body of a [`dual_test`] is an `async fn` that takes the strategy as its parameter, and
+/// goes through [`call`] wherever the two APIs differ. [`Blocking`] calls the blocking function
+/// right away, while [`NonBlocking`] awaits the future of the `async` one.
+///
+/// ```
+/// use smol_macros::{Blocking, NonBlocking, Strategy};
+///
+/// async fn double<S: Strategy>(strategy: S, n: u32) -> u32 {
+///     strategy.call(|| n * 2, || async move { n * 2 }).await
+/// }
+///
+/// # futures_lite::future::block_on(async {
+/// assert_eq!(double(Blocking, 2).await, 4);
+/// assert_eq!(double(NonBlocking, 2).await, 4);
+/// # });
+/// ```
+///
+/// [`dual_test`]: crate::dual_test
+/// [`call`]: Strategy::call
+pub trait Strategy: Copy {
+    /// Check whether the `async` API is called.
+    fn is_async(self) -> bool;
+
+    /// Call either the blocking function or the `async` one, depending on the strategy.
+    ///
+    /// Only one of the closures is called. The returned future completes right away for the
+    /// blocking function.
+    fn call<F: Future>(
+        self,
+        blocking: impl FnOnce() -> F::Output,
+        non_blocking: impl FnOnce() -> F,
+    ) -> Call<F>;
+}
+
+/// The [`Strategy`] that calls the blocking API.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Blocking;
+
+impl Strategy for Blocking {
+    #[inline]
+    fn is_async(self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn call<F: Future>(
+        self,
+        blocking: impl FnOnce() -> F::Output,
+        _non_blocking: impl FnOnce() -> F,
+    ) -> Call<F> {
+        Call(Inner::Ready(Some(blocking())))
+    }
+}
+
+/// The [`Strategy`] that calls the `async` API.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NonBlocking;
+
+impl Strategy for NonBlocking {
+    #[inline]
+    fn is_async(self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn call<F: Future>(
+        self,
+        _blocking: impl FnOnce() -> F::Output,
+        non_blocking: impl FnOnce() -> F,
+    ) -> Call<F> {
+        Call(Inner::Pending(Box::pin(non_blocking())))
+    }
+}
+
+/// The future returned by [`Strategy::call`].
+#[must_use = "futures do nothing unless awaited"]
+pub struct Call<F: Future>(Inner<F>);
+
+/// The state of a [`Call`].
+enum Inner<F: Future> {
+    /// The blocking function was called, and its output hasn't been taken yet.
+    Ready(Option<F::Output>),
+
+    /// The future of the `async` function.
+    Pending(Pin<Box<F>>),
+}
+
+// The output is never pinned, and the future is pinned on the heap.
+impl<F: Future> Unpin for Call<F> {}
+
+impl<F: Future> Future for Call<F> {
+    type Output = F::Output;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &mut self.get_mut().0 {
+            Inner::Ready(output) => {
+                Poll::Ready(output.take().expect("`Call` polled after completion"))
+            }
+            Inner::Pending(future) => future.as_mut().poll(cx),
+        }
+    }
+}
+
+impl<F: Future> fmt::Debug for Call<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let strategy = match self.0 {
+            Inner::Ready(_) => "Blocking",
+            Inner::Pending(_) => "NonBlocking",
+        };
+        f.debug_tuple("Call").field(&strategy).finish()
+    }
+}
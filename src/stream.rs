@@ -0,0 +1,54 @@
+//! Driving a stream returned by the body of `main` to completion.
+
+use futures_lite::stream::{Stream, StreamExt};
+use std::future::Future;
+
+/// The value returned once a stream has been drained.
+pub trait Drained<Item>: Sized {
+    /// Look at an item of the stream, returning early if it ends the run.
+    fn item(item: Item) -> Result<(), Self>;
+
+    /// The value returned once the stream ended.
+    fn done() -> Self;
+}
+
+impl<T> Drained<T> for () {
+    #[inline]
+    fn item(_item: T) -> Result<(), Self> {
+        Ok(())
+    }
+
+    #[inline]
+    fn done() -> Self {}
+}
+
+impl<T, E, F: From<E>> Drained<Result<T, E>> for Result<(), F> {
+    #[inline]
+    fn item(item: Result<T, E>) -> Result<(), Self> {
+        match item {
+            Ok(_) => Ok(()),
+            Err(err) => Err(Err(err.into())),
+        }
+    }
+
+    #[inline]
+    fn done() -> Self {
+        Ok(())
+    }
+}
+
+/// Wait for the stream built by a future, then run it until it ends.
+pub async fn drain_stream<S, R>(stream: impl Future<Output = S>) -> R
+where
+    S: Stream,
+    R: Drained<S::Item>,
+{
+    let stream = stream.await;
+    futures_lite::pin!(stream);
+    while let Some(item) = stream.next().await {
+        if let Err(output) = R::item(item) {
+            return output;
+        }
+    }
+    R::done()
+}
@@ -0,0 +1,91 @@
+//! Limiting the number of tasks that run at the same time.
+
+use crate::Executor;
+use async_executor::Task;
+use async_lock::Semaphore;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Spawns tasks onto an executor, with at most a given number of them running at the same time.
+///
+/// [`spawn`] waits for one of the running tasks to complete before spawning another one once the
+/// limit is reached, which applies backpressure to the code spawning them. Tasks that are
+/// spawned onto the executor directly don't count towards the limit.
+///
+/// With the `#[max_concurrent_tasks(N)]` attribute, [`main`] creates a limit for the executor
+/// and passes it in as the second parameter.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, Executor, TaskLimit};
+///
+/// #[apply(main!)]
+/// #[max_concurrent_tasks(4)]
+/// async fn main(ex: &Executor<'_>, limit: &TaskLimit<'_, '_>) {
+///     let mut tasks = Vec::new();
+///     for i in 0..16 {
+///         tasks.push(limit.spawn(async move { i * 2 }).await);
+///     }
+///
+///     for task in tasks {
+///         task.await;
+///     }
+/// }
+/// ```
+///
+/// [`spawn`]: TaskLimit::spawn
+/// [`main`]: crate::main
+#[derive(Debug)]
+pub struct TaskLimit<'a, 'ex> {
+    /// The executor to spawn the tasks onto.
+    ex: &'a Executor<'ex>,
+
+    /// A permit for every task that can still be spawned.
+    permits: Arc<Semaphore>,
+}
+
+impl<'a, 'ex> TaskLimit<'a, 'ex> {
+    /// Create a limit of this many running tasks for an executor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the limit is zero, since no task could ever be spawned.
+    #[inline]
+    pub fn new(ex: &'a Executor<'ex>, max_tasks: usize) -> Self {
+        assert!(max_tasks > 0, "the task limit must be at least 1");
+        Self {
+            ex,
+            permits: Arc::new(Semaphore::new(max_tasks)),
+        }
+    }
+
+    /// Wait until fewer tasks than the limit are running, then spawn a task.
+    ///
+    /// The task counts towards the limit until it completes or is cancelled.
+    pub async fn spawn<T: Send + 'ex>(
+        &self,
+        future: impl Future<Output = T> + Send + 'ex,
+    ) -> Task<T> {
+        let permit = self.permits.acquire_arc().await;
+        self.ex.spawn(async move {
+            let _permit = permit;
+            future.await
+        })
+    }
+
+    /// Spawn a task if fewer tasks than the limit are running.
+    ///
+    /// Returns the future back if the limit is reached.
+    pub fn try_spawn<T: Send + 'ex, F: Future<Output = T> + Send + 'ex>(
+        &self,
+        future: F,
+    ) -> Result<Task<T>, F> {
+        match self.permits.try_acquire_arc() {
+            Some(permit) => Ok(self.ex.spawn(async move {
+                let _permit = permit;
+                future.await
+            })),
+            None => Err(future),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Names for tasks, for debugging.
+
+use crate::Executor;
+use async_executor::Task;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::task::{Context, Poll};
+
+/// The names of the named futures that haven't been dropped yet, keyed by a unique id.
+static RUNNING: Mutex<Option<HashMap<usize, &'static str>>> = Mutex::new(None);
+
+thread_local! {
+    /// The name of the future that the current thread is polling.
+    static CURRENT: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Give a future a name, which is reported while it is polled and until it is dropped.
+///
+/// `async-executor` doesn't attach anything to its tasks, so the name is tracked by this wrapper
+/// instead. Any executor can spawn the wrapped future, like `ex.spawn(named("flush", future))`.
+/// While the future is polled, [`current_task_name`] returns its name, and until it is dropped,
+/// its name is included in [`running_tasks`]. This needs the `task-names` feature.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{current_task_name, named, test, Executor};
+///
+/// #[apply(test!)]
+/// async fn names(ex: &Executor<'_>) {
+///     let name = ex.spawn(named("worker", async { current_task_name() }));
+///     assert_eq!(name.await, Some("worker"));
+/// }
+/// ```
+pub fn named<F: Future>(name: &'static str, future: F) -> Named<F> {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    RUNNING
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_or_insert_with(HashMap::new)
+        .insert(id, name);
+
+    Named {
+        name,
+        id,
+        future: Box::pin(future),
+    }
+}
+
+/// Spawn a future onto an executor with a name, see [`named`].
+#[inline]
+pub fn spawn_named<'a, T: Send + 'a>(
+    ex: &Executor<'a>,
+    name: &'static str,
+    future: impl Future<Output = T> + Send + 'a,
+) -> Task<T> {
+    ex.spawn(named(name, future))
+}
+
+/// Get the name of the named future that the current thread is polling.
+///
+/// Returns `None` outside of a future wrapped with [`named`].
+#[inline]
+pub fn current_task_name() -> Option<&'static str> {
+    CURRENT.with(Cell::get)
+}
+
+/// Get the names of all futures wrapped with [`named`] that haven't been dropped yet, sorted.
+///
+/// This includes futures that have completed but are still held on to, e.g. by a task whose
+/// output hasn't been awaited yet.
+pub fn running_tasks() -> Vec<&'static str> {
+    let mut names = RUNNING
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .map_or_else(Vec::new, |running| running.values().copied().collect());
+    names.sort_unstable();
+    names
+}
+
+/// A future with a name, created by [`named`].
+pub struct Named<F> {
+    /// The name of the future.
+    name: &'static str,
+
+    /// The key of the future in the map of running futures.
+    id: usize,
+
+    /// The inner future.
+    future: Pin<Box<F>>,
+}
+
+impl<F> fmt::Debug for Named<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Named").field("name", &self.name).finish()
+    }
+}
+
+impl<F: Future> Future for Named<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        /// Restores the previous name once the future returns or panics.
+        struct Guard(Option<&'static str>);
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                CURRENT.with(|current| current.set(self.0));
+            }
+        }
+
+        let _guard = Guard(CURRENT.with(|current| current.replace(Some(self.name))));
+        self.future.as_mut().poll(cx)
+    }
+}
+
+impl<F> Drop for Named<F> {
+    fn drop(&mut self) {
+        if let Some(running) = RUNNING
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+        {
+            running.remove(&self.id);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! A set of tasks that are awaited or cancelled together.
+
+use crate::{AnyExecutor, Executor, LocalExecutor};
+use async_executor::Task;
+use std::future::Future;
+use std::mem;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A set of tasks spawned onto an executor, which are awaited or cancelled together.
+///
+/// This replaces the pattern of pushing tasks into a `Vec` and awaiting them one by one. The
+/// tasks that are still in the set when it is dropped are cancelled.
+///
+/// A task set can be created from a reference to any of the executors supported by [`main`].
+/// Like with [`AnyExecutor`], the spawned futures need to be `Send` even if the executor is a
+/// [`LocalExecutor`].
+///
+/// With the `#[with_task_set]` attribute, [`main`] creates a task set for the executor and passes
+/// it in as the second parameter.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{main, Executor, TaskSet};
+///
+/// #[apply(main!)]
+/// #[with_task_set]
+/// async fn main(ex: &Executor<'_>, tasks: &TaskSet<usize>) {
+///     for i in 0..16 {
+///         tasks.spawn(async move { i * 2 });
+///     }
+///
+///     let results = tasks.join_all().await;
+///     assert_eq!(results.len(), 16);
+/// }
+/// ```
+///
+/// [`main`]: crate::main
+#[derive(Debug)]
+pub struct TaskSet<'a, 'ex, T> {
+    /// The executor to spawn the tasks onto.
+    ex: ExecutorRef<'a, 'ex>,
+
+    /// The tasks that haven't been awaited yet.
+    tasks: Mutex<Vec<Task<T>>>,
+}
+
+/// A reference to one of the supported executors.
+#[derive(Debug)]
+pub(crate) enum ExecutorRef<'a, 'ex> {
+    /// A thread-safe executor.
+    Executor(&'a Executor<'ex>),
+
+    /// A thread-local executor.
+    Local(&'a LocalExecutor<'ex>),
+
+    /// An executor whose kind is chosen at runtime.
+    Any(&'a AnyExecutor<'ex>),
+}
+
+impl<'ex> ExecutorRef<'_, 'ex> {
+    /// Spawn a task onto the executor.
+    #[inline]
+    pub(crate) fn spawn<T: Send + 'ex>(
+        &self,
+        future: impl Future<Output = T> + Send + 'ex,
+    ) -> Task<T> {
+        match self {
+            ExecutorRef::Executor(ex) => ex.spawn(future),
+            ExecutorRef::Local(ex) => ex.spawn(future),
+            ExecutorRef::Any(ex) => ex.spawn(future),
+        }
+    }
+}
+
+impl<'ex, T: Send + 'ex> TaskSet<'_, 'ex, T> {
+    /// Spawn a task onto the executor and add it to the set.
+    pub fn spawn(&self, future: impl Future<Output = T> + Send + 'ex) {
+        let task = self.ex.spawn(future);
+        self.lock().push(task);
+    }
+
+    /// Wait for all tasks in the set to complete, returning their outputs in the order in which
+    /// they were spawned.
+    ///
+    /// Tasks that are spawned onto the set while waiting are awaited as well.
+    pub async fn join_all(&self) -> Vec<T> {
+        let mut outputs = Vec::new();
+        loop {
+            let tasks = mem::take(&mut *self.lock());
+            if tasks.is_empty() {
+                return outputs;
+            }
+
+            for task in tasks {
+                outputs.push(task.await);
+            }
+        }
+    }
+}
+
+impl<'a, 'ex, T> TaskSet<'a, 'ex, T> {
+    /// Create an empty set for an executor.
+    #[inline]
+    fn with_executor(ex: ExecutorRef<'a, 'ex>) -> Self {
+        Self {
+            ex,
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Get the number of tasks in the set.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Check whether the set is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Lock the tasks, ignoring poisoning since pushing a task can't leave the `Vec` in a bad
+    /// state.
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, Vec<Task<T>>> {
+        self.tasks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<'a, 'ex, T> From<&'a Executor<'ex>> for TaskSet<'a, 'ex, T> {
+    #[inline]
+    fn from(ex: &'a Executor<'ex>) -> Self {
+        Self::with_executor(ExecutorRef::Executor(ex))
+    }
+}
+
+impl<'a, 'ex, T> From<&'a Arc<Executor<'ex>>> for TaskSet<'a, 'ex, T> {
+    #[inline]
+    fn from(ex: &'a Arc<Executor<'ex>>) -> Self {
+        Self::with_executor(ExecutorRef::Executor(ex))
+    }
+}
+
+impl<'a, 'ex, T> From<&'a LocalExecutor<'ex>> for TaskSet<'a, 'ex, T> {
+    #[inline]
+    fn from(ex: &'a LocalExecutor<'ex>) -> Self {
+        Self::with_executor(ExecutorRef::Local(ex))
+    }
+}
+
+impl<'a, 'ex, T> From<&'a Rc<LocalExecutor<'ex>>> for TaskSet<'a, 'ex, T> {
+    #[inline]
+    fn from(ex: &'a Rc<LocalExecutor<'ex>>) -> Self {
+        Self::with_executor(ExecutorRef::Local(ex))
+    }
+}
+
+impl<'a, 'ex, T> From<&'a AnyExecutor<'ex>> for TaskSet<'a, 'ex, T> {
+    #[inline]
+    fn from(ex: &'a AnyExecutor<'ex>) -> Self {
+        Self::with_executor(ExecutorRef::Any(ex))
+    }
+}
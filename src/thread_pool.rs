@@ -0,0 +1,1195 @@
+//! The thread pool that runs the executor.
+
+use crate::lifecycle::Lifecycle;
+use crate::main_executor::{
+    current_executor, enter_runtime, polling_main, with_current_executor, with_worker_thread_ids,
+    with_worker_threads, Config, LifecycleTimings, SetupError, Threads, WorkerThreadIds,
+};
+use crate::priority_executor::{run_lanes, tick_lanes, try_tick_lanes};
+use crate::{Executor, ScheduledExecutor};
+use async_io::block_on;
+use async_io::Timer;
+use event_listener::{Event, Listener};
+use futures_lite::future::{self, FutureExt};
+use std::any::Any;
+use std::cell::RefCell;
+use std::future::Future;
+use std::io;
+use std::ops::{Deref, Range};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once, PoisonError};
+use std::thread::{self, JoinHandle, Scope};
+use std::time::{Duration, Instant};
+
+/// Run a function that takes an `Executor` inside of a thread pool.
+#[inline]
+pub(crate) fn with_thread_pool<T>(
+    ex: &Executor<'_>,
+    config: &Config,
+    f: impl FnOnce() -> T,
+) -> Result<T, SetupError> {
+    if config.current_thread {
+        return Ok(f());
+    }
+    with_pool(&[Lanes::Single(ex)], config, f)
+}
+
+/// Run a function that takes a `PriorityExecutor` inside of a thread pool.
+#[inline]
+pub(crate) fn with_priority_pool<'ex, T>(
+    high: &Executor<'ex>,
+    low: &Executor<'ex>,
+    config: &Config,
+    f: impl FnOnce() -> T,
+) -> Result<T, SetupError> {
+    if config.current_thread {
+        return Ok(f());
+    }
+    with_pool(&[Lanes::Priority(high, low)], config, f)
+}
+
+/// Get the number of worker threads that a `PinnedExecutor` needs an executor for.
+#[inline]
+pub(crate) fn pinned_workers(config: &Config) -> usize {
+    if config.current_thread {
+        0
+    } else {
+        pool_size(config).max(1)
+    }
+}
+
+/// Run a function that takes a `PinnedExecutor` inside of a thread pool, where each worker
+/// thread runs its own executor before the shared one.
+///
+/// Idle threads don't exit, since nothing else runs the executor of their thread.
+#[inline]
+pub(crate) fn with_pinned_pool<'ex, T>(
+    shared: &Executor<'ex>,
+    workers: &[Executor<'ex>],
+    config: &Config,
+    f: impl FnOnce() -> T,
+) -> Result<T, SetupError> {
+    if workers.is_empty() {
+        return Ok(f());
+    }
+
+    let lanes = workers
+        .iter()
+        .map(|worker| Lanes::Priority(worker, shared))
+        .collect::<Vec<_>>();
+    let mut config = config.clone();
+    config.idle_timeout = None;
+    config.lazy_workers = false;
+    config.fallback_local = false;
+    with_pool(&lanes, &config, f)
+}
+
+/// Run a function that takes a `ScheduledExecutor` inside of a thread pool.
+#[inline]
+pub(crate) fn with_scheduled_pool<T>(
+    ex: &ScheduledExecutor<'_>,
+    config: &Config,
+    f: impl FnOnce() -> T,
+) -> Result<T, SetupError> {
+    if config.current_thread {
+        return Ok(f());
+    }
+    with_pool(&[Lanes::Scheduled(ex)], config, f)
+}
+
+/// Run a function that takes `Shards` inside of a thread pool split between the shards.
+///
+/// Idle threads don't exit, since the threads of the other shards can't tell that one of the
+/// shards needs a thread again.
+#[inline]
+pub(crate) fn with_sharded_pool<T>(
+    shards: &[Executor<'_>],
+    config: &Config,
+    f: impl FnOnce() -> T,
+) -> Result<T, SetupError> {
+    let lanes = shards.iter().map(Lanes::Single).collect::<Vec<_>>();
+    let mut config = config.clone();
+    config.idle_timeout = None;
+    config.lazy_workers = false;
+    config.fallback_local = false;
+    with_pool(&lanes, &config, f)
+}
+
+/// Run a function inside of a thread pool running these executors.
+///
+/// The worker threads take turns running each of the executors, and there are at least as many
+/// threads as executors so that each of them runs.
+fn with_pool<T>(
+    lanes: &[Lanes<'_, '_>],
+    config: &Config,
+    f: impl FnOnce() -> T,
+) -> Result<T, SetupError> {
+    let num_threads = pool_size(config).max(lanes.len());
+    let main_worker = config.main_thread_worker && config.cpu_set.is_none();
+    let workers = num_threads + usize::from(main_worker);
+
+    // Hand out the executor on the worker threads too if the current thread does.
+    let current = match lanes {
+        [Lanes::Single(ex)] => current_executor().filter(|current| {
+            std::ptr::eq(
+                Arc::as_ptr(current).cast::<()>(),
+                (*ex as *const Executor<'_>).cast(),
+            )
+        }),
+        _ => None,
+    };
+
+    // Lazy pools start with the threads they keep and grow once tasks pile up.
+    let min_workers = config.min_workers.unwrap_or(1).clamp(1, num_threads.max(1));
+    let initial = if config.lazy_workers {
+        min_workers.max(lanes.len()).min(num_threads)
+    } else {
+        num_threads
+    };
+
+    let pool = Pool {
+        lanes,
+        config,
+        workers,
+        current,
+        thread_ids: WorkerThreadIds::default(),
+        stopper: Arc::new(WaitForStop::new()),
+        restarts: AtomicUsize::new(0),
+        slots: (0..num_threads)
+            .map(|i| AtomicBool::new(i < initial))
+            .collect(),
+        live: AtomicUsize::new(initial),
+        min_workers,
+        panic: Mutex::new(None),
+        spawn_error: Mutex::new(None),
+        starting: AtomicUsize::new(initial),
+        started: Event::new(),
+    };
+
+    // The function is only taken once the threads are up, so that it can still run without them.
+    let mut f = Some(f);
+    let mut startup = None;
+    let mut stopping = None;
+    let result = thread::scope(|scope| {
+        if config.reactor_thread {
+            if let Err(err) = pool.spawn_reactor(scope) {
+                return Err(SetupError::spawn_thread(err));
+            }
+        }
+
+        // Unless all of the threads have to be up before the main function runs, the first
+        // worker thread spawns the others while it runs. A main function that completes right
+        // away then stops the pool before the remaining threads are spawned.
+        let spawning = config.time_lifecycle.map(|_| Instant::now());
+        let spawned = if config.wait_for_workers
+            || config.fallback_local
+            || config.time_lifecycle.is_some()
+        {
+            pool.spawn_initial(scope, 0..initial)
+        } else if initial > 0 {
+            pool.spawn(scope, 0, 1..initial)
+        } else {
+            Ok(())
+        };
+        if let Err(err) = spawned {
+            // Stop the threads that were already spawned before bailing out.
+            pool.stopper.stop();
+            return Err(SetupError::spawn_thread(err));
+        }
+        startup = spawning.map(|start| start.elapsed());
+
+        if config.wait_for_workers {
+            pool.wait_until_started();
+        }
+
+        let f = f.take().expect("the main function is only run once");
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            let _registered = main_worker.then(|| Registered::new(&pool.thread_ids));
+            with_worker_thread_ids(pool.thread_ids.clone(), || {
+                with_worker_threads(workers, || {
+                    if config.stop_on_panic {
+                        stop_on_panic(&pool.stopper, f)
+                    } else {
+                        f()
+                    }
+                })
+            })
+        }));
+
+        if result.is_err() && config.debug_on_panic {
+            let busy = pool.lanes.iter().filter(|lanes| !lanes.is_empty()).count();
+            report_panic(
+                busy,
+                pool.lanes.len(),
+                pool.live.load(Ordering::SeqCst),
+                num_threads,
+            );
+        }
+
+        stopping = config.time_lifecycle.map(|_| Instant::now());
+        pool.stopper.stop();
+
+        // Scoped threads can't be detached, so if they don't stop in time the only way to
+        // keep the process from hanging is to take it down.
+        if let Some(timeout) = config.shutdown_timeout {
+            if !pool.stopper.wait_for_workers(Instant::now() + timeout) {
+                eprintln!("smol-macros: worker threads did not stop within {timeout:?}, aborting");
+                std::process::abort();
+            }
+        }
+
+        Ok(result)
+    });
+
+    // The threads that were spawned already have been stopped and joined by the scope.
+    let mut result = match (result, f) {
+        (Err(_), Some(f)) if config.fallback_local => return Ok(f()),
+        (result, _) => result?,
+    };
+
+    if let (Some(report), Some(startup), Some(stopping)) =
+        (config.time_lifecycle, startup, stopping)
+    {
+        report(LifecycleTimings {
+            threads: initial,
+            startup,
+            shutdown: stopping.elapsed(),
+        });
+    }
+
+    // A worker thread that couldn't be spawned fails the run like it would have before the body
+    // started, unless the main future panicked.
+    if let Some(err) = pool
+        .spawn_error
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner)
+    {
+        if result.is_ok() {
+            return Err(SetupError::spawn_thread(err));
+        }
+    }
+
+    // Don't let a successful main future hide a worker thread that died.
+    if let Some(err) = pool
+        .panic
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner)
+    {
+        if result.is_ok() {
+            result = Err(err);
+        }
+    }
+
+    match result {
+        Ok(value) => Ok(value),
+        Err(err) => std::panic::resume_unwind(err),
+    }
+}
+
+/// The executors run by a thread pool.
+#[derive(Clone, Copy)]
+enum Lanes<'a, 'ex> {
+    /// A single executor.
+    Single(&'a Executor<'ex>),
+
+    /// Two executors, preferring the tasks of the first one.
+    Priority(&'a Executor<'ex>, &'a Executor<'ex>),
+
+    /// An executor running its ready tasks in a chosen order.
+    Scheduled(&'a ScheduledExecutor<'ex>),
+}
+
+impl Lanes<'_, '_> {
+    /// Run the executors until the future completes.
+    async fn run<T>(self, future: impl Future<Output = T>) -> T {
+        match self {
+            Self::Single(ex) => ex.run(future).await,
+            Self::Priority(high, low) => run_lanes(high, low, future).await,
+            Self::Scheduled(ex) => ex.run(future).await,
+        }
+    }
+
+    /// Wait for a single task to be ready and run it.
+    async fn tick(self) {
+        match self {
+            Self::Single(ex) => ex.tick().await,
+            Self::Priority(high, low) => tick_lanes(high, low).await,
+            Self::Scheduled(ex) => ex.tick().await,
+        }
+    }
+
+    /// Attempt to run a single task, returning `true` if one was run.
+    fn try_tick(self) -> bool {
+        match self {
+            Self::Single(ex) => ex.try_tick(),
+            Self::Priority(high, low) => try_tick_lanes(high, low),
+            Self::Scheduled(ex) => ex.try_tick(),
+        }
+    }
+
+    /// Check whether none of the executors has unfinished tasks.
+    fn is_empty(self) -> bool {
+        match self {
+            Self::Single(ex) => ex.is_empty(),
+            Self::Priority(high, low) => high.is_empty() && low.is_empty(),
+            Self::Scheduled(ex) => ex.is_empty(),
+        }
+    }
+}
+
+/// Print the state of a pool whose main future panicked, for `#[debug_on_panic]`.
+///
+/// The executors don't count their tasks, so this only tells whether any are left.
+fn report_panic(busy: usize, executors: usize, live: usize, threads: usize) {
+    let tasks = match (busy, executors) {
+        (0, _) => "no unfinished tasks".to_string(),
+        (_, 1) => "unfinished tasks".to_string(),
+        (busy, executors) => format!("unfinished tasks on {busy} of {executors} executors"),
+    };
+    eprintln!(
+        "smol-macros: the main future panicked with {tasks} and {live} of {threads} worker threads running"
+    );
+}
+
+/// Run the executors on a worker thread until the pool stops.
+async fn run_until_stopped(lanes: Lanes<'_, '_>, stopper: &WaitForStop, config: &Config) {
+    match config.stop_check_interval {
+        Some(interval) => {
+            stopper
+                .wait()
+                .or(async {
+                    loop {
+                        for _ in 0..interval {
+                            lanes.tick().await;
+                        }
+
+                        // Yielding lets `or` check whether the pool stopped.
+                        future::yield_now().await;
+                    }
+                })
+                .await
+        }
+        None => lanes.run(stopper.wait()).await,
+    }
+}
+
+/// Get the number of worker threads to spawn.
+fn pool_size(config: &Config) -> usize {
+    if let Some(cpus) = &config.cpu_set {
+        return cpus.len();
+    }
+
+    // Create a thread for each CPU by default, counting the current thread if it is one of the
+    // workers.
+    let threads = match config.threads {
+        Some(Threads::Count(threads)) => threads,
+        Some(Threads::Fraction { fraction, min }) => {
+            ((num_cpus(config) as f64 * fraction).round() as usize).max(min)
+        }
+        None => num_cpus(config),
+    };
+    if config.main_thread_worker {
+        threads - 1
+    } else {
+        threads
+    }
+}
+
+/// The worker threads running an executor.
+struct Pool<'a, 'ex> {
+    /// The executors run by the worker threads, in turn.
+    lanes: &'a [Lanes<'a, 'ex>],
+
+    /// The configuration of the runtime.
+    config: &'a Config,
+
+    /// The number of threads running the executor, as reported by `worker_threads`.
+    workers: usize,
+
+    /// The handle to the executor, as reported by `current_executor`.
+    current: Option<Arc<Executor<'static>>>,
+
+    /// The ids of the running worker threads, as reported by `worker_thread_ids`.
+    thread_ids: WorkerThreadIds,
+
+    /// Stops the worker threads.
+    stopper: Arc<WaitForStop>,
+
+    /// The number of times worker threads have been restarted after a panic.
+    restarts: AtomicUsize,
+
+    /// Whether a thread is running for each of the worker indices.
+    slots: Vec<AtomicBool>,
+
+    /// The number of occupied slots.
+    live: AtomicUsize,
+
+    /// The number of worker threads that don't exit when idle.
+    min_workers: usize,
+
+    /// The first panic that escaped a worker thread.
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+
+    /// The error of a worker thread that the first one failed to spawn.
+    spawn_error: Mutex<Option<io::Error>>,
+
+    /// The number of worker threads that haven't started running the executor yet.
+    starting: AtomicUsize,
+
+    /// Signalled once all worker threads have started running the executor.
+    started: Event,
+}
+
+impl<'scope, 'ex> Pool<'scope, 'ex> {
+    /// Get the executors run by the worker thread with the given index.
+    #[inline]
+    fn lanes(&self, index: usize) -> Lanes<'scope, 'ex> {
+        self.lanes[index % self.lanes.len()]
+    }
+
+    /// Spawn the worker threads for these slots, which have already been occupied.
+    ///
+    /// Once the pool is stopped, the remaining threads would only start to exit again, so their
+    /// slots are freed instead.
+    fn spawn_initial<'env>(
+        &'scope self,
+        scope: &'scope Scope<'scope, 'env>,
+        indices: Range<usize>,
+    ) -> io::Result<()> {
+        let end = indices.end;
+        for index in indices {
+            if self.stopper.stopped.load(Ordering::SeqCst) {
+                (index..end).for_each(|index| self.release(index));
+                return Ok(());
+            }
+
+            if let Err(err) = self.spawn(scope, index, 0..0) {
+                (index..end).for_each(|index| self.release(index));
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Spawn the worker thread for a slot that has already been occupied.
+    ///
+    /// Before running the executor, the thread spawns the threads for the slots in `then`.
+    fn spawn<'env>(
+        &'scope self,
+        scope: &'scope Scope<'scope, 'env>,
+        index: usize,
+        then: Range<usize>,
+    ) -> io::Result<()> {
+        let worker = self.stopper.worker();
+
+        thread_builder(self.config, || worker_name(self.config, index)).spawn_scoped(
+            scope,
+            move || {
+                let _worker = worker;
+                let _registered = Registered::new(&self.thread_ids);
+                // The remaining threads are spawned before this one is set up, like when they
+                // are spawned by the calling thread. An error is reported once the run is over.
+                if let Err(err) = self.spawn_initial(scope, then) {
+                    let mut error = self
+                        .spawn_error
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner);
+                    error.get_or_insert(err);
+                }
+
+                // A panicking hook is propagated like a panic while running the executor,
+                // rather than by the scope, which would replace a panic of the main future.
+                let started = std::panic::catch_unwind(|| {
+                    let _starting = Starting(self);
+                    if let Some(cpus) = &self.config.cpu_set {
+                        pin_to_cpu(cpus[index]);
+                    }
+                    for init in &self.config.worker_init {
+                        init(index);
+                    }
+                });
+
+                let result = started.and_then(|()| {
+                    std::panic::catch_unwind(AssertUnwindSafe(|| {
+                        with_current_executor(self.current.clone(), || {
+                            with_worker_thread_ids(self.thread_ids.clone(), || {
+                                with_worker_threads(self.workers, || {
+                                    run_worker(
+                                        || self.run(scope, index),
+                                        self.config,
+                                        &self.stopper,
+                                        &self.restarts,
+                                    )
+                                })
+                            })
+                        })
+                    }))
+                });
+                if let Err(err) = result {
+                    let mut panic = self.panic.lock().unwrap_or_else(PoisonError::into_inner);
+                    panic.get_or_insert(err);
+                }
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// Block until all of the worker threads that were spawned first are about to run the
+    /// executor, or failed to start.
+    fn wait_until_started(&self) {
+        loop {
+            if self.starting.load(Ordering::Acquire) == 0 {
+                return;
+            }
+
+            let listener = self.started.listen();
+
+            if self.starting.load(Ordering::Acquire) == 0 {
+                return;
+            }
+
+            listener.wait();
+        }
+    }
+
+    /// Spawn a thread that only drives the `async-io` reactor until the pool stops.
+    fn spawn_reactor<'env>(&'scope self, scope: &'scope Scope<'scope, 'env>) -> io::Result<()> {
+        let worker = self.stopper.worker();
+
+        thread_builder(self.config, || "smol-macros-reactor".into()).spawn_scoped(
+            scope,
+            move || {
+                let _worker = worker;
+                enter_runtime(|| block_on(self.stopper.wait()));
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// Run the executor on a worker thread until the pool stops or the thread exits.
+    fn run<'env>(&'scope self, scope: &'scope Scope<'scope, 'env>, index: usize) {
+        let config = self.config;
+        if config.idle_timeout.is_some() || config.lazy_workers {
+            config.block_on(self.stopper.wait().or(self.run_until_idle(
+                scope,
+                index,
+                config.idle_timeout,
+            )))
+        } else {
+            config.block_on(run_until_stopped(self.lanes(index), &self.stopper, config))
+        }
+    }
+
+    /// Run tasks until the thread has been idle for the timeout and is allowed to exit.
+    ///
+    /// Without a timeout, the thread only runs tasks and grows the pool, and never exits.
+    async fn run_until_idle<'env>(
+        &'scope self,
+        scope: &'scope Scope<'scope, 'env>,
+        index: usize,
+        timeout: Option<Duration>,
+    ) {
+        let ex = self.lanes(index);
+        let mut last_task = Instant::now();
+        let mut timer = timeout.map_or_else(Timer::never, |timeout| Timer::at(last_task + timeout));
+        let mut unchecked = 0;
+
+        loop {
+            let ran = async {
+                ex.tick().await;
+                true
+            };
+            let idle = async {
+                (&mut timer).await;
+                false
+            };
+
+            if ran.or(idle).await {
+                // If there are more tasks waiting right away, the load is growing.
+                last_task = Instant::now();
+                if self.live.load(Ordering::SeqCst) < self.slots.len() && ex.try_tick() {
+                    self.grow(scope);
+                }
+
+                // Yielding lets the caller check whether the pool stopped.
+                unchecked += 1;
+                if Some(unchecked) == self.config.stop_check_interval {
+                    unchecked = 0;
+                    future::yield_now().await;
+                }
+                continue;
+            }
+
+            // The timer only fires with a timeout.
+            let timeout = match timeout {
+                Some(timeout) => timeout,
+                None => continue,
+            };
+
+            // The timer isn't reset for every task, so check whether one ran in the meantime.
+            if last_task.elapsed() >= timeout {
+                if self.shrink(index) {
+                    return;
+                }
+
+                // There are only as many threads as need to be kept, so start over.
+                last_task = Instant::now();
+            }
+            timer.set_at(last_task + timeout);
+        }
+    }
+
+    /// Spawn a thread for one of the slots that are free, if any.
+    fn grow<'env>(&'scope self, scope: &'scope Scope<'scope, 'env>) {
+        if self.stopper.stopped.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let reserved = self
+            .live
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |live| {
+                (live < self.slots.len()).then(|| live + 1)
+            })
+            .is_ok();
+        if !reserved {
+            return;
+        }
+
+        // A thread that is exiting may not have freed its slot yet, in which case the next
+        // task that finds more tasks waiting tries again.
+        let free = self.slots.iter().position(|slot| {
+            slot.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        });
+        let index = match free {
+            Some(index) => index,
+            None => {
+                self.live.fetch_sub(1, Ordering::SeqCst);
+                return;
+            }
+        };
+
+        // If the thread can't be spawned, the threads that are still running keep going.
+        if self.spawn(scope, index, 0..0).is_err() {
+            self.slots[index].store(false, Ordering::SeqCst);
+            self.live.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Free the slot of a thread that was never spawned.
+    fn release(&self, index: usize) {
+        self.slots[index].store(false, Ordering::SeqCst);
+        self.live.fetch_sub(1, Ordering::SeqCst);
+        drop(Starting(self));
+    }
+
+    /// Free the slot of an idle thread, unless that would leave too few threads running.
+    fn shrink(&self, index: usize) -> bool {
+        let shrunk = self
+            .live
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |live| {
+                (live > self.min_workers).then(|| live - 1)
+            })
+            .is_ok();
+        if shrunk {
+            self.slots[index].store(false, Ordering::SeqCst);
+        }
+        shrunk
+    }
+}
+
+/// Lists the current thread among the worker threads of a pool until dropped.
+struct Registered<'a>(&'a WorkerThreadIds);
+
+impl<'a> Registered<'a> {
+    fn new(ids: &'a WorkerThreadIds) -> Self {
+        ids.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(thread::current().id());
+        Self(ids)
+    }
+}
+
+impl Drop for Registered<'_> {
+    fn drop(&mut self) {
+        let id = thread::current().id();
+        let mut ids = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(index) = ids.iter().position(|&other| other == id) {
+            ids.remove(index);
+        }
+    }
+}
+
+/// Records that a worker thread is about to run the executor once dropped, even if it panicked
+/// while starting.
+struct Starting<'a, 'scope, 'ex>(&'a Pool<'scope, 'ex>);
+
+impl Drop for Starting<'_, '_, '_> {
+    fn drop(&mut self) {
+        // Threads that are spawned later, once the pool grows again, aren't waited for.
+        let starting = self
+            .0
+            .starting
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if starting == Ok(1) {
+            self.0.started.notify_additional(usize::MAX);
+        }
+    }
+}
+
+thread_local! {
+    /// Stops the thread pool whose main future is run by the current thread.
+    static CURRENT_POOL: RefCell<Option<Arc<WaitForStop>>> = const { RefCell::new(None) };
+}
+
+/// Run a function, stopping the thread pool as soon as the main future panics.
+///
+/// A panic hook is installed the first time this is called, and never removed. It stops the pool
+/// before the panic unwinds, so that the worker threads wind down while the main future drops.
+fn stop_on_panic<T>(stopper: &Arc<WaitForStop>, f: impl FnOnce() -> T) -> T {
+    /// Restores the previous pool once the function returns or panics.
+    struct Guard(Option<Arc<WaitForStop>>);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            CURRENT_POOL.with(|pool| *pool.borrow_mut() = self.0.take());
+        }
+    }
+
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let prev_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if polling_main() {
+                CURRENT_POOL.with(|pool| {
+                    if let Some(stopper) = &*pool.borrow() {
+                        stopper.stop();
+                    }
+                });
+            }
+            prev_hook(info);
+        }));
+    });
+
+    let _guard = Guard(CURRENT_POOL.with(|pool| pool.replace(Some(stopper.clone()))));
+    f()
+}
+
+/// Run the loop of a worker thread, restarting it after a panic if that is configured.
+fn run_worker(
+    mut run: impl FnMut(),
+    config: &Config,
+    stopper: &WaitForStop,
+    restarts: &AtomicUsize,
+) {
+    let max_restarts = match config.max_worker_restarts {
+        Some(max_restarts) => max_restarts,
+        None => return run(),
+    };
+
+    loop {
+        let err = match std::panic::catch_unwind(AssertUnwindSafe(&mut run)) {
+            Ok(()) => return,
+            Err(err) => err,
+        };
+
+        // Once the pool is stopping or out of restarts, the panic is propagated as usual.
+        let restarted = restarts.fetch_add(1, Ordering::SeqCst);
+        if stopper.stopped.load(Ordering::SeqCst) || restarted >= max_restarts {
+            std::panic::resume_unwind(err);
+        }
+    }
+}
+
+/// Worker threads that aren't scoped, running an executor that they share ownership of.
+#[derive(Debug)]
+pub(crate) struct DetachedPool {
+    /// Stops the worker threads.
+    stopper: Arc<WaitForStop>,
+
+    /// The worker threads.
+    threads: Vec<JoinHandle<()>>,
+
+    /// How long to wait for the worker threads to stop.
+    shutdown_timeout: Option<Duration>,
+
+    /// Reports the lifecycle of the worker threads, if anyone is interested.
+    lifecycle: Option<Arc<Lifecycle>>,
+}
+
+impl DetachedPool {
+    /// Spawn the worker threads.
+    pub(crate) fn spawn(
+        ex: &Arc<Executor<'static>>,
+        config: &Config,
+        lifecycle: Option<Arc<Lifecycle>>,
+    ) -> Result<Self, SetupError> {
+        let num_threads = pool_size(config);
+        let config = Arc::new(config.clone());
+        let restarts = Arc::new(AtomicUsize::new(0));
+        let thread_ids = WorkerThreadIds::default();
+        let mut pool = Self {
+            stopper: Arc::new(WaitForStop::new()),
+            threads: Vec::with_capacity(num_threads),
+            shutdown_timeout: config.shutdown_timeout,
+            lifecycle,
+        };
+
+        for index in 0..num_threads {
+            let worker = pool.stopper.owned_worker();
+            let ex = ex.clone();
+            let config = config.clone();
+            let restarts = restarts.clone();
+            let thread_ids = thread_ids.clone();
+            let workers =
+                num_threads + usize::from(config.main_thread_worker && config.cpu_set.is_none());
+            let lifecycle = pool.lifecycle.clone();
+            if let Some(lifecycle) = &lifecycle {
+                lifecycle.worker_spawned();
+            }
+
+            let thread = thread_builder(&config, || worker_name(&config, index)).spawn(move || {
+                /// Reports that the thread exited once it returns or panics.
+                struct Exited(Option<Arc<Lifecycle>>);
+
+                impl Drop for Exited {
+                    fn drop(&mut self) {
+                        if let Some(lifecycle) = &self.0 {
+                            lifecycle.worker_exited();
+                        }
+                    }
+                }
+
+                let exited = Exited(lifecycle);
+                let _registered = Registered::new(&thread_ids);
+                let stopper = &*worker.0;
+                if let Some(cpus) = &config.cpu_set {
+                    pin_to_cpu(cpus[index]);
+                }
+                for init in &config.worker_init {
+                    init(index);
+                }
+                if let Some(lifecycle) = &exited.0 {
+                    lifecycle.worker_online(index);
+                }
+
+                with_current_executor(Some(ex.clone()), || {
+                    with_worker_thread_ids(thread_ids.clone(), || {
+                        with_worker_threads(workers, || {
+                            run_worker(
+                                || {
+                                    enter_runtime(|| {
+                                        config.block_on(run_until_stopped(
+                                            Lanes::Single(&ex),
+                                            stopper,
+                                            &config,
+                                        ))
+                                    })
+                                },
+                                &config,
+                                stopper,
+                                &restarts,
+                            )
+                        })
+                    })
+                })
+            });
+
+            match thread {
+                Ok(thread) => pool.threads.push(thread),
+                // Dropping the pool stops the threads that were already spawned.
+                Err(err) => {
+                    if let Some(lifecycle) = &pool.lifecycle {
+                        lifecycle.worker_exited();
+                    }
+                    return Err(SetupError::spawn_thread(err));
+                }
+            }
+        }
+
+        if let Some(lifecycle) = &pool.lifecycle {
+            lifecycle.all_spawned();
+        }
+        Ok(pool)
+    }
+
+    /// Tell the worker threads to stop once they finish the task they are running.
+    pub(crate) fn stop(&self) {
+        self.stopper.stop();
+        if let Some(lifecycle) = &self.lifecycle {
+            lifecycle.stopping();
+        }
+    }
+
+    /// Stop the worker threads and wait for them to exit.
+    pub(crate) fn join(mut self) {
+        self.stop();
+
+        // The threads own everything they use, so they can be left behind if they don't stop.
+        if let Some(timeout) = self.shutdown_timeout {
+            if !self.stopper.wait_for_workers(Instant::now() + timeout) {
+                return;
+            }
+        }
+
+        let mut panic = None;
+        for thread in self.threads.drain(..) {
+            if let Err(err) = thread.join() {
+                panic.get_or_insert(err);
+            }
+        }
+
+        if let Some(err) = panic {
+            std::panic::resume_unwind(err);
+        }
+    }
+}
+
+impl Drop for DetachedPool {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Run a function that takes an `Executor` on the process-wide thread pool.
+///
+/// Rather than spawning threads, this spawns one task per shared thread that runs the
+/// executor until the function completes. Tasks spawned onto the executor are dropped along
+/// with it, so only the threads are shared.
+pub(crate) fn with_shared_pool<T>(
+    ex: &Arc<Executor<'static>>,
+    config: &Config,
+    f: impl FnOnce() -> T,
+) -> T {
+    let stopper = Arc::new(WaitForStop::new());
+
+    let workers = shared_pool();
+    let runners = (0..workers).map(|_| {
+        let ex = ex.clone();
+        let stopper = stopper.clone();
+        SHARED_POOL.spawn(async move {
+            // The shared threads run other executors too, so the handle is only set while
+            // this one is polled.
+            let run = ex.run(stopper.wait());
+            futures_lite::pin!(run);
+            future::poll_fn(|cx| with_current_executor(Some(ex.clone()), || run.as_mut().poll(cx)))
+                .await
+        })
+    });
+    let runners = runners.collect::<Vec<_>>();
+
+    let mut result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        with_current_executor(Some(ex.clone()), || with_worker_threads(workers, f))
+    }));
+
+    if result.is_err() && config.debug_on_panic {
+        report_panic(usize::from(!ex.is_empty()), 1, workers, workers);
+    }
+
+    stopper.stop();
+
+    // Unlike scoped threads, the runners can just be cancelled if they take too long.
+    let stopped = async {
+        for runner in runners {
+            if let Err(err) = AssertUnwindSafe(runner).catch_unwind().await {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+    };
+    match config.shutdown_timeout {
+        Some(timeout) => block_on(stopped.or(async {
+            async_io::Timer::after(timeout).await;
+        })),
+        None => block_on(stopped),
+    }
+
+    match result {
+        Ok(value) => value,
+        Err(err) => std::panic::resume_unwind(err),
+    }
+}
+
+/// The executor driven by the process-wide thread pool.
+static SHARED_POOL: Executor<'static> = Executor::new();
+
+/// Start the process-wide thread pool if it isn't running yet, returning its size.
+fn shared_pool() -> usize {
+    static START: Once = Once::new();
+    static NUM_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+    START.call_once(|| {
+        let num_threads = num_cpus(&Config::new());
+        for i in 0..num_threads {
+            thread::Builder::new()
+                .name(format!("smol-macros-shared-{i}"))
+                .spawn(move || {
+                    with_worker_threads(num_threads, || {
+                        enter_runtime(|| block_on(SHARED_POOL.run(future::pending::<()>())))
+                    })
+                })
+                .expect("failed to spawn thread");
+        }
+
+        NUM_THREADS.store(num_threads, Ordering::Release);
+    });
+
+    NUM_THREADS.load(Ordering::Acquire)
+}
+
+/// Create the builder for a thread of the pool, which is named unless names are disabled.
+fn thread_builder(config: &Config, name: impl FnOnce() -> String) -> thread::Builder {
+    let builder = thread::Builder::new();
+    if config.no_thread_names {
+        builder
+    } else {
+        builder.name(name())
+    }
+}
+
+/// Get the name of the worker thread with the given index.
+fn worker_name(config: &Config, index: usize) -> String {
+    match config.worker_roles.split_last() {
+        Some((last, roles)) => match roles.get(index) {
+            Some(role) => role.clone(),
+            None => format!("{last}-{}", index - roles.len()),
+        },
+        None => format!("smol-macros-{index}"),
+    }
+}
+
+/// Get the number of CPUs available to the process.
+///
+/// If the number can't be determined, the fallback from the configuration is used, or 1.
+fn num_cpus(config: &Config) -> usize {
+    let fallback = config.parallelism_fallback.unwrap_or(1);
+    let num_cpus = thread::available_parallelism().map_or(fallback, |num| num.get());
+
+    // `available_parallelism` accounts for the cgroup's CPU set, but not for its quota.
+    #[cfg(all(feature = "cgroup-quota", target_os = "linux"))]
+    let num_cpus = crate::cgroup::cpu_quota().map_or(num_cpus, |quota| quota.min(num_cpus));
+
+    num_cpus
+}
+
+/// Pin the current thread to a CPU.
+///
+/// If the CPU doesn't exist or the thread can't be pinned, it is left unpinned.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn pin_to_cpu(cpu: usize) {
+    use rustix::thread::{sched_setaffinity, CpuSet};
+
+    if cpu < CpuSet::MAX_CPU {
+        let mut set = CpuSet::new();
+        set.set(cpu);
+        sched_setaffinity(None, &set).ok();
+    }
+}
+
+/// Pin the current thread to a CPU.
+///
+/// Thread affinity isn't supported on this platform, so the thread is left unpinned.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn pin_to_cpu(_cpu: usize) {}
+
+/// Wait for the executor to stop.
+#[derive(Debug)]
+struct WaitForStop {
+    /// Whether or not we need to stop.
+    stopped: AtomicBool,
+
+    /// Wait for the stop.
+    events: Event,
+
+    /// The number of worker threads that are still running.
+    workers: AtomicUsize,
+
+    /// Signalled when a worker thread exits.
+    exited: Event,
+}
+
+impl WaitForStop {
+    /// Create a new wait for stop.
+    #[inline]
+    fn new() -> Self {
+        Self {
+            stopped: AtomicBool::new(false),
+            events: Event::new(),
+            workers: AtomicUsize::new(0),
+            exited: Event::new(),
+        }
+    }
+
+    /// Wait for the event to stop.
+    #[inline]
+    async fn wait(&self) {
+        loop {
+            if self.stopped.load(Ordering::Relaxed) {
+                return;
+            }
+
+            event_listener::listener!(&self.events => listener);
+
+            if self.stopped.load(Ordering::Acquire) {
+                return;
+            }
+
+            listener.await;
+        }
+    }
+
+    /// Stop the waiter.
+    #[inline]
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.events.notify_additional(usize::MAX);
+    }
+
+    /// Register a worker thread, which is unregistered once the guard is dropped.
+    #[inline]
+    fn worker(&self) -> WorkerGuard<&Self> {
+        self.workers.fetch_add(1, Ordering::SeqCst);
+        WorkerGuard(self)
+    }
+
+    /// Register a worker thread with a guard that can be moved onto a thread that isn't scoped.
+    #[inline]
+    fn owned_worker(self: &Arc<Self>) -> WorkerGuard<Arc<Self>> {
+        self.workers.fetch_add(1, Ordering::SeqCst);
+        WorkerGuard(self.clone())
+    }
+
+    /// Block until all worker threads have exited, returning `false` if the deadline passes.
+    fn wait_for_workers(&self, deadline: Instant) -> bool {
+        loop {
+            if self.workers.load(Ordering::Acquire) == 0 {
+                return true;
+            }
+
+            let listener = self.exited.listen();
+
+            if self.workers.load(Ordering::Acquire) == 0 {
+                return true;
+            }
+
+            if listener.wait_deadline(deadline).is_none() {
+                return false;
+            }
+        }
+    }
+}
+
+/// Marks a worker thread as running until dropped, even if the worker panics.
+struct WorkerGuard<S: Deref<Target = WaitForStop>>(S);
+
+impl<S: Deref<Target = WaitForStop>> Drop for WorkerGuard<S> {
+    #[inline]
+    fn drop(&mut self) {
+        self.0.workers.fetch_sub(1, Ordering::SeqCst);
+        self.0.exited.notify_additional(usize::MAX);
+    }
+}
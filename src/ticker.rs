@@ -0,0 +1,74 @@
+//! Driving an executor by hand, one task at a time.
+
+use crate::LocalExecutor;
+use std::rc::Rc;
+
+/// A handle for running the tasks of a [`LocalExecutor`] one at a time.
+///
+/// With the `#[manual_tick]` attribute, [`test`] doesn't run the executor while it blocks on
+/// the body, so spawned tasks only make progress when the body runs them through a ticker. This
+/// makes the order in which tasks run reproducible, and lets the body check the state of the
+/// program in between. The ticker is passed in as the second parameter, and the executor has to
+/// be a [`LocalExecutor`], since worker threads would run the tasks on their own.
+///
+/// ```
+/// use macro_rules_attribute::apply;
+/// use smol_macros::{test, LocalExecutor, Ticker};
+/// use std::cell::RefCell;
+///
+/// #[apply(test!)]
+/// #[manual_tick]
+/// async fn ordering(ex: &LocalExecutor<'_>, ticker: &Ticker<'_, '_>) {
+///     let log = RefCell::new(Vec::new());
+///     ex.spawn(async { log.borrow_mut().push("a") }).detach();
+///     ex.spawn(async { log.borrow_mut().push("b") }).detach();
+///     assert!(log.borrow().is_empty());
+///
+///     assert!(ticker.tick());
+///     assert_eq!(*log.borrow(), ["a"]);
+///
+///     assert_eq!(ticker.run_until_idle(), 1);
+///     assert_eq!(*log.borrow(), ["a", "b"]);
+/// }
+/// ```
+///
+/// [`test`]: crate::test
+#[derive(Debug)]
+pub struct Ticker<'a, 'ex> {
+    /// The executor whose tasks are run.
+    ex: &'a LocalExecutor<'ex>,
+}
+
+impl Ticker<'_, '_> {
+    /// Run a single task if one is ready, returning `true` if one was run.
+    #[inline]
+    pub fn tick(&self) -> bool {
+        self.ex.try_tick()
+    }
+
+    /// Run tasks until none of them are ready, returning how many were run.
+    ///
+    /// Tasks that are woken up while this runs are run as well, so this doesn't return if a task
+    /// keeps waking itself up, like one that yields in a loop.
+    pub fn run_until_idle(&self) -> usize {
+        let mut ticks = 0;
+        while self.tick() {
+            ticks += 1;
+        }
+        ticks
+    }
+}
+
+impl<'a, 'ex> From<&'a LocalExecutor<'ex>> for Ticker<'a, 'ex> {
+    #[inline]
+    fn from(ex: &'a LocalExecutor<'ex>) -> Self {
+        Self { ex }
+    }
+}
+
+impl<'a, 'ex> From<&'a Rc<LocalExecutor<'ex>>> for Ticker<'a, 'ex> {
+    #[inline]
+    fn from(ex: &'a Rc<LocalExecutor<'ex>>) -> Self {
+        Self { ex }
+    }
+}
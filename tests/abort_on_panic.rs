@@ -0,0 +1,53 @@
+//! Testing `#[abort_on_panic]`.
+//!
+//! The panic hook is process-wide, so these tests live in their own binary to avoid aborting
+//! unrelated tests that panic on purpose.
+
+use macro_rules_attribute::apply;
+use smol_macros::{main, Executor};
+
+use std::env;
+use std::panic;
+use std::process::Command;
+
+/// Set when this binary is run by `aborts_on_task_panic`.
+const CHILD_VAR: &str = "SMOL_MACROS_ABORT_CHILD";
+
+#[apply(main!)]
+#[abort_on_panic]
+async fn panic_in_task(ex: &Executor<'_>) {
+    ex.spawn(async { panic!("task panicked") }).await;
+}
+
+#[apply(main!)]
+#[abort_on_panic]
+async fn no_panic(ex: &Executor<'_>) {
+    ex.spawn(async {}).await;
+}
+
+#[test]
+fn abort_child() {
+    if env::var_os(CHILD_VAR).is_some() {
+        panic_in_task();
+    }
+}
+
+#[test]
+fn aborts_on_task_panic() {
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "abort_child", "--nocapture"])
+        .env(CHILD_VAR, "1")
+        .output()
+        .unwrap();
+
+    // Unwinding would make the test fail normally instead of killing the process.
+    assert!(!output.status.success());
+    assert_eq!(output.status.code(), None);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("task panicked"));
+}
+
+#[test]
+fn restores_panic_hook() {
+    no_panic();
+    assert!(panic::catch_unwind(|| panic!("unwinds")).is_err());
+}
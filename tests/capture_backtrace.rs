@@ -0,0 +1,60 @@
+//! Testing `#[capture_backtrace]`.
+//!
+//! The panic hook is process-wide, so these tests live in their own binary to avoid changing how
+//! unrelated tests report their panics.
+
+use macro_rules_attribute::apply;
+use smol_macros::{main, Executor};
+
+use std::env;
+use std::panic;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set when this binary is run by `reports_backtrace`.
+const CHILD_VAR: &str = "SMOL_MACROS_BACKTRACE_CHILD";
+
+#[apply(main!)]
+#[capture_backtrace]
+async fn panic_in_task(ex: &Executor<'_>) {
+    ex.spawn(async { panic!("task panicked") }).await;
+}
+
+#[apply(main!)]
+#[capture_backtrace]
+async fn no_panic(ex: &Executor<'_>) {
+    ex.spawn(async {}).await;
+}
+
+#[test]
+fn backtrace_child() {
+    if env::var_os(CHILD_VAR).is_some() {
+        panic_in_task();
+    }
+}
+
+#[test]
+fn reports_backtrace() {
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "backtrace_child", "--nocapture"])
+        .env(CHILD_VAR, "1")
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("task panicked"));
+    assert!(stderr.contains("stack backtrace:"));
+}
+
+#[test]
+fn restores_panic_hook() {
+    static CALLED: AtomicBool = AtomicBool::new(false);
+
+    panic::set_hook(Box::new(|_| CALLED.store(true, Ordering::SeqCst)));
+    no_panic();
+    assert!(panic::catch_unwind(|| panic!("unwinds")).is_err());
+    drop(panic::take_hook());
+    assert!(CALLED.load(Ordering::SeqCst));
+}
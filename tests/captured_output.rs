@@ -0,0 +1,62 @@
+//! Testing that the output of tasks is captured along with the test that spawned them.
+//!
+//! The output is only shown for failing tests, so these tests run this binary again to look at
+//! the output of a test that fails on purpose.
+
+#![cfg(not(feature = "single-thread"))]
+
+use macro_rules_attribute::apply;
+use smol_macros::{test, Executor};
+
+use std::env;
+use std::process::Command;
+
+/// Set when this binary is run by `shows_task_output`.
+const CHILD_VAR: &str = "SMOL_MACROS_OUTPUT_CHILD";
+
+/// Print from a task that runs on one of the worker threads.
+async fn print_on_worker(ex: &Executor<'_>, message: &'static str) {
+    // Block this thread so that the task has to run on a worker thread.
+    let (tx, rx) = std::sync::mpsc::channel();
+    ex.spawn(async move {
+        println!("{message}");
+        tx.send(()).unwrap();
+    })
+    .detach();
+    rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+}
+
+#[apply(test!)]
+async fn output_child_1(ex: &Executor<'_>) {
+    if env::var_os(CHILD_VAR).is_some() {
+        print_on_worker(ex, "printed by the first test").await;
+    }
+}
+
+#[apply(test!)]
+async fn output_child_2(ex: &Executor<'_>) {
+    if env::var_os(CHILD_VAR).is_some() {
+        print_on_worker(ex, "printed by the second test").await;
+        panic!("the second test failed");
+    }
+}
+
+#[test]
+fn shows_task_output() {
+    // The first test runs first, so threads that outlive it would keep printing into its capture.
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["output_child", "--test-threads", "1"])
+        .env(CHILD_VAR, "1")
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let failure = stdout
+        .split("---- output_child_2 stdout ----")
+        .nth(1)
+        .expect("the second test didn't fail");
+    assert!(failure.contains("printed by the second test"));
+    assert!(!stdout.contains("printed by the first test"));
+}
@@ -0,0 +1,29 @@
+//! Testing the table-driven test macro.
+
+use smol_macros::cases;
+
+cases! {
+    #[cases((1, 2, 3), (2, 2, 4), (0, 0, 0),)]
+    async fn add(a: usize, b: usize, expected: usize) {
+        assert_eq!(a + b, expected);
+    }
+}
+
+cases! {
+    #[cases((1), (3))]
+    #[should_panic]
+    async fn single_field(n: u32) {
+        assert_eq!(n % 2, 0);
+    }
+}
+
+cases! {
+    #[cases((1, 1), (2, 4))]
+    async fn returns_result(n: u32, square: u32) -> Result<(), String> {
+        if n * n == square {
+            Ok(())
+        } else {
+            Err(format!("{n}^2 != {square}"))
+        }
+    }
+}
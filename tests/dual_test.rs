@@ -0,0 +1,52 @@
+//! Testing the macro that generates blocking and `async` tests from the same body.
+
+use smol_macros::{dual_test, Strategy};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static BLOCKING_CALLS: AtomicUsize = AtomicUsize::new(0);
+static ASYNC_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn load(key: &str) -> usize {
+    BLOCKING_CALLS.fetch_add(1, Ordering::SeqCst);
+    key.len()
+}
+
+async fn load_async(key: &str) -> usize {
+    ASYNC_CALLS.fetch_add(1, Ordering::SeqCst);
+    futures_lite::future::yield_now().await;
+    key.len()
+}
+
+dual_test! {
+    async fn calls_one_api(strategy: impl Strategy) {
+        let (blocking, non_blocking) = (
+            BLOCKING_CALLS.load(Ordering::SeqCst),
+            ASYNC_CALLS.load(Ordering::SeqCst),
+        );
+        assert_eq!(strategy.call(|| load("abc"), || load_async("abc")).await, 3);
+
+        // The other test may run at the same time, so only the counter of this API is checked.
+        if strategy.is_async() {
+            assert!(ASYNC_CALLS.load(Ordering::SeqCst) > non_blocking);
+        } else {
+            assert!(BLOCKING_CALLS.load(Ordering::SeqCst) > blocking);
+        }
+    }
+}
+
+dual_test! {
+    #[should_panic]
+    async fn panics(strategy: impl Strategy) {
+        let n = strategy.call(|| load("a"), || load_async("a")).await;
+        assert_eq!(n, 2);
+    }
+}
+
+dual_test! {
+    async fn returns_result(strategy: impl Strategy) -> Result<(), String> {
+        match strategy.call(|| load("ok"), || load_async("ok")).await {
+            2 => Ok(()),
+            n => Err(format!("unexpected length {n}")),
+        }
+    }
+}
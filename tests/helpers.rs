@@ -0,0 +1,936 @@
+//! Testing the helpers used inside of `main` bodies.
+
+use futures_lite::future;
+use macro_rules_attribute::apply;
+use smol_macros::main_executor::Config;
+use smol_macros::{
+    spawn_workers, test, Executor, InstrumentedExecutor, LocalExecutor, SplitExecutor, TaskSet,
+};
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[apply(test!)]
+async fn spawn_workers_drains_channel(ex: &Executor<'_>) {
+    let (sender, receiver) = async_channel::bounded(1);
+    let processed = Arc::new(AtomicUsize::new(0));
+
+    let handler = {
+        let processed = processed.clone();
+        move |item: usize| {
+            let processed = processed.clone();
+            async move {
+                processed.fetch_add(item, Ordering::SeqCst);
+            }
+        }
+    };
+    let workers = spawn_workers!(ex, count = 3, handler, receiver);
+    assert_eq!(workers.len(), 3);
+
+    for _ in 0..100 {
+        sender.send(1).await.unwrap();
+    }
+    sender.close();
+
+    for worker in workers {
+        worker.await;
+    }
+    assert_eq!(processed.load(Ordering::SeqCst), 100);
+}
+
+#[apply(test!)]
+async fn spawn_workers_local(ex: &LocalExecutor<'_>) {
+    let (sender, receiver) = async_channel::unbounded();
+    let processed = Rc::new(Cell::new(0));
+
+    let workers = spawn_workers!(
+        ex,
+        count = 2,
+        {
+            let processed = processed.clone();
+            move |item: usize| {
+                processed.set(processed.get() + item);
+                async {}
+            }
+        },
+        receiver,
+    );
+
+    sender.send(5).await.unwrap();
+    sender.send(6).await.unwrap();
+    drop(sender);
+
+    for worker in workers {
+        worker.await;
+    }
+    assert_eq!(processed.get(), 11);
+}
+
+#[apply(test!)]
+async fn in_runtime_main_thread(ex: &LocalExecutor<'_>) {
+    assert!(smol_macros::main_executor::in_runtime());
+    assert!(
+        ex.spawn(async { smol_macros::main_executor::in_runtime() })
+            .await
+    );
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+async fn in_runtime_worker_thread(ex: &Executor<'_>) {
+    // Block this thread so that the task has to run on a worker thread.
+    let (tx, rx) = std::sync::mpsc::channel();
+    ex.spawn(async move {
+        tx.send(smol_macros::main_executor::in_runtime()).unwrap();
+    })
+    .detach();
+    assert!(rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap());
+}
+
+#[test]
+fn not_in_runtime() {
+    assert!(!smol_macros::main_executor::in_runtime());
+}
+
+#[test]
+fn run_future() {
+    let value = smol_macros::run!(async {
+        assert!(smol_macros::main_executor::in_runtime());
+        1 + 1
+    });
+    assert_eq!(value, 2);
+}
+
+#[test]
+fn run_with_executor() {
+    let value = smol_macros::run!(|ex| async move { ex.spawn(async { 2 + 2 }).await });
+    assert_eq!(value, 4);
+
+    let value = smol_macros::run!(ex => ex.spawn(async { 3 + 3 }));
+    assert_eq!(value, 6);
+}
+
+#[test]
+fn run_with_local_executor() {
+    let local = Rc::new(Cell::new(0));
+    let value = smol_macros::run!(|ex: &LocalExecutor<'_>| async move {
+        let local = local.clone();
+        ex.spawn(async move {
+            local.set(5);
+            local.get()
+        })
+        .await
+    });
+    assert_eq!(value, 5);
+}
+
+#[test]
+fn main_with_driver() {
+    let value = smol_macros::main_with_driver!(|ex| {
+        let task = ex.spawn(async { 7 * 6 });
+        future::block_on(ex.run(task))
+    });
+    assert_eq!(value, 42);
+
+    let value = smol_macros::main_with_driver!(|ex: &LocalExecutor<'_>| {
+        let local = Rc::new(Cell::new(1));
+        let task = ex.spawn({
+            let local = local.clone();
+            async move { local.set(2) }
+        });
+        while !task.is_finished() {
+            ex.try_tick();
+        }
+        local.get()
+    });
+    assert_eq!(value, 2);
+}
+
+#[apply(test!)]
+async fn instrumented_executor(ex: &InstrumentedExecutor<'_>) {
+    for i in 0..5 {
+        assert_eq!(ex.spawn(async move { i }).await, i);
+    }
+
+    // Cancelled tasks are spawned but never completed.
+    drop(ex.spawn(future::pending::<()>()));
+
+    assert_eq!(ex.stats().spawned, 6);
+    assert_eq!(ex.stats().completed, 5);
+}
+
+#[test]
+fn instrumented_executor_stats_after_main() {
+    let config = Config::new();
+    let (value, stats) = InstrumentedExecutor::with_main_stats(&config, |ex| {
+        config.block_on(ex.run(async { ex.spawn(async { 1 }).await + ex.spawn(async { 2 }).await }))
+    });
+
+    assert_eq!(value, 3);
+    assert_eq!(stats.spawned, 2);
+    assert_eq!(stats.completed, 2);
+}
+
+#[apply(test!)]
+#[with_task_set]
+async fn task_set_join_all(ex: &LocalExecutor<'_>, tasks: &TaskSet<usize>) {
+    for i in 0..8 {
+        tasks.spawn(async move { i });
+    }
+    assert_eq!(tasks.len(), 8);
+
+    assert_eq!(tasks.join_all().await, (0..8).collect::<Vec<_>>());
+    assert!(tasks.is_empty());
+    assert_eq!(ex.spawn(async { 1 }).await, 1);
+}
+
+#[apply(test!)]
+async fn task_set_cancels_on_drop(ex: &Executor<'_>) {
+    let (sender, receiver) = async_channel::bounded::<()>(1);
+
+    let tasks = TaskSet::from(ex);
+    tasks.spawn(async move {
+        let _sender = sender;
+        future::pending::<()>().await;
+    });
+    drop(tasks);
+
+    // The sender is dropped along with the cancelled task.
+    assert!(receiver.recv().await.is_err());
+}
+
+/// The outputs handed over by `#[collect_results]`.
+static COLLECTED: std::sync::Mutex<Vec<usize>> = std::sync::Mutex::new(Vec::new());
+
+#[apply(smol_macros::main!)]
+#[collect_results(|outputs| *COLLECTED.lock().unwrap() = outputs)]
+async fn collect_into(ex: &Executor<'_>, tasks: &TaskSet<usize>) {
+    let (sender, receiver) = async_channel::bounded(1);
+    tasks.spawn(async move { receiver.recv().await.unwrap() });
+    for i in 1..4 {
+        tasks.spawn(async move { i });
+    }
+
+    // This one only finishes after the body returned.
+    ex.spawn(async move { sender.send(0).await.unwrap() })
+        .detach();
+}
+
+#[test]
+fn collect_results() {
+    collect_into();
+    assert_eq!(*COLLECTED.lock().unwrap(), [0, 1, 2, 3]);
+}
+
+#[test]
+fn config_run() {
+    let config = Config::new().threads(2);
+    let workers = config.run(|ex| async move {
+        assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+        smol_macros::main_executor::worker_threads()
+    });
+    let expected = if cfg!(feature = "single-thread") {
+        0
+    } else {
+        2
+    };
+    assert_eq!(workers, expected);
+}
+
+#[test]
+fn validate_runtime() {
+    use smol_macros::main_executor::validate_runtime;
+
+    validate_runtime(&Config::new()).unwrap();
+    validate_runtime(&Config::new().cpu_set([0, 0])).unwrap();
+}
+
+#[apply(test!)]
+async fn split_executor(ex: &SplitExecutor<'_>) {
+    let main_thread = std::thread::current().id();
+
+    let foreground = ex
+        .foreground()
+        .spawn(async { std::thread::current().id() })
+        .await;
+    assert_eq!(foreground, main_thread);
+
+    let background = ex
+        .background()
+        .spawn(async { std::thread::current().id() })
+        .await;
+    if cfg!(feature = "single-thread") {
+        assert_eq!(background, main_thread);
+    } else {
+        assert_ne!(background, main_thread);
+    }
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[cpu_set(0, 0)]
+async fn multithreaded(ex: &Executor<'_>) {
+    use smol_macros::main_executor::worker_threads;
+
+    smol_macros::assert_multithreaded!();
+    assert_eq!(worker_threads(), 2);
+    assert_eq!(ex.spawn(async { worker_threads() }).await, 2);
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[threads(2)]
+#[wait_for_workers]
+async fn worker_thread_ids(ex: &Executor<'_>) {
+    use smol_macros::main_executor::worker_thread_ids;
+    use std::thread;
+
+    let ids = worker_thread_ids();
+    assert_eq!(ids.len(), 2);
+    assert!(!ids.contains(&thread::current().id()));
+
+    // Block this thread so that the task has to run on one of the workers.
+    let (tx, rx) = std::sync::mpsc::channel();
+    ex.spawn(async move {
+        tx.send((thread::current().id(), worker_thread_ids()))
+            .unwrap();
+    })
+    .detach();
+    let (thread, seen) = rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+    assert!(ids.contains(&thread));
+    assert_eq!(seen, ids);
+}
+
+#[apply(test!)]
+#[threads(2)]
+async fn spawn_blocking_on_workers(ex: &Executor<'_>) {
+    let tasks = (0..4)
+        .map(|_| {
+            smol_macros::spawn_blocking_on(ex, || {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                std::thread::current().name().map(String::from)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut offloaded = 0;
+    for task in tasks {
+        let name = task.await.unwrap_or_default();
+        if name.starts_with("blocking-") {
+            offloaded += 1;
+        }
+    }
+
+    // One of the threads running the executor is always kept free.
+    if cfg!(feature = "single-thread") {
+        assert_eq!(offloaded, 4);
+    } else {
+        assert!((1..4).contains(&offloaded), "{offloaded} offloaded");
+    }
+}
+
+#[apply(test!)]
+async fn within_deadline(ex: &Executor<'_>) {
+    use smol_macros::main_executor::{try_within, within};
+    use std::time::Duration;
+
+    assert_eq!(
+        within(Duration::from_secs(5), ex.spawn(async { 1 })).await,
+        1
+    );
+    assert_eq!(
+        try_within(Duration::from_millis(10), future::pending::<()>()).await,
+        None
+    );
+
+    let slow =
+        std::panic::AssertUnwindSafe(within(Duration::from_millis(10), future::pending::<()>()));
+    assert!(futures_lite::FutureExt::catch_unwind(slow).await.is_err());
+}
+
+#[apply(test!)]
+#[threads(3)]
+async fn pinned_executor(ex: &smol_macros::PinnedExecutor<'_>) {
+    let expected = if cfg!(feature = "single-thread") {
+        0
+    } else {
+        3
+    };
+    assert_eq!(ex.workers(), expected);
+
+    let tasks = (0..12)
+        .map(|index| {
+            ex.spawn_on_worker(index, async move {
+                let mut threads = Vec::new();
+                for _ in 0..4 {
+                    threads.push(std::thread::current().id());
+                    future::yield_now().await;
+                }
+                (index, threads)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut pinned = std::collections::HashMap::new();
+    for task in tasks {
+        let (index, threads) = task.await;
+        let thread = *pinned.entry(index % 3).or_insert(threads[0]);
+        if !cfg!(feature = "single-thread") {
+            assert!(threads.iter().all(|id| *id == thread));
+        }
+    }
+    assert_eq!(ex.spawn(async { 1 }).await, 1);
+    assert!(ex.is_empty());
+}
+
+#[apply(test!)]
+async fn spawn_isolated_catches_panics(ex: &Executor<'_>) {
+    let crashed = smol_macros::spawn_isolated(ex, async {
+        future::yield_now().await;
+        panic!("plugin crashed");
+    });
+    let healthy = smol_macros::spawn_isolated(ex, async { 1 + 1 });
+
+    let payload = crashed.await.unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"plugin crashed"));
+    assert_eq!(healthy.await.unwrap(), 2);
+
+    // The executor keeps running tasks afterwards.
+    assert_eq!(ex.spawn(async { 3 }).await, 3);
+}
+
+#[cfg(all(unix, feature = "async-signal"))]
+#[apply(test!)]
+async fn ctrl_c_completes_on_sigint() {
+    let ctrl_c = smol_macros::main_executor::ctrl_c();
+    futures_lite::pin!(ctrl_c);
+
+    // Register the handler before sending the signal.
+    assert!(future::poll_once(ctrl_c.as_mut()).await.is_none());
+    let status = std::process::Command::new("kill")
+        .args(["-INT", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    ctrl_c.await;
+}
+
+#[apply(test!)]
+#[threads(2)]
+async fn thread_distribution(ex: &Executor<'_>) {
+    use smol_macros::main_executor::observe_thread_distribution;
+
+    let distribution = observe_thread_distribution(ex, 20).await;
+    assert_eq!(distribution.values().sum::<usize>(), 20);
+    if cfg!(feature = "single-thread") {
+        assert_eq!(
+            distribution.keys().collect::<Vec<_>>(),
+            [&std::thread::current().id()]
+        );
+    } else {
+        assert!(distribution.len() > 1, "{distribution:?}");
+    }
+}
+
+#[apply(test!)]
+async fn race_tasks_cancels_losers(ex: &Executor<'_>) {
+    use smol_macros::main_executor::race_tasks;
+
+    /// Counts the losing tasks that were dropped.
+    struct OnDrop(Arc<AtomicUsize>);
+
+    impl Drop for OnDrop {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let futures = (0..4).map(|i| {
+        let on_drop = OnDrop(dropped.clone());
+        async move {
+            let _on_drop = on_drop;
+            if i != 2 {
+                future::pending::<()>().await;
+            }
+            i
+        }
+    });
+
+    assert_eq!(race_tasks(ex, futures).await, 2);
+    while dropped.load(Ordering::SeqCst) < 4 {
+        future::yield_now().await;
+    }
+}
+
+#[apply(test!)]
+async fn scope_awaits_tasks() {
+    use smol_macros::main_executor::scope;
+
+    let finished = AtomicUsize::new(0);
+    let finished_ref = &finished;
+    let spawned = scope(|s| async move {
+        for _ in 0..4 {
+            s.spawn(async move {
+                future::yield_now().await;
+                finished_ref.fetch_add(1, Ordering::SeqCst);
+            })
+            .detach();
+        }
+        s.spawn(async { 4 }).await
+    })
+    .await;
+
+    // The detached tasks are awaited before the scope returns.
+    assert_eq!(finished.into_inner(), spawned);
+}
+
+#[apply(test!)]
+async fn scope_cancels_tasks_on_drop() {
+    use smol_macros::main_executor::scope;
+
+    let (started, finished) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    let (started_ref, finished_ref) = (&started, &finished);
+    let body = scope(|s| async move {
+        s.spawn(async move {
+            started_ref.fetch_add(1, Ordering::SeqCst);
+            future::pending::<()>().await;
+            finished_ref.fetch_add(1, Ordering::SeqCst);
+        })
+        .detach();
+        future::pending::<()>().await
+    });
+
+    // Poll the scope for a while, then drop it.
+    future::or(body, async {
+        async_io::Timer::after(std::time::Duration::from_millis(10)).await;
+    })
+    .await;
+
+    assert_eq!(started.into_inner(), 1);
+    assert_eq!(finished.into_inner(), 0);
+}
+
+#[apply(test!)]
+#[should_panic(expected = "the test needs a multithreaded runtime")]
+async fn not_multithreaded(_ex: &LocalExecutor<'_>) {
+    smol_macros::assert_multithreaded!();
+}
+
+#[apply(test!)]
+async fn current_executor(ex: &Arc<Executor<'static>>) {
+    use smol_macros::main_executor::current_executor;
+
+    assert!(Arc::ptr_eq(&current_executor().unwrap(), ex));
+
+    let in_task = ex.spawn(async { current_executor() }).await;
+    assert!(Arc::ptr_eq(&in_task.unwrap(), ex));
+
+    // Tasks can be spawned from code that isn't passed the executor.
+    let spawned = current_executor().unwrap().spawn(async { 7 }).await;
+    assert_eq!(spawned, 7);
+}
+
+#[apply(test!)]
+#[shared_executor]
+async fn current_executor_shared(ex: &Arc<Executor<'static>>) {
+    use smol_macros::main_executor::current_executor;
+
+    assert!(Arc::ptr_eq(&current_executor().unwrap(), ex));
+    let in_task = ex.spawn(async { current_executor() }).await;
+    assert!(Arc::ptr_eq(&in_task.unwrap(), ex));
+}
+
+#[apply(test!)]
+async fn current_executor_borrowed(ex: &Executor<'_>) {
+    use smol_macros::main_executor::current_executor;
+
+    assert!(current_executor().is_none());
+    assert!(ex.spawn(async { current_executor() }).await.is_none());
+}
+
+#[test]
+fn run_test() {
+    use smol_macros::main_executor::{current_executor, run_test};
+
+    let value = run_test(|ex| async move {
+        assert!(Arc::ptr_eq(&current_executor().unwrap(), &ex));
+        ex.spawn(async { 7 }).await
+    });
+    assert_eq!(value, 7);
+    assert!(current_executor().is_none());
+
+    let panicked = std::panic::catch_unwind(|| run_test(|_| async { panic!("test failed") }));
+    assert!(panicked.is_err());
+}
+
+#[apply(test!)]
+async fn catch_task_panic(ex: &Executor<'_>) {
+    use smol_macros::main_executor::catch_task_panic;
+
+    let id = std::process::id();
+    let err = catch_task_panic(ex.spawn(async move { panic!("task {id} failed") }))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<String>().map(String::as_str),
+        Some(format!("task {id} failed").as_str())
+    );
+
+    assert_eq!(catch_task_panic(ex.spawn(async { 2 })).await.unwrap(), 2);
+}
+
+#[test]
+fn background_runtime() {
+    use smol_macros::main_executor::{current_executor, Config, Runtime};
+
+    let runtime = Runtime::start(&Config::new().threads(2)).unwrap();
+    let handle = runtime.handle();
+    assert!(current_executor().is_none());
+
+    // Ticking from a thread that isn't part of the runtime, like a GUI event loop.
+    let task = handle.spawn(async { current_executor().is_some() });
+    while !task.is_finished() {
+        handle.tick();
+    }
+    assert!(futures_lite::future::block_on(task));
+    assert!(!handle.tick());
+
+    runtime.shutdown();
+}
+
+#[test]
+fn local_runtime() {
+    use smol_macros::main_executor::LocalRuntime;
+
+    let runtime = LocalRuntime::new();
+    assert!(!runtime.tick());
+
+    let ran = Rc::new(Cell::new(0));
+    let task = runtime.spawn({
+        let ran = ran.clone();
+        async move {
+            for _ in 0..3 {
+                ran.set(ran.get() + 1);
+                future::yield_now().await;
+            }
+        }
+    });
+    assert_eq!(ran.get(), 0);
+
+    assert!(runtime.tick());
+    assert_eq!(ran.get(), 1);
+    assert_eq!(runtime.run_until_stalled(), 3);
+    assert!(task.is_finished());
+    assert!(runtime.is_empty());
+}
+
+#[test]
+fn runtime_lifecycle_events() {
+    use futures_lite::StreamExt;
+    use smol_macros::main_executor::{LifecycleEvent, Runtime};
+
+    let (runtime, mut events) = Runtime::start_with_events(&Config::new().threads(2)).unwrap();
+    let mut started = future::block_on(async {
+        let mut started = Vec::new();
+        while let Some(event) = events.next().await {
+            started.push(event);
+            if event == LifecycleEvent::Started {
+                break;
+            }
+        }
+        started
+    });
+    runtime.shutdown();
+    started.extend(future::block_on(events.collect::<Vec<_>>()));
+    let events = started;
+
+    let mut online: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            LifecycleEvent::WorkerOnline(index) => Some(*index),
+            _ => None,
+        })
+        .collect();
+    online.sort_unstable();
+    let rest: Vec<_> = events
+        .into_iter()
+        .filter(|event| !matches!(event, LifecycleEvent::WorkerOnline(_)))
+        .collect();
+    assert_eq!(
+        rest,
+        [
+            LifecycleEvent::Started,
+            LifecycleEvent::Stopping,
+            LifecycleEvent::Stopped
+        ]
+    );
+
+    #[cfg(not(feature = "single-thread"))]
+    assert_eq!(online, [0, 1]);
+    #[cfg(feature = "single-thread")]
+    assert!(online.is_empty());
+}
+
+#[test]
+fn runtime_stop_token() {
+    use smol_macros::main_executor::Runtime;
+
+    let runtime = Runtime::start(&Config::new().threads(1)).unwrap();
+    let token = runtime.stop_token();
+    let task = runtime.handle().spawn({
+        let token = token.clone();
+        async move { token.cancelled().await }
+    });
+
+    assert!(!token.is_cancelled());
+    runtime.shutdown();
+    assert!(token.is_cancelled());
+    drop(task);
+
+    let runtime = Runtime::start(&Config::new().threads(1)).unwrap();
+    let token = runtime.stop_token();
+    drop(runtime);
+    assert!(token.is_cancelled());
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[test]
+fn detached_thread_pool() {
+    use smol_macros::main_executor::ThreadPool;
+
+    let ex = Arc::new(Executor::new());
+    let pool = ThreadPool::spawn(&ex, &Config::new().cpu_set([0, 0])).unwrap();
+
+    let main_thread = std::thread::current().id();
+    let worker = future::block_on(ex.spawn(async { std::thread::current().id() }));
+    assert_ne!(worker, main_thread);
+
+    pool.join();
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[test]
+fn stop_check_interval_latency() {
+    use smol_macros::main_executor::ThreadPool;
+    use std::time::{Duration, Instant};
+
+    let ex = Arc::new(Executor::new());
+    let config = Config::new().threads(1).stop_check_interval(1);
+    let pool = ThreadPool::spawn(&ex, &config).unwrap();
+
+    // A task that is always ready again, taking a millisecond every time it runs. Without the
+    // interval, the worker would run it 200 times before noticing the stop.
+    let started = Arc::new(AtomicUsize::new(0));
+    ex.spawn({
+        let started = started.clone();
+        async move {
+            loop {
+                started.fetch_add(1, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(1));
+                future::yield_now().await;
+            }
+        }
+    })
+    .detach();
+    while started.load(Ordering::SeqCst) < 5 {
+        std::thread::yield_now();
+    }
+
+    let stop = Instant::now();
+    pool.join();
+    assert!(stop.elapsed() < Duration::from_millis(100));
+}
+
+#[apply(test!)]
+#[manual_tick]
+async fn manual_tick(ex: &LocalExecutor<'_>, ticker: &smol_macros::Ticker<'_, '_>) {
+    let log = Rc::new(std::cell::RefCell::new(Vec::new()));
+    for i in 0..3 {
+        let log = log.clone();
+        ex.spawn(async move {
+            log.borrow_mut().push(i);
+            future::yield_now().await;
+            log.borrow_mut().push(i + 10);
+        })
+        .detach();
+    }
+
+    // Nothing runs until the ticker is used, even while the body is pending.
+    future::yield_now().await;
+    assert!(log.borrow().is_empty());
+
+    assert!(ticker.tick());
+    assert_eq!(*log.borrow(), [0]);
+
+    assert_eq!(ticker.run_until_idle(), 5);
+    assert_eq!(*log.borrow(), [0, 1, 2, 10, 11, 12]);
+    assert!(!ticker.tick());
+}
+
+#[apply(test!)]
+#[step]
+async fn step_executor(ex: &smol_macros::StepExecutor<'_>) {
+    let log = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let (sender, receiver) = async_channel::bounded(1);
+    ex.spawn({
+        let log = log.clone();
+        async move {
+            let value: u32 = receiver.recv().await.unwrap();
+            log.borrow_mut().push(value);
+        }
+    })
+    .detach();
+    ex.spawn({
+        let log = log.clone();
+        async move {
+            log.borrow_mut().push(1);
+            sender.send(2).await.unwrap();
+            log.borrow_mut().push(3);
+        }
+    })
+    .detach();
+
+    // Nothing runs until the executor is stepped, even while the body is pending.
+    future::yield_now().await;
+    assert_eq!(ex.ready(), 2);
+    assert!(log.borrow().is_empty());
+
+    // The receiver waits, and the sender wakes it up, which only runs in the next round.
+    assert_eq!(ex.run_ready(), 2);
+    assert_eq!(*log.borrow(), [1, 3]);
+    assert_eq!(ex.ready(), 1);
+
+    assert!(ex.step_one());
+    assert_eq!(*log.borrow(), [1, 3, 2]);
+    assert_eq!(ex.run_ready(), 0);
+    assert!(ex.is_empty());
+
+    // Cancelled tasks are only dropped by the next step.
+    drop(ex.spawn(future::pending::<()>()));
+    assert_eq!(ex.ready(), 1);
+    assert!(ex.step_one());
+    assert_eq!(ex.ready(), 0);
+}
+
+#[test]
+fn priority_executor_prefers_high() {
+    let ex = smol_macros::PriorityExecutor::new();
+    let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+    for (lane, name) in [(ex.low(), "low"), (ex.high(), "high"), (ex.low(), "low")] {
+        let order = order.clone();
+        lane.spawn(async move { order.lock().unwrap().push(name) })
+            .detach();
+    }
+
+    while ex.try_tick() {}
+    assert_eq!(*order.lock().unwrap(), ["high", "low", "low"]);
+    assert!(ex.is_empty());
+}
+
+#[apply(test!)]
+async fn priority_executor(ex: &smol_macros::PriorityExecutor<'_>) {
+    let low = ex.low().spawn(async { 1 });
+    let high = ex.spawn(async { 2 });
+    assert_eq!(low.await + high.await, 3);
+}
+
+#[cfg(feature = "task-names")]
+#[apply(test!)]
+async fn named_tasks(ex: &Executor<'_>) {
+    use smol_macros::{current_task_name, named, running_tasks, spawn_named};
+
+    assert_eq!(current_task_name(), None);
+
+    let (sender, receiver) = async_channel::bounded::<()>(1);
+    let waiting = spawn_named(ex, "named_tasks::waiting", async move {
+        receiver.recv().await.ok();
+        current_task_name()
+    });
+    assert!(running_tasks().contains(&"named_tasks::waiting"));
+
+    let nested = named("named_tasks::outer", async {
+        let inner = named("named_tasks::inner", async { current_task_name() }).await;
+        (inner, current_task_name())
+    });
+    assert_eq!(
+        nested.await,
+        (Some("named_tasks::inner"), Some("named_tasks::outer"))
+    );
+
+    sender.send(()).await.unwrap();
+    assert_eq!(waiting.await, Some("named_tasks::waiting"));
+    assert!(!running_tasks().contains(&"named_tasks::waiting"));
+}
+
+#[apply(test!)]
+async fn lazy_executor_without_spawns(ex: &smol_macros::LazyExecutor) {
+    future::yield_now().await;
+    assert!(!ex.is_started());
+    assert!(smol_macros::main_executor::worker_thread_ids().is_empty());
+}
+
+#[apply(test!)]
+#[threads(2)]
+async fn lazy_executor(ex: &smol_macros::LazyExecutor) {
+    assert!(!ex.is_started());
+    let tasks = (0..4)
+        .map(|i| ex.spawn(async move { i * 2 }))
+        .collect::<Vec<_>>();
+    assert!(ex.is_started());
+
+    let mut sum = 0;
+    for task in tasks {
+        sum += task.await;
+    }
+    assert_eq!(sum, 12);
+    assert!(smol_macros::main_executor::current_executor().is_some());
+}
+
+#[test]
+fn bench_on_reuses_runtime() {
+    let executors = Arc::new(AtomicUsize::new(0));
+    let mut routine = smol_macros::main_executor::bench_on({
+        let executors = executors.clone();
+        move |ex| {
+            executors.store(Arc::as_ptr(&ex) as usize, Ordering::SeqCst);
+            async move { ex.spawn(async { 1 + 2 }).await }
+        }
+    });
+
+    let mut first = None;
+    for _ in 0..5 {
+        assert_eq!(routine(), 3);
+        assert!(smol_macros::main_executor::current_executor().is_none());
+        let ex = executors.load(Ordering::SeqCst);
+        assert_eq!(*first.get_or_insert(ex), ex);
+    }
+}
+
+#[cfg(all(feature = "peak-concurrency", not(feature = "single-thread")))]
+#[test]
+fn instrumented_executor_peak_concurrency() {
+    let config = Config::new().threads(3);
+    let barrier = Arc::new(async_lock::Barrier::new(2));
+    let ((), stats) = InstrumentedExecutor::with_main_stats(&config, |ex| {
+        config.block_on(ex.run(async {
+            // Both tasks block their thread until the other one is polled as well.
+            let tasks = (0..2)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    ex.spawn(async move {
+                        future::block_on(barrier.wait());
+                    })
+                })
+                .collect::<Vec<_>>();
+            for task in tasks {
+                task.await;
+            }
+        }))
+    });
+
+    assert_eq!(stats.completed, 2);
+    assert_eq!(stats.peak_concurrency, 2);
+}
@@ -0,0 +1,37 @@
+//! Testing `#[io_config]`.
+//!
+//! The settings are passed on through environment variables, so these tests live in their own
+//! binary to avoid racing with tests that read or change the environment.
+
+use smol_macros::Executor;
+
+use std::env;
+use std::sync::Mutex;
+
+/// The value of `BLOCKING_MAX_THREADS` seen by `#[init]`.
+static SEEN_BY_INIT: Mutex<Option<String>> = Mutex::new(None);
+
+fn record_env() {
+    *SEEN_BY_INIT.lock().unwrap() = env::var("BLOCKING_MAX_THREADS").ok();
+}
+
+smol_macros::main! {
+    #[io_config(blocking_max_threads = 16)]
+    #[init(record_env)]
+    async fn configured(_ex: &Executor<'_>) -> Option<String> {
+        assert_eq!(blocking::unblock(|| 1 + 1).await, 2);
+        env::var("BLOCKING_MAX_THREADS").ok()
+    }
+}
+
+#[test]
+fn io_config() {
+    env::remove_var("BLOCKING_MAX_THREADS");
+    assert_eq!(configured().as_deref(), Some("16"));
+    assert_eq!(SEEN_BY_INIT.lock().unwrap().as_deref(), Some("16"));
+
+    // A value set outside of the program wins.
+    env::set_var("BLOCKING_MAX_THREADS", "4");
+    assert_eq!(configured().as_deref(), Some("4"));
+    assert_eq!(SEEN_BY_INIT.lock().unwrap().as_deref(), Some("4"));
+}
@@ -0,0 +1,32 @@
+//! Testing `#[isolate_env]`.
+//!
+//! Restoring the environment would undo the changes that other tests make to it in the meantime,
+//! so these tests live in their own binary.
+
+use smol_macros::Executor;
+
+use std::env;
+
+smol_macros::main! {
+    #[isolate_env]
+    async fn change_env(_ex: &Executor<'_>, fail: bool) {
+        env::set_var("SMOL_MACROS_ISOLATED_NEW", "1");
+        env::set_var("SMOL_MACROS_ISOLATED_CHANGED", "2");
+        env::remove_var("SMOL_MACROS_ISOLATED_REMOVED");
+        assert!(!fail, "failed with the environment changed");
+    }
+}
+
+#[test]
+fn restores_env() {
+    env::set_var("SMOL_MACROS_ISOLATED_CHANGED", "1");
+    env::set_var("SMOL_MACROS_ISOLATED_REMOVED", "1");
+
+    for fail in [false, true] {
+        let result = std::panic::catch_unwind(|| change_env(fail));
+        assert_eq!(result.is_err(), fail);
+        assert!(env::var_os("SMOL_MACROS_ISOLATED_NEW").is_none());
+        assert_eq!(env::var("SMOL_MACROS_ISOLATED_CHANGED").as_deref(), Ok("1"));
+        assert_eq!(env::var("SMOL_MACROS_ISOLATED_REMOVED").as_deref(), Ok("1"));
+    }
+}
@@ -3,9 +3,16 @@
 use async_lock::Barrier;
 use futures_lite::prelude::*;
 use macro_rules_attribute::apply;
-use smol_macros::{test, Executor, LocalExecutor};
+use smol_macros::main_executor::within;
+use smol_macros::{
+    test, AnyExecutor, CancellationToken, Executor, LocalExecutor, MockClock, Reactor, Schedule,
+    ScheduledExecutor, TaskLimit,
+};
 
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -30,13 +37,7 @@ async fn with_executor(ex: &Executor<'static>) {
         }
     })
     .detach();
-    barrier
-        .wait()
-        .or(async {
-            async_io::Timer::after(Duration::from_secs(5)).await;
-            panic!("timed out")
-        })
-        .await;
+    within(Duration::from_secs(5), barrier.wait()).await;
 }
 
 #[apply(test!)]
@@ -49,13 +50,16 @@ async fn with_executor_arc(ex: Arc<Executor<'static>>) {
         }
     })
     .detach();
-    barrier
-        .wait()
-        .or(async {
-            async_io::Timer::after(Duration::from_secs(5)).await;
-            panic!("timed out")
-        })
-        .await;
+    within(Duration::from_secs(5), barrier.wait()).await;
+}
+
+#[apply(test!)]
+async fn with_executor_arc_in_task(ex: Arc<Executor<'static>>) {
+    let task = ex.spawn({
+        let ex = ex.clone();
+        async move { ex.spawn(async { 1 + 1 }).await }
+    });
+    assert_eq!(task.await, 2);
 }
 
 #[apply(test!)]
@@ -68,13 +72,7 @@ async fn with_executor_arcref(ex: &Arc<Executor<'static>>) {
         }
     })
     .detach();
-    barrier
-        .wait()
-        .or(async {
-            async_io::Timer::after(Duration::from_secs(5)).await;
-            panic!("timed out")
-        })
-        .await;
+    within(Duration::from_secs(5), barrier.wait()).await;
 }
 
 #[apply(test!)]
@@ -87,13 +85,7 @@ async fn with_local(ex: &LocalExecutor<'_>) {
         }
     })
     .detach();
-    barrier
-        .wait()
-        .or(async {
-            async_io::Timer::after(Duration::from_secs(5)).await;
-            panic!("timed out")
-        })
-        .await;
+    within(Duration::from_secs(5), barrier.wait()).await;
 }
 
 #[apply(test!)]
@@ -106,13 +98,7 @@ async fn with_local_rc(ex: Rc<LocalExecutor<'_>>) {
         }
     })
     .detach();
-    barrier
-        .wait()
-        .or(async {
-            async_io::Timer::after(Duration::from_secs(5)).await;
-            panic!("timed out")
-        })
-        .await;
+    within(Duration::from_secs(5), barrier.wait()).await;
 }
 
 #[apply(test!)]
@@ -125,13 +111,7 @@ async fn with_local_rcref(ex: &Rc<LocalExecutor<'_>>) {
         }
     })
     .detach();
-    barrier
-        .wait()
-        .or(async {
-            async_io::Timer::after(Duration::from_secs(5)).await;
-            panic!("timed out")
-        })
-        .await;
+    within(Duration::from_secs(5), barrier.wait()).await;
 }
 
 #[apply(test!)]
@@ -139,3 +119,1967 @@ async fn it_works(_ex: &Executor<'_>) -> Result<(), Box<dyn std::error::Error>>
     let _ = u32::try_from(20usize)?;
     Ok(())
 }
+
+#[apply(test!)]
+#[shutdown_timeout(5s)]
+async fn with_shutdown_timeout(ex: &Executor<'_>) {
+    ex.spawn(async { 1 + 1 }).await;
+}
+
+#[apply(test!)]
+#[shutdown_timeout(Duration::from_millis(500))]
+async fn with_shutdown_timeout_expr(ex: Arc<Executor<'_>>) {
+    ex.spawn(async { 1 + 1 }).await;
+}
+
+#[test]
+fn duration_literals() {
+    use smol_macros::main_executor::parse_duration;
+
+    // Literals are parsed while compiling, so an invalid one is a compile error.
+    const TIMEOUT: Duration = parse_duration("1_500ms");
+    assert_eq!(TIMEOUT, Duration::from_millis(1500));
+
+    assert_eq!(parse_duration("7ns"), Duration::from_nanos(7));
+    assert_eq!(parse_duration("7us"), Duration::from_micros(7));
+    assert_eq!(parse_duration("7s"), Duration::from_secs(7));
+    assert_eq!(parse_duration("7m"), Duration::from_secs(7 * 60));
+    assert_eq!(parse_duration("7h"), Duration::from_secs(7 * 60 * 60));
+}
+
+#[test]
+#[should_panic(expected = "invalid duration unit")]
+fn duration_literal_no_unit() {
+    smol_macros::main_executor::parse_duration("5");
+}
+
+#[test]
+#[should_panic(expected = "invalid duration unit")]
+fn duration_literal_unknown_unit() {
+    smol_macros::main_executor::parse_duration("10sec");
+}
+
+#[test]
+#[should_panic(expected = "the duration is too long")]
+fn duration_literal_overflow() {
+    smol_macros::main_executor::parse_duration("999999999999999999h");
+}
+
+async fn delegated_with_executor(ex: &Executor<'_>) {
+    assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+}
+
+async fn delegated_without_executor() -> Result<(), Box<dyn std::error::Error>> {
+    let _ = u32::try_from(20usize)?;
+    Ok(())
+}
+
+test! {
+    fn delegated_executor(ex: &Executor<'_>) = delegated_with_executor;
+}
+
+test! {
+    fn delegated_no_executor() -> Result<(), Box<dyn std::error::Error>> = delegated_without_executor;
+}
+
+smol_macros::main! {
+    #[shared_executor]
+    async fn shared_worker_threads(ex: &Executor<'static>) -> Vec<std::thread::ThreadId> {
+        // Block every shared thread at once, so that each of them runs one of the tasks. This
+        // relies on no other test blocking the shared threads at the same time.
+        let workers = smol_macros::main_executor::worker_threads();
+        let barrier = Arc::new(std::sync::Barrier::new(workers));
+        let (tx, rx) = std::sync::mpsc::channel();
+        for _ in 0..workers {
+            let barrier = barrier.clone();
+            let tx = tx.clone();
+            ex.spawn(async move {
+                barrier.wait();
+                let thread = std::thread::current();
+                assert!(thread.name().unwrap().starts_with("smol-macros-shared-"));
+                tx.send(thread.id()).unwrap();
+            })
+            .detach();
+        }
+
+        // Block this thread so that the tasks have to run on the shared threads.
+        (0..workers)
+            .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect()
+    }
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[shared_executor]
+async fn with_shared_executor_arc(ex: Arc<Executor<'static>>) {
+    // Block this thread so that the task has to run on one of the shared threads.
+    let (tx, rx) = std::sync::mpsc::channel();
+    ex.spawn(async move {
+        tx.send(std::thread::current()).unwrap();
+    })
+    .detach();
+    let thread = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(thread.name().unwrap().starts_with("smol-macros-shared-"));
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[test]
+fn with_shared_executor() {
+    // Every call runs on the same threads, rather than spawning a thread pool of its own.
+    let first = shared_worker_threads();
+    let second = shared_worker_threads();
+    assert!(!first.is_empty());
+    assert_eq!(first.len(), second.len());
+    assert!(second.iter().all(|thread| first.contains(thread)));
+}
+
+#[apply(test!)]
+#[name = renamed_test]
+async fn original_name(ex: &Executor<'_>) {
+    ex.spawn(async {}).await;
+}
+
+#[test]
+fn renamed_test_exists() {
+    let _: fn() = renamed_test;
+}
+
+mod visible {
+    use super::*;
+
+    #[apply(smol_macros::main!)]
+    pub async fn public_main(ex: &Executor<'_>) -> u32 {
+        ex.spawn(async { 1 + 1 }).await
+    }
+
+    smol_macros::main! {
+        pub(crate) async fn crate_main() -> u32 = async { 2 + 2 };
+    }
+
+    #[apply(test!)]
+    pub async fn public_test(ex: &Executor<'_>) {
+        ex.spawn(async {}).await;
+    }
+
+    #[apply(test!)]
+    #[name = renamed_crate_test]
+    pub(crate) async fn crate_test() {}
+}
+
+#[test]
+fn with_visibility() {
+    assert_eq!(visible::public_main(), 2);
+    assert_eq!(visible::crate_main(), 4);
+
+    let _: fn() = visible::public_test;
+    let _: fn() = visible::renamed_crate_test;
+}
+
+#[apply(test!)]
+#[no_reactor]
+async fn with_no_reactor() {
+    futures_lite::future::yield_now().await;
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[no_reactor]
+async fn with_no_reactor_executor(ex: &Executor<'_>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    ex.spawn(async move {
+        tx.send(1 + 1).unwrap();
+    })
+    .detach();
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 2);
+}
+
+thread_local! {
+    static AROUND_DEPTH: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+fn around_counter<T>(f: impl FnOnce() -> T) -> T {
+    AROUND_DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let result = f();
+    AROUND_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    result
+}
+
+#[apply(test!)]
+#[around(around_counter)]
+async fn with_around() {
+    assert_eq!(AROUND_DEPTH.with(|depth| depth.get()), 1);
+}
+
+#[apply(test!)]
+#[around(around_counter)]
+#[around(self::around_counter)]
+async fn with_around_executor(ex: &Executor<'_>) {
+    ex.spawn(async {}).await;
+    assert_eq!(AROUND_DEPTH.with(|depth| depth.get()), 2);
+}
+
+fn build_future<'a>(ex: &'a Executor<'_>) -> impl Future<Output = u32> + 'a {
+    let task = ex.spawn(async { 1 + 1 });
+    async move { task.await * 2 }
+}
+
+test! {
+    async fn with_future_expr(ex: &Executor<'_>) = async move {
+        assert_eq!(build_future(ex).await, 4);
+    };
+}
+
+test! {
+    async fn with_future_expr_no_executor() = futures_lite::future::ready(());
+}
+
+static PRELUDE_BARRIER: Barrier = Barrier::new(2);
+
+#[apply(test!)]
+#[prelude(async {
+    PRELUDE_BARRIER.wait().await;
+})]
+async fn with_prelude(_ex: &Executor<'_>) {
+    within(Duration::from_secs(5), PRELUDE_BARRIER.wait()).await;
+}
+
+#[apply(test!)]
+#[seed]
+async fn with_seed(ex: &Executor<'_>) {
+    let seed = smol_macros::test_seed().unwrap();
+    let first = fastrand::Rng::with_seed(seed).u64(..);
+    assert_eq!(fastrand::Rng::with_seed(seed).u64(..), first);
+
+    ex.spawn(async {}).await;
+    assert_eq!(smol_macros::test_seed(), Some(seed));
+}
+
+#[apply(test!)]
+async fn without_seed() {
+    assert_eq!(smol_macros::test_seed(), None);
+}
+
+#[apply(test!)]
+#[single_threaded(false)]
+async fn with_any_executor(ex: &AnyExecutor<'_>) {
+    assert!(!ex.is_local());
+    assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+}
+
+#[apply(test!)]
+#[single_threaded(1 + 1 == 2)]
+async fn with_any_executor_local(ex: &AnyExecutor<'_>) {
+    assert!(ex.is_local());
+    let id = std::thread::current().id();
+    assert_eq!(
+        ex.spawn(async move { std::thread::current().id() }).await,
+        id
+    );
+}
+
+#[apply(test!)]
+#[multi_thread(all())]
+async fn with_multi_thread(ex: &AnyExecutor<'_>) {
+    assert!(!ex.is_local());
+}
+
+#[apply(test!)]
+#[multi_thread(any())]
+async fn with_multi_thread_disabled(ex: &AnyExecutor<'_>) {
+    assert!(ex.is_local());
+}
+
+#[apply(test!)]
+#[main_thread_worker]
+async fn with_main_thread_worker(ex: &Executor<'_>) {
+    let num_threads = std::thread::available_parallelism().map_or(1, |num| num.get());
+    let extra = format!("smol-macros-{}", num_threads - 1);
+
+    let tasks = (0..64)
+        .map(|_| ex.spawn(async { std::thread::current().name().map(String::from) }))
+        .collect::<Vec<_>>();
+    for task in tasks {
+        assert_ne!(task.await.as_deref(), Some(extra.as_str()));
+    }
+}
+
+#[apply(test!)]
+#[prewarm_blocking(4)]
+async fn with_prewarm_blocking(ex: &Executor<'_>) {
+    let task = ex.spawn(blocking::unblock(|| 1 + 1));
+    assert_eq!(task.await, 2);
+}
+
+#[apply(test!)]
+#[prewarm_blocking(2)]
+async fn with_prewarm_blocking_no_executor() {
+    assert_eq!(blocking::unblock(|| 1 + 1).await, 2);
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[cpu_set(0)]
+async fn with_cpu_set(ex: &Executor<'_>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    ex.spawn(async move {
+        tx.send(std::thread::current().name().map(String::from))
+            .unwrap();
+    })
+    .detach();
+
+    let name = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(name.as_deref(), Some("smol-macros-0"));
+}
+
+#[cfg(feature = "tokio-compat")]
+#[apply(test!)]
+#[threads(2)]
+#[tokio_bridge]
+async fn with_tokio_bridge(ex: &Executor<'_>) {
+    let handle = tokio::runtime::Handle::current();
+
+    // Tasks on the worker threads have a tokio context as well, without `Compat`.
+    let tasks = (0..4)
+        .map(|i| {
+            ex.spawn(async move {
+                let handle = tokio::runtime::Handle::current();
+                handle.spawn(async move { i * 2 }).await.unwrap()
+            })
+        })
+        .collect::<Vec<_>>();
+    for (i, task) in tasks.into_iter().enumerate() {
+        assert_eq!(task.await, i * 2);
+    }
+    assert_eq!(handle.spawn(async { 1 }).await.unwrap(), 1);
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[cpu_set(0, 0, 0)]
+#[idle_timeout(20ms)]
+#[min_workers(1)]
+async fn with_idle_timeout(ex: &Executor<'_>) {
+    // Give the worker threads time to exit.
+    async_io::Timer::after(Duration::from_millis(200)).await;
+
+    // Block this thread so that the remaining worker has to spawn more threads to help out.
+    let (tx, rx) = std::sync::mpsc::channel();
+    for _ in 0..50 {
+        let tx = tx.clone();
+        ex.spawn(async move {
+            tx.send(std::thread::current().name().map(String::from))
+                .unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        })
+        .detach();
+    }
+
+    let mut names = (0..50)
+        .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    assert!(names.len() > 1, "{names:?}");
+    assert!(names.iter().all(|name| name.starts_with("smol-macros-")));
+}
+
+#[cfg(not(feature = "single-thread"))]
+static LAZY_WORKERS: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(not(feature = "single-thread"))]
+fn count_lazy_worker(_index: usize) {
+    LAZY_WORKERS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[threads(3)]
+#[lazy_workers]
+#[worker_init(count_lazy_worker)]
+#[wait_for_workers]
+async fn with_lazy_workers(ex: &Executor<'_>) {
+    assert_eq!(LAZY_WORKERS.load(Ordering::SeqCst), 1);
+
+    // Block this thread so that the only worker has to spawn more threads to help out.
+    let (tx, rx) = std::sync::mpsc::channel();
+    for _ in 0..50 {
+        let tx = tx.clone();
+        ex.spawn(async move {
+            tx.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        })
+        .detach();
+    }
+    for _ in 0..50 {
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    let workers = LAZY_WORKERS.load(Ordering::SeqCst);
+    assert!((2..=3).contains(&workers), "{workers} workers");
+}
+
+static SPAWNED_DONE: AtomicBool = AtomicBool::new(false);
+
+smol_macros::main! {
+    #[await_spawned]
+    async fn spawn_and_return(ex: &Executor<'_>) -> u32 {
+        ex.spawn(async {
+            async_io::Timer::after(Duration::from_millis(50)).await;
+            SPAWNED_DONE.store(true, Ordering::SeqCst);
+        })
+        .detach();
+        1 + 1
+    }
+}
+
+#[test]
+fn with_await_spawned() {
+    assert_eq!(spawn_and_return(), 2);
+    assert!(SPAWNED_DONE.load(Ordering::SeqCst));
+}
+
+thread_local! {
+    static LOCAL_SPAWNED_DONE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+smol_macros::main! {
+    #[await_spawned]
+    async fn spawn_and_return_early(ex: &LocalExecutor<'_>) -> Result<(), ()> {
+        ex.spawn(async {
+            async_io::Timer::after(Duration::from_millis(10)).await;
+            LOCAL_SPAWNED_DONE.with(|done| done.set(true));
+        })
+        .detach();
+
+        if !ex.is_empty() {
+            return Ok(());
+        }
+        Err(())
+    }
+}
+
+#[test]
+fn with_await_spawned_early_return() {
+    assert_eq!(spawn_and_return_early(), Ok(()));
+    assert!(LOCAL_SPAWNED_DONE.with(|done| done.get()));
+}
+
+smol_macros::main! {
+    #[drain_timeout(200ms)]
+    async fn drain_spawned(ex: &LocalExecutor<'_>) -> Rc<std::cell::Cell<bool>> {
+        let done = Rc::new(std::cell::Cell::new(false));
+        let flag = done.clone();
+        ex.spawn(async move {
+            async_io::Timer::after(Duration::from_millis(10)).await;
+            flag.set(true);
+        })
+        .detach();
+
+        // A task that never completes is dropped once the timeout elapses.
+        ex.spawn(futures_lite::future::pending::<()>()).detach();
+        done
+    }
+}
+
+smol_macros::main! {
+    #[drain_timeout(Duration::ZERO)]
+    async fn drain_nothing(ex: &LocalExecutor<'_>) -> Rc<std::cell::Cell<bool>> {
+        let done = Rc::new(std::cell::Cell::new(false));
+        let flag = done.clone();
+        ex.spawn(async move { flag.set(true) }).detach();
+        done
+    }
+}
+
+#[test]
+fn with_drain_timeout() {
+    let start = std::time::Instant::now();
+    assert!(drain_spawned().get());
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    assert!(!drain_nothing().get());
+}
+
+smol_macros::try_main! {
+    async fn try_compute(ex: &Executor<'_>) -> u32 {
+        ex.spawn(async { 1 + 1 }).await
+    }
+}
+
+smol_macros::try_main! {
+    #[shared_executor]
+    async fn try_compute_shared(ex: &Executor<'static>) {
+        ex.spawn(async {}).await;
+    }
+}
+
+smol_macros::try_main! {
+    async fn try_compute_no_executor() -> u32 {
+        1 + 1
+    }
+}
+
+#[test]
+fn with_try_main() {
+    assert_eq!(try_compute().unwrap(), 2);
+    try_compute_shared().unwrap();
+    assert_eq!(try_compute_no_executor().unwrap(), 2);
+}
+
+#[cfg(feature = "tokio-compat")]
+#[apply(test!)]
+#[tokio_compat]
+async fn with_tokio_compat(ex: &LocalExecutor<'_>) {
+    tokio::runtime::Handle::current();
+    ex.spawn(async { tokio::runtime::Handle::current() }).await;
+}
+
+#[cfg(feature = "tokio-compat")]
+#[apply(test!)]
+#[tokio_compat]
+async fn with_tokio_compat_no_executor() {
+    tokio::runtime::Handle::current();
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[cpu_set(0, 0, 0)]
+#[worker_roles("timer", "compute")]
+async fn with_worker_roles(ex: &Executor<'_>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let barrier = Arc::new(std::sync::Barrier::new(3));
+
+    // Block all three worker threads at once so that each of them reports its name.
+    for _ in 0..3 {
+        let tx = tx.clone();
+        let barrier = barrier.clone();
+        ex.spawn(async move {
+            tx.send(std::thread::current().name().map(String::from))
+                .unwrap();
+            barrier.wait();
+        })
+        .detach();
+    }
+
+    let mut names = (0..3)
+        .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["compute-0", "compute-1", "timer"]);
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[cpu_set(0, 0)]
+#[no_thread_names]
+async fn with_no_thread_names(ex: &Executor<'_>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let barrier = Arc::new(std::sync::Barrier::new(2));
+
+    for _ in 0..2 {
+        let tx = tx.clone();
+        let barrier = barrier.clone();
+        ex.spawn(async move {
+            tx.send(std::thread::current().name().map(String::from))
+                .unwrap();
+            barrier.wait();
+        })
+        .detach();
+    }
+
+    for _ in 0..2 {
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), None);
+    }
+}
+
+#[cfg(not(feature = "single-thread"))]
+thread_local! {
+    static WORKER_INDEX: std::cell::Cell<Option<usize>> = const { std::cell::Cell::new(None) };
+}
+
+#[cfg(not(feature = "single-thread"))]
+fn set_worker_index(index: usize) {
+    WORKER_INDEX.with(|worker| worker.set(Some(index)));
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[cpu_set(0, 0)]
+#[worker_init(set_worker_index)]
+async fn with_worker_init(ex: &Executor<'_>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let barrier = Arc::new(std::sync::Barrier::new(2));
+
+    for _ in 0..2 {
+        let tx = tx.clone();
+        let barrier = barrier.clone();
+        ex.spawn(async move {
+            tx.send(WORKER_INDEX.with(|worker| worker.get())).unwrap();
+            barrier.wait();
+        })
+        .detach();
+    }
+
+    let mut indices = (0..2)
+        .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+        .collect::<Vec<_>>();
+    indices.sort();
+    assert_eq!(indices, [Some(0), Some(1)]);
+    assert_eq!(WORKER_INDEX.with(|worker| worker.get()), None);
+}
+
+#[apply(test!)]
+#[local]
+async fn with_local_attribute(ex: &LocalExecutor<'_>) {
+    assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+}
+
+#[apply(test!)]
+#[local]
+#[single_threaded(false)]
+async fn with_local_any_executor(ex: &AnyExecutor<'_>) {
+    assert!(ex.is_local());
+}
+
+/// A custom exit status.
+struct Status(u8);
+
+impl std::process::Termination for Status {
+    fn report(self) -> std::process::ExitCode {
+        self.0.into()
+    }
+}
+
+#[apply(test!)]
+async fn returns_termination() -> impl std::process::Termination {
+    Status(0)
+}
+
+#[apply(test!)]
+async fn returns_termination_executor(ex: &Executor<'_>) -> impl std::process::Termination {
+    Status(ex.spawn(async { 0 }).await)
+}
+
+smol_macros::main! {
+    async fn status_with_executor(ex: &LocalExecutor<'_>) -> impl std::process::Termination {
+        Status(ex.spawn(async { 1 }).await)
+    }
+}
+
+smol_macros::try_main! {
+    async fn try_status() -> impl std::process::Termination {
+        Status(1)
+    }
+}
+
+#[test]
+fn with_termination() {
+    use std::process::{ExitCode, Termination};
+
+    assert_eq!(
+        format!("{:?}", status_with_executor().report()),
+        format!("{:?}", ExitCode::from(1))
+    );
+    assert_eq!(
+        format!("{:?}", try_status().unwrap().report()),
+        format!("{:?}", ExitCode::from(1))
+    );
+}
+
+#[apply(test!)]
+#[resilient_workers(3)]
+async fn with_resilient_workers(ex: &Executor<'_>) {
+    // Task panics are caught by the executor, so they don't take down the worker either way.
+    let task = ex.spawn(async { panic!("task panicked") });
+    assert!(AssertUnwindSafe(task).catch_unwind().await.is_err());
+    assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+}
+
+#[apply(test!)]
+#[resilient_workers]
+async fn with_resilient_workers_default(ex: &Executor<'_>) {
+    assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+}
+
+#[apply(test!)]
+#[main_poll_budget(1)]
+async fn with_main_poll_budget(ex: &LocalExecutor<'_>) {
+    let tasks = (0..10)
+        .map(|i| ex.spawn(async move { i }))
+        .collect::<Vec<_>>();
+
+    let mut sum = 0;
+    for task in tasks {
+        sum += task.await;
+    }
+    assert_eq!(sum, 45);
+}
+
+#[apply(test!)]
+#[main_poll_budget(4)]
+async fn with_main_poll_budget_any_executor(ex: &AnyExecutor<'_>) {
+    assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+}
+
+#[apply(test!)]
+#[display_errors]
+async fn with_display_errors(ex: &Executor<'_>) -> Result<(), std::num::ParseIntError> {
+    assert_eq!(ex.spawn(async { "2".parse::<u32>() }).await?, 2);
+    Ok(())
+}
+
+smol_macros::main! {
+    #[display_errors]
+    async fn display_error() -> Result<(), std::num::ParseIntError> {
+        "two".parse::<u32>()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn with_display_errors_failure() {
+    use std::process::{ExitCode, Termination};
+
+    assert_eq!(
+        format!("{:?}", display_error().report()),
+        format!("{:?}", ExitCode::FAILURE)
+    );
+}
+
+static BACKGROUND_BARRIER: Barrier = Barrier::new(2);
+
+/// Set by the background task once it is cancelled.
+static BACKGROUND_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Sets a flag when dropped.
+struct SetOnDrop(&'static AtomicBool);
+
+impl Drop for SetOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+smol_macros::main! {
+    #[background(async {
+        let _guard = SetOnDrop(&BACKGROUND_CANCELLED);
+        BACKGROUND_BARRIER.wait().await;
+        std::future::pending::<()>().await;
+    })]
+    #[await_spawned]
+    async fn run_with_background(_ex: &Executor<'_>) -> u32 {
+        BACKGROUND_BARRIER.wait().await;
+        1 + 1
+    }
+}
+
+#[test]
+fn with_background() {
+    assert_eq!(run_with_background(), 2);
+    assert!(BACKGROUND_CANCELLED.load(Ordering::SeqCst));
+}
+
+#[apply(test!)]
+#[spin_wait]
+async fn with_spin_wait(ex: &Executor<'_>) {
+    async_io::Timer::after(Duration::from_millis(1)).await;
+    assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+}
+
+#[apply(test!)]
+#[spin_wait]
+async fn with_spin_wait_no_executor() {
+    async_io::Timer::after(Duration::from_millis(1)).await;
+}
+
+#[apply(test!)]
+#[expected(4)]
+async fn with_expected(ex: &Executor<'_>) -> u32 {
+    ex.spawn(async { 2 + 2 }).await
+}
+
+#[apply(test!)]
+#[expected("hello")]
+async fn with_expected_no_executor() -> &'static str {
+    "hello"
+}
+
+#[apply(test!)]
+#[expected(5)]
+#[should_panic = "assertion `left == right` failed"]
+async fn with_expected_mismatch(ex: &LocalExecutor<'_>) -> u32 {
+    ex.spawn(async { 2 + 2 }).await
+}
+
+static CUSTOM_BLOCK_ON: AtomicBool = AtomicBool::new(false);
+
+fn custom_block_on<T>(future: impl Future<Output = T>) -> T {
+    CUSTOM_BLOCK_ON.store(true, Ordering::SeqCst);
+    futures_lite::future::block_on(future)
+}
+
+#[apply(test!)]
+#[block_on(custom_block_on)]
+async fn with_block_on(ex: &LocalExecutor<'_>) {
+    assert!(CUSTOM_BLOCK_ON.load(Ordering::SeqCst));
+    assert!(smol_macros::main_executor::in_runtime());
+    ex.spawn(async {}).await;
+}
+
+#[apply(test!)]
+#[block_on(futures_lite::future::block_on)]
+#[expected(1)]
+async fn with_block_on_path() -> u32 {
+    async { 1 }.await
+}
+
+static INIT_ORDER: AtomicUsize = AtomicUsize::new(0);
+
+/// An executor that checks that it is set up after the `#[init]` hooks ran.
+struct OrderedExecutor(LocalExecutor<'static>);
+
+impl std::ops::Deref for OrderedExecutor {
+    type Target = LocalExecutor<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl smol_macros::main_executor::MainExecutor for OrderedExecutor {
+    fn with_main<T, F: FnOnce(&Self) -> T>(
+        _config: &smol_macros::main_executor::Config,
+        f: F,
+    ) -> T {
+        assert_eq!(INIT_ORDER.fetch_add(1, Ordering::SeqCst), 2);
+        f(&OrderedExecutor(LocalExecutor::new()))
+    }
+}
+
+#[apply(test!)]
+#[init(|| assert_eq!(INIT_ORDER.fetch_add(1, Ordering::SeqCst), 0))]
+#[init(|| assert_eq!(INIT_ORDER.fetch_add(1, Ordering::SeqCst), 1))]
+async fn with_init(ex: &OrderedExecutor) {
+    assert_eq!(INIT_ORDER.fetch_add(1, Ordering::SeqCst), 3);
+    ex.spawn(async {}).await;
+}
+
+#[cfg(not(feature = "single-thread"))]
+static WORKER_STOPPED_FIRST: AtomicBool = AtomicBool::new(false);
+
+#[cfg(not(feature = "single-thread"))]
+smol_macros::main! {
+    #[cpu_set(0)]
+    #[stop_on_panic]
+    async fn panic_with_worker(ex: &Executor<'_>) {
+        /// Sends a message once the worker thread exits.
+        struct OnExit(std::sync::mpsc::Sender<()>);
+
+        impl Drop for OnExit {
+            fn drop(&mut self) {
+                self.0.send(()).ok();
+            }
+        }
+
+        thread_local! {
+            static ON_EXIT: std::cell::RefCell<Option<OnExit>> = const { std::cell::RefCell::new(None) };
+        }
+
+        /// Checks whether the worker thread exited before the locals of the body were dropped.
+        struct CheckWorker(std::sync::mpsc::Receiver<()>);
+
+        impl Drop for CheckWorker {
+            fn drop(&mut self) {
+                let stopped = self.0.recv_timeout(Duration::from_secs(5)).is_ok();
+                WORKER_STOPPED_FIRST.store(stopped, Ordering::SeqCst);
+            }
+        }
+
+        // Block this thread so that the task has to run on the worker thread.
+        let (tx, rx) = std::sync::mpsc::channel();
+        let _check = CheckWorker(rx);
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        ex.spawn(async move {
+            ON_EXIT.with(|on_exit| *on_exit.borrow_mut() = Some(OnExit(tx)));
+            started_tx.send(()).unwrap();
+        })
+        .detach();
+        started_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        panic!("main panicked");
+    }
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[test]
+fn with_stop_on_panic() {
+    let result = std::panic::catch_unwind(panic_with_worker);
+    assert!(result.is_err());
+    assert!(WORKER_STOPPED_FIRST.load(Ordering::SeqCst));
+}
+
+#[cfg(not(feature = "single-thread"))]
+static BODY_RETURNED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(not(feature = "single-thread"))]
+fn panic_after_body(_index: usize) {
+    // Panics in tasks are caught by the executor, but this hook only runs on the worker thread.
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while !BODY_RETURNED.load(Ordering::SeqCst) && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    panic!("worker panicked");
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[threads(1)]
+#[worker_init(panic_after_body)]
+#[should_panic(expected = "worker panicked")]
+async fn worker_panic_after_success(ex: &Executor<'_>) {
+    ex.spawn(async {}).await;
+    BODY_RETURNED.store(true, Ordering::SeqCst);
+}
+
+#[cfg(not(feature = "single-thread"))]
+static WORKER_PANICKING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(not(feature = "single-thread"))]
+fn panic_in_worker(_index: usize) {
+    WORKER_PANICKING.store(true, Ordering::SeqCst);
+    panic!("worker panicked");
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[threads(1)]
+#[worker_init(panic_in_worker)]
+#[should_panic(expected = "main panicked")]
+async fn main_panic_beats_worker_panic(_ex: &Executor<'_>) {
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while !WORKER_PANICKING.load(Ordering::SeqCst) && std::time::Instant::now() < deadline {
+        async_io::Timer::after(Duration::from_millis(1)).await;
+    }
+    panic!("main panicked");
+}
+
+#[apply(test!)]
+#[debug_on_panic]
+#[should_panic(expected = "left behind")]
+async fn with_debug_on_panic(ex: &Executor<'_>) {
+    ex.spawn(futures_lite::future::pending::<()>()).detach();
+    panic!("left behind");
+}
+
+#[cfg(not(feature = "single-thread"))]
+static TIMED_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(not(feature = "single-thread"))]
+fn record_timings(timings: smol_macros::main_executor::LifecycleTimings) {
+    TIMED_THREADS.store(timings.threads, Ordering::SeqCst);
+}
+
+#[cfg(not(feature = "single-thread"))]
+smol_macros::main! {
+    #[threads(3)]
+    #[time_lifecycle(record_timings)]
+    async fn timed_lifecycle(_ex: &Executor<'_>) {
+        assert_eq!(TIMED_THREADS.load(Ordering::SeqCst), 0);
+    }
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[test]
+fn with_time_lifecycle() {
+    timed_lifecycle();
+    assert_eq!(TIMED_THREADS.load(Ordering::SeqCst), 3);
+}
+
+#[apply(test!)]
+#[time_lifecycle]
+async fn with_time_lifecycle_printed(_ex: &Executor<'_>) {}
+
+static REPEATED: AtomicUsize = AtomicUsize::new(0);
+
+#[apply(test!)]
+#[repeat(10)]
+async fn with_repeat(ex: &Executor<'_>) {
+    REPEATED.fetch_add(1, Ordering::SeqCst);
+    assert!(ex.is_empty());
+    ex.spawn(async {}).detach();
+}
+
+#[test]
+fn repeat_runs_every_iteration() {
+    with_repeat();
+    assert!(REPEATED.load(Ordering::SeqCst) >= 10);
+}
+
+#[apply(test!)]
+#[repeat(5)]
+#[expected(2)]
+async fn with_repeat_expected(ex: &LocalExecutor<'_>) -> u32 {
+    ex.spawn(async { 1 + 1 }).await
+}
+
+static REPEATED_ERR: AtomicUsize = AtomicUsize::new(0);
+
+#[apply(test!)]
+#[repeat(5)]
+#[should_panic = "iteration 3 of 5 failed"]
+async fn with_repeat_error() -> Result<(), String> {
+    match REPEATED_ERR.fetch_add(1, Ordering::SeqCst) {
+        2 => Err("third".into()),
+        _ => Ok(()),
+    }
+}
+
+static RETRIED: AtomicUsize = AtomicUsize::new(0);
+
+#[apply(test!)]
+#[retry(times = 3, on = std::io::Error)]
+async fn with_retry(ex: &Executor<'_>) -> std::io::Result<()> {
+    match RETRIED.fetch_add(1, Ordering::SeqCst) {
+        0 | 1 => Err(std::io::ErrorKind::ConnectionReset.into()),
+        _ => ex.spawn(async { Ok(()) }).await,
+    }
+}
+
+static RETRIED_BOXED: AtomicUsize = AtomicUsize::new(0);
+
+smol_macros::main! {
+    #[retry(times = 2, on = std::io::Error)]
+    async fn retry_transient() -> Result<(), Box<dyn std::error::Error>> {
+        RETRIED_BOXED.fetch_add(1, Ordering::SeqCst);
+        Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into())
+    }
+}
+
+smol_macros::main! {
+    #[retry(times = 2, on = std::io::Error)]
+    async fn retry_other() -> Result<(), Box<dyn std::error::Error>> {
+        RETRIED_BOXED.fetch_add(1, Ordering::SeqCst);
+        "not a number".parse::<u32>()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn retry_only_on_matching_errors() {
+    assert!(retry_other().is_err());
+    assert_eq!(RETRIED_BOXED.swap(0, Ordering::SeqCst), 1);
+
+    let err = retry_transient().unwrap_err();
+    assert!(err.is::<std::io::Error>());
+    assert_eq!(RETRIED_BOXED.swap(0, Ordering::SeqCst), 2);
+}
+
+#[cfg(all(target_os = "linux", not(feature = "single-thread")))]
+#[apply(test!)]
+#[reactor_thread]
+async fn with_reactor_thread(_ex: &Executor<'_>) {
+    async_io::Timer::after(Duration::from_millis(10)).await;
+
+    // Linux truncates thread names to 15 bytes.
+    let names = std::fs::read_dir("/proc/self/task")
+        .unwrap()
+        .map(|task| std::fs::read_to_string(task.unwrap().path().join("comm")).unwrap_or_default())
+        .collect::<Vec<_>>();
+    assert!(names.iter().any(|name| name.trim() == "smol-macros-rea"));
+}
+
+#[cfg(not(feature = "single-thread"))]
+static STARTED_WORKERS: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(not(feature = "single-thread"))]
+fn record_started_worker(_index: usize) {
+    // Give the body a chance to start before the worker is ready.
+    std::thread::sleep(Duration::from_millis(50));
+    STARTED_WORKERS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[threads(2)]
+#[worker_init(record_started_worker)]
+#[wait_for_workers]
+async fn with_wait_for_workers(ex: &Executor<'_>) {
+    assert_eq!(STARTED_WORKERS.load(Ordering::SeqCst), 2);
+
+    // The task runs on a worker thread while the body blocks its own thread.
+    let ran = Arc::new(AtomicBool::new(false));
+    ex.spawn({
+        let ran = ran.clone();
+        async move { ran.store(true, Ordering::SeqCst) }
+    })
+    .detach();
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while !ran.load(Ordering::SeqCst) {
+        assert!(std::time::Instant::now() < deadline, "the task didn't run");
+        std::thread::yield_now();
+    }
+}
+
+#[cfg(not(feature = "single-thread"))]
+static SPAWNED_WORKERS: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(not(feature = "single-thread"))]
+fn record_spawned_worker(_index: usize) {
+    SPAWNED_WORKERS.fetch_add(1, Ordering::SeqCst);
+}
+
+// The first worker thread spawns the others, so with this many of them, the body is done long
+// before the last one would be spawned.
+#[cfg(not(feature = "single-thread"))]
+smol_macros::main! {
+    #[threads(256)]
+    #[worker_init(record_spawned_worker)]
+    async fn quick_main(_ex: &Executor<'_>) {}
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[test]
+fn quick_main_skips_workers() {
+    quick_main();
+    assert!(SPAWNED_WORKERS.load(Ordering::SeqCst) < 256);
+}
+
+#[cfg(target_os = "linux")]
+#[apply(test!)]
+#[main_thread_name("smol-main")]
+async fn with_main_thread_name() {
+    let name = std::fs::read_to_string("/proc/thread-self/comm").unwrap();
+    assert_eq!(name.trim(), "smol-main");
+}
+
+#[apply(test!)]
+#[shutdown_on(async { 7 })]
+#[expected(7)]
+async fn with_shutdown_on(_ex: &Executor<'_>) -> u32 {
+    futures_lite::future::pending().await
+}
+
+#[apply(test!)]
+#[shutdown_on(futures_lite::future::pending())]
+#[stop_on_panic]
+#[expected(3)]
+async fn with_shutdown_on_not_triggered() -> u32 {
+    futures_lite::future::yield_now().await;
+    3
+}
+
+#[apply(test!)]
+#[shutdown_on(async {
+    async_io::Timer::after(Duration::from_millis(10)).await;
+    Ok(())
+})]
+async fn with_shutdown_on_result() -> Result<(), String> {
+    futures_lite::future::pending::<()>().await;
+    Err("the body wasn't stopped".into())
+}
+
+#[apply(test!)]
+#[timeout(20ms)]
+#[should_panic = "`macro_usages::with_timeout` timed out after"]
+async fn with_timeout(_ex: &Executor<'_>) {
+    futures_lite::future::pending::<()>().await;
+}
+
+#[apply(test!)]
+#[timeout(Duration::from_secs(60))]
+async fn with_timeout_not_reached() {
+    async_io::Timer::after(Duration::from_millis(1)).await;
+}
+
+struct App;
+
+impl App {
+    fn answer() -> u32 {
+        1 + 1
+    }
+
+    #[apply(smol_macros::main!)]
+    async fn run(ex: &Executor<'_>) -> u32 {
+        ex.spawn(async { Self::answer() }).await
+    }
+
+    #[apply(smol_macros::try_main!)]
+    async fn try_run() -> u32 {
+        3
+    }
+
+    #[apply(smol_macros::main!)]
+    #[repeat(2)]
+    async fn run_repeated(_ex: &LocalExecutor<'_>) {}
+}
+
+#[test]
+fn with_associated_function() {
+    assert_eq!(App::run(), 2);
+    assert_eq!(App::try_run().unwrap(), 3);
+    App::run_repeated();
+}
+
+#[apply(smol_macros::main!)]
+#[async_fn(sum_async)]
+async fn sum(ex: &Executor<'_>) -> u32 {
+    ex.spawn(async { 1 }).await + ex.spawn(async { 2 }).await
+}
+
+#[apply(smol_macros::main!)]
+#[async_fn(answer_async)]
+async fn answer() -> u32 {
+    42
+}
+
+#[apply(test!)]
+async fn with_async_fn(ex: &Executor<'_>) {
+    assert_eq!(sum(), 3);
+    assert_eq!(sum_async(ex).await, 3);
+    assert_eq!(answer_async().await, answer());
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[threads(3)]
+async fn with_threads(_ex: &Executor<'_>) {
+    assert_eq!(smol_macros::main_executor::worker_threads(), 3);
+}
+
+#[apply(test!)]
+#[current_thread]
+async fn with_current_thread(ex: &Executor<'_>) {
+    assert_eq!(smol_macros::main_executor::worker_threads(), 0);
+
+    let main_thread = std::thread::current().id();
+    let tasks = (0..8)
+        .map(|_| ex.spawn(async { std::thread::current().id() }))
+        .collect::<Vec<_>>();
+    for task in tasks {
+        assert_eq!(task.await, main_thread);
+    }
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[threads(2)]
+#[fallback_local]
+async fn with_fallback_local(ex: &Executor<'_>) {
+    // The threads can be spawned, so the fallback isn't needed.
+    assert_eq!(smol_macros::main_executor::worker_threads(), 2);
+    assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+}
+
+#[apply(test!)]
+#[collect_results]
+async fn with_collect_results(
+    ex: &Executor<'_>,
+    tasks: &smol_macros::TaskSet<u32>,
+) -> Result<(), std::num::ParseIntError> {
+    tasks.spawn(async { 1 });
+    let _ = ex;
+    let _: u32 = "2".parse()?;
+    Ok(())
+}
+
+static STOPPED_SERVICES: std::sync::Mutex<Vec<&str>> = std::sync::Mutex::new(Vec::new());
+
+smol_macros::main! {
+    #[with_services]
+    async fn run_services(ex: &Executor<'_>, services: &smol_macros::ServiceSet<'_, '_>) -> usize {
+        for (priority, name) in [(1, "pool"), (0, "server"), (1, "cache"), (2, "metrics")] {
+            services.spawn(priority, move |token| async move {
+                token.cancelled().await;
+                STOPPED_SERVICES.lock().unwrap().push(name);
+            });
+        }
+        ex.spawn(async {}).await;
+        services.len()
+    }
+}
+
+#[test]
+fn with_services() {
+    assert_eq!(run_services(), 4);
+    assert_eq!(
+        *STOPPED_SERVICES.lock().unwrap(),
+        ["server", "cache", "pool", "metrics"]
+    );
+}
+
+#[apply(test!)]
+#[stream]
+async fn with_stream(ex: &Executor<'_>) {
+    let seen = Arc::new(AtomicUsize::new(0));
+    let total = seen.clone();
+    futures_lite::stream::iter(1..=3)
+        .then(move |i| {
+            let seen = seen.clone();
+            ex.spawn(async move { seen.fetch_add(i, Ordering::SeqCst) })
+        })
+        .chain(futures_lite::stream::once_future(async move {
+            assert_eq!(total.load(Ordering::SeqCst), 6);
+            0
+        }))
+}
+
+mod streams {
+    use super::*;
+    use std::num::ParseIntError;
+
+    smol_macros::main! {
+        #[stream]
+        #[async_fn(parse_all_async)]
+        async fn parse_all(ex: &Executor<'_>, records: Vec<&'static str>) -> Result<(), ParseIntError> {
+            futures_lite::stream::iter(records).then(move |record| {
+                ex.spawn(async move { record.parse::<u32>() })
+            })
+        }
+    }
+
+    #[test]
+    fn with_stream_result() {
+        assert!(parse_all(vec!["1", "2"]).is_ok());
+        assert!(parse_all(vec!["1", "x", "2"]).is_err());
+    }
+
+    #[apply(test!)]
+    async fn with_stream_async_fn(ex: &Executor<'_>) {
+        assert!(parse_all_async(ex, vec!["1", "x"]).await.is_err());
+    }
+}
+
+#[apply(test!)]
+#[expect_timeout(20ms)]
+async fn with_expect_timeout(ex: &Executor<'_>) -> Result<(), std::num::ParseIntError> {
+    ex.spawn(futures_lite::future::pending::<()>()).await;
+    Ok(())
+}
+
+#[apply(test!)]
+#[expect_timeout(Duration::from_secs(5))]
+#[should_panic(expected = "but was expected to run for at least 5s")]
+async fn with_expect_timeout_completed() {}
+
+smol_macros::build_main! {
+    #[shutdown_timeout(Duration::from_secs(5))]
+    async fn build_script(ex: &Executor<'_>) -> usize {
+        ex.spawn(async { smol_macros::main_executor::worker_threads() }).await
+    }
+}
+
+#[test]
+fn with_build_main() {
+    let expected = if cfg!(feature = "single-thread") {
+        0
+    } else {
+        1
+    };
+    assert_eq!(build_script(), expected);
+}
+
+smol_macros::main! {
+    async fn exit_with(ex: &Executor<'_>, fatal: bool) -> smol_macros::ExitReason {
+        if ex.spawn(async move { fatal }).await {
+            return smol_macros::ExitReason::Fatal;
+        }
+        smol_macros::ExitReason::Normal
+    }
+}
+
+#[test]
+fn with_exit_reason() {
+    use smol_macros::ExitReason;
+    use std::process::{ExitCode, Termination};
+
+    assert_eq!(exit_with(false), ExitReason::Normal);
+    assert_eq!(exit_with(true), ExitReason::Fatal);
+
+    let code = |reason: ExitReason| format!("{:?}", reason.report());
+    assert_eq!(code(ExitReason::Normal), format!("{:?}", ExitCode::SUCCESS));
+    assert_eq!(
+        code(ExitReason::ShutdownRequested),
+        format!("{:?}", ExitCode::SUCCESS)
+    );
+    assert_eq!(code(ExitReason::Fatal), format!("{:?}", ExitCode::FAILURE));
+}
+
+/// Spawn a few tasks at once and return the order in which they ran.
+async fn spawn_order(ex: &ScheduledExecutor<'_>) -> Vec<usize> {
+    let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let tasks = (0..3)
+        .map(|i| {
+            let order = order.clone();
+            ex.spawn(async move { order.lock().unwrap().push(i) })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        task.await;
+    }
+
+    let order = order.lock().unwrap();
+    order.clone()
+}
+
+#[apply(test!)]
+#[current_thread]
+#[schedule(lifo)]
+async fn with_schedule_lifo(ex: &ScheduledExecutor<'_>) {
+    assert_eq!(ex.schedule(), Schedule::Lifo);
+    assert_eq!(spawn_order(ex).await, [2, 1, 0]);
+}
+
+#[apply(test!)]
+#[current_thread]
+#[schedule(fifo)]
+async fn with_schedule_fifo(ex: &ScheduledExecutor<'_>) {
+    assert_eq!(spawn_order(ex).await, [0, 1, 2]);
+}
+
+#[apply(test!)]
+async fn scheduled_executor_default(ex: &ScheduledExecutor<'_>) {
+    assert_eq!(ex.schedule(), Schedule::Fifo);
+    let tasks = (0..100)
+        .map(|i| ex.spawn(async move { i * 2 }))
+        .collect::<Vec<_>>();
+    for (i, task) in tasks.into_iter().enumerate() {
+        assert_eq!(task.await, i * 2);
+    }
+}
+
+/// Blocks without the `async-io` reactor, and lets timers elapse right away.
+struct InstantReactor;
+
+thread_local! {
+    static IN_INSTANT_REACTOR: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+impl Reactor for InstantReactor {
+    type Sleep = futures_lite::future::Ready<()>;
+
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        IN_INSTANT_REACTOR.with(|flag| flag.set(true));
+        let output = futures_lite::future::block_on(future);
+        IN_INSTANT_REACTOR.with(|flag| flag.set(false));
+        output
+    }
+
+    fn sleep(_duration: Duration) -> Self::Sleep {
+        futures_lite::future::ready(())
+    }
+}
+
+#[apply(test!)]
+#[reactor(InstantReactor)]
+async fn with_reactor(ex: &Executor<'_>) {
+    assert!(IN_INSTANT_REACTOR.with(|flag| flag.get()));
+    assert!(
+        ex.spawn(async { IN_INSTANT_REACTOR.with(|flag| flag.get()) })
+            .await
+    );
+}
+
+#[apply(test!)]
+#[reactor(InstantReactor)]
+#[timeout(1h)]
+#[should_panic(expected = "timed out")]
+async fn with_reactor_timeout() {
+    futures_lite::future::pending::<()>().await;
+}
+
+#[cfg(not(feature = "single-thread"))]
+fn shared_config() -> smol_macros::main_executor::Config {
+    smol_macros::main_executor::Config::new().threads(2)
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[config(shared_config())]
+async fn with_config(_ex: &Executor<'_>) {
+    assert_eq!(smol_macros::main_executor::worker_threads(), 2);
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[threads(3)]
+#[config(&shared_config())]
+async fn with_config_overridden(_ex: &Executor<'_>) {
+    assert_eq!(smol_macros::main_executor::worker_threads(), 3);
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[threads(fraction = 0.5, min = 2)]
+async fn with_threads_fraction(_ex: &Executor<'_>) {
+    let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+    let expected = ((cpus as f64 * 0.5).round() as usize).max(2);
+    assert_eq!(smol_macros::main_executor::worker_threads(), expected);
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[threads(env = "SMOL_MACROS_TEST_THREADS_UNSET")]
+#[cpu_set(0, 0)]
+async fn with_threads_env_unset(_ex: &Executor<'_>) {
+    assert_eq!(smol_macros::main_executor::worker_threads(), 2);
+}
+
+#[apply(test!)]
+#[should_panic = "the fraction of threads must be greater than 0 and at most 1, got 1.5"]
+#[threads(fraction = 1.5)]
+async fn with_threads_invalid_fraction(_ex: &Executor<'_>) {}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[parallelism_fallback(4)]
+async fn with_parallelism_fallback(_ex: &Executor<'_>) {
+    // The fallback is only used when the number of CPUs can't be determined.
+    let cpus = std::thread::available_parallelism().unwrap().get();
+    assert_eq!(smol_macros::main_executor::worker_threads(), cpus);
+}
+
+#[apply(test!)]
+#[should_panic = "the fallback number of CPUs must be at least 1"]
+#[parallelism_fallback(0)]
+async fn with_parallelism_fallback_zero(_ex: &Executor<'_>) {}
+
+#[apply(test!)]
+#[boxed_main]
+async fn with_boxed_main(ex: &Executor<'_>) {
+    let large = [1u8; 64 * 1024];
+    futures_lite::future::yield_now().await;
+    let sum = ex.spawn(async move { large.iter().map(|&x| x as u32).sum::<u32>() });
+    assert_eq!(sum.await, 64 * 1024);
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[reuse_main_thread]
+#[threads(3)]
+async fn with_reuse_main_thread(ex: &Executor<'_>) {
+    // Two worker threads are spawned, and the current thread is the third.
+    assert_eq!(smol_macros::main_executor::worker_threads(), 3);
+    let tasks = (0..64)
+        .map(|_| ex.spawn(async { std::thread::current().name().map(String::from) }))
+        .collect::<Vec<_>>();
+    for task in tasks {
+        assert_ne!(task.await.as_deref(), Some("smol-macros-2"));
+    }
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[should_panic = "boom\nruntime: async_executor::Executor<'_> on 2 worker threads"]
+#[panic_context]
+#[threads(2)]
+async fn with_panic_context(_ex: &Executor<'_>) {
+    panic!("boom");
+}
+
+#[apply(test!)]
+#[should_panic = "boom\nruntime: no executor on the current thread only"]
+#[panic_context]
+async fn with_panic_context_no_executor() {
+    panic!("boom");
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[apply(test!)]
+#[shards(3)]
+#[threads(3)]
+async fn with_shards(shards: &[Executor<'_>]) {
+    assert_eq!(shards.len(), 3);
+
+    // Each shard has a worker thread of its own.
+    for (i, shard) in shards.iter().enumerate() {
+        let name = shard
+            .spawn(async { std::thread::current().name().map(String::from) })
+            .await;
+        if name.as_deref() != Some("main") && name.as_deref() != Some("with_shards") {
+            assert_eq!(name, Some(format!("smol-macros-{i}")));
+        }
+    }
+}
+
+#[apply(test!)]
+#[shards(2)]
+async fn with_shards_tasks(shards: &[Executor<'_>]) {
+    let tasks = shards
+        .iter()
+        .map(|shard| shard.spawn(async { 1 }))
+        .collect::<Vec<_>>();
+    let mut sum = 0;
+    for task in tasks {
+        sum += task.await;
+    }
+    assert_eq!(sum, 2);
+}
+
+#[no_mangle]
+#[apply(smol_macros::main!)]
+async fn smol_macros_exported_entry(ex: &Executor<'_>) -> u32 {
+    ex.spawn(async { 42 }).await
+}
+
+#[test]
+fn with_no_mangle() {
+    extern "Rust" {
+        // Resolved through the exported symbol rather than the path of the function.
+        #[link_name = "smol_macros_exported_entry"]
+        fn exported_entry() -> u32;
+    }
+
+    assert_eq!(unsafe { exported_entry() }, 42);
+}
+
+/// Marks a function as a test, standing in for the attribute of another test framework.
+macro_rules! framework_test {
+    ($($item:tt)*) => {
+        #[test]
+        $($item)*
+    };
+}
+
+#[apply(test!)]
+#[test_attr(apply(framework_test!))]
+async fn with_test_attr(ex: &Executor<'_>) {
+    assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+}
+
+static SERIAL_RUNNING: AtomicBool = AtomicBool::new(false);
+
+async fn run_serially() {
+    assert!(!SERIAL_RUNNING.swap(true, Ordering::SeqCst));
+    async_io::Timer::after(Duration::from_millis(20)).await;
+    SERIAL_RUNNING.store(false, Ordering::SeqCst);
+}
+
+#[apply(test!)]
+#[serial("serial-tests")]
+async fn with_serial_1() {
+    run_serially().await;
+}
+
+#[apply(test!)]
+#[serial("serial-tests")]
+async fn with_serial_2(_ex: &Executor<'_>) {
+    run_serially().await;
+}
+
+#[apply(test!)]
+#[serial]
+async fn with_serial_default_group() {}
+
+#[apply(test!)]
+#[requires(network, filesystem)]
+async fn with_requires_several() {}
+
+smol_macros::main! {
+    #[await_spawned]
+    async fn cancel_on_return(
+        ex: &Executor<'_>,
+        token: CancellationToken,
+    ) -> Arc<AtomicBool> {
+        let stopped = Arc::new(AtomicBool::new(false));
+        assert!(!token.is_cancelled());
+
+        let flag = stopped.clone();
+        ex.spawn(async move {
+            token.cancelled().await;
+            flag.store(true, Ordering::SeqCst);
+        })
+        .detach();
+        stopped
+    }
+}
+
+#[test]
+fn with_cancellation_token() {
+    assert!(cancel_on_return().load(Ordering::SeqCst));
+}
+
+smol_macros::main! {
+    #[await_spawned]
+    #[shutdown_on(async {})]
+    async fn cancel_on_shutdown(ex: &Executor<'_>, token: CancellationToken) {
+        // The body is dropped right away, which cancels the token for the task.
+        ex.spawn(async move { token.cancelled().await }).detach();
+        futures_lite::future::pending::<()>().await;
+    }
+}
+
+#[test]
+fn with_cancellation_token_shutdown() {
+    // This would hang if the token weren't cancelled.
+    cancel_on_shutdown();
+}
+
+trait Scenario: Send + 'static {
+    fn input(&self) -> u32;
+}
+
+struct Double(u32);
+
+impl Scenario for Double {
+    fn input(&self) -> u32 {
+        self.0 * 2
+    }
+}
+
+smol_macros::main! {
+    async fn generic_main<S: Scenario>(ex: &Executor<'_>, scenario: S) -> u32 {
+        ex.spawn(async move { scenario.input() }).await
+    }
+}
+
+smol_macros::main! {
+    async fn where_main<T, B: Into<Vec<u8>>>(ex: &Executor<'_>, items: Vec<T>, bytes: B) -> usize
+    where
+        T: Clone + Send + 'static,
+        Vec<T>: Default,
+    {
+        let items = ex.spawn(async move { items.to_vec() }).await;
+        items.len() + bytes.into().len()
+    }
+}
+
+smol_macros::main! {
+    async fn default_main<T: Default>() -> T {
+        T::default()
+    }
+}
+
+#[test]
+fn with_generics() {
+    assert_eq!(generic_main(Double(21)), 42);
+    assert_eq!(where_main(vec!['a', 'b'], "abc"), 5);
+    assert_eq!(default_main::<u32>(), 0);
+}
+
+smol_macros::main! {
+    #[max_concurrent_tasks(2)]
+    async fn limited(ex: &Executor<'_>, limit: &TaskLimit<'_, '_>) -> usize {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let mut tasks = Vec::new();
+        for _ in 0..8 {
+            let running = running.clone();
+            let peak = peak.clone();
+            tasks.push(
+                limit
+                    .spawn(async move {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        async_io::Timer::after(Duration::from_millis(5)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                    .await,
+            );
+        }
+        for task in tasks {
+            task.await;
+        }
+
+        // All permits are returned once the tasks complete.
+        assert!(limit.try_spawn(async {}).is_ok());
+        assert!(limit.try_spawn(async {}).is_ok());
+        ex.spawn(async {}).await;
+        peak.load(Ordering::SeqCst)
+    }
+}
+
+#[test]
+fn with_max_concurrent_tasks() {
+    assert!(limited() <= 2);
+}
+
+#[apply(test!)]
+#[current_thread]
+#[max_queued_tasks(2)]
+async fn with_max_queued_tasks(ex: &Executor<'_>, queue: &smol_macros::QueueLimit<'_, '_>) {
+    let (sender, receiver) = async_channel::unbounded::<()>();
+    let mut tasks = Vec::new();
+    for _ in 0..2 {
+        let receiver = receiver.clone();
+        tasks.push(
+            queue
+                .try_spawn(async move { receiver.recv().await.ok() })
+                .ok()
+                .unwrap(),
+        );
+    }
+    assert!(queue.try_spawn(async {}).is_err());
+
+    // Once the queued tasks start, more can be spawned, even though they are still running.
+    let third = within(Duration::from_secs(5), queue.spawn(async { 3 })).await;
+    assert_eq!(third.await, 3);
+
+    drop(sender);
+    for task in tasks {
+        assert_eq!(task.await, None);
+    }
+}
+
+#[apply(test!)]
+#[mock_time]
+async fn with_mock_time(ex: &Executor<'_>, clock: &MockClock) {
+    let start = clock.now();
+    let task = ex.spawn({
+        let clock = clock.clone();
+        async move {
+            clock.sleep_until(start + Duration::from_secs(3600)).await;
+            clock.now()
+        }
+    });
+
+    clock.advance(Duration::from_secs(1800));
+    assert!(
+        futures_lite::future::poll_once(clock.sleep(Duration::from_secs(1)))
+            .await
+            .is_none()
+    );
+    clock.advance(Duration::from_secs(1800));
+    assert_eq!(task.await, start + Duration::from_secs(3600));
+    assert_eq!(clock.elapsed(), Duration::from_secs(3600));
+}
+
+#[apply(test!)]
+#[mock_time]
+async fn with_mock_time_only(clock: &MockClock) {
+    let deadline = clock.now() + Duration::from_secs(5);
+    let sleep = clock.sleep_until(deadline);
+    clock.advance(Duration::from_secs(5));
+    sleep.await;
+    assert_eq!(clock.now(), deadline);
+}
+
+static REPORTED: AtomicUsize = AtomicUsize::new(0);
+
+fn count_report(err: &u32) {
+    REPORTED.fetch_add(*err as usize, Ordering::SeqCst);
+}
+
+smol_macros::main! {
+    #[report(count_report)]
+    async fn reported(ex: &Executor<'_>, fail: bool) -> Result<(), u32> {
+        if ex.spawn(async move { fail }).await {
+            Err(3)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn with_report() {
+    assert_eq!(reported(false), Ok(()));
+    assert_eq!(REPORTED.load(Ordering::SeqCst), 0);
+    assert_eq!(reported(true), Err(3));
+    assert_eq!(REPORTED.load(Ordering::SeqCst), 3);
+}
+
+#[apply(test!)]
+#[cfg_attr(miri, ignore)]
+async fn cfg_attr_ignore_under_miri(ex: &Executor<'_>) {
+    assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+}
+
+// This would fail if the generated test weren't ignored.
+#[apply(test!)]
+#[cfg_attr(not(miri), ignore = "only runs under Miri")]
+#[cfg_attr(miri, should_panic)]
+async fn cfg_attr_ignore_outside_miri() {
+    panic!("ran outside of Miri");
+}
+
+smol_macros::main! {
+    #[task_capacity(1000)]
+    async fn with_capacity(ex: &Executor<'_>) -> usize {
+        let tasks: Vec<_> = (0..1000).map(|i| ex.spawn(async move { i })).collect();
+        let mut sum = 0;
+        for task in tasks {
+            sum += task.await;
+        }
+        sum
+    }
+}
+
+#[test]
+fn with_task_capacity() {
+    assert_eq!(with_capacity(), 499_500);
+}
+
+fn spawn_pinned(ex: Pin<&Executor<'_>>) -> async_executor::Task<u32> {
+    ex.get_ref().spawn(async { 1 + 1 })
+}
+
+smol_macros::main! {
+    async fn pinned(ex: Pin<&Executor<'_>>) -> u32 {
+        spawn_pinned(ex).await + ex.spawn(async { 2 }).await
+    }
+}
+
+#[test]
+fn with_pinned_executor() {
+    assert_eq!(pinned(), 4);
+}
+
+static RUNTIME_ORDER: std::sync::Mutex<Vec<&str>> = std::sync::Mutex::new(Vec::new());
+
+fn record_before_runtime() {
+    let workers = smol_macros::main_executor::worker_threads();
+    RUNTIME_ORDER.lock().unwrap().push(if workers == 0 {
+        "before_runtime"
+    } else {
+        "before_runtime with workers"
+    });
+}
+
+fn record_init() {
+    RUNTIME_ORDER.lock().unwrap().push("init");
+}
+
+fn record_worker(_index: usize) {
+    RUNTIME_ORDER.lock().unwrap().push("worker");
+}
+
+smol_macros::main! {
+    #[init(record_init)]
+    #[worker_init(record_worker)]
+    #[threads(1)]
+    #[before_runtime(record_before_runtime)]
+    async fn ordered_runtime(ex: &Executor<'_>) {
+        ex.spawn(async {}).await;
+        RUNTIME_ORDER.lock().unwrap().push("body");
+    }
+}
+
+#[test]
+fn with_before_runtime() {
+    ordered_runtime();
+    let order = RUNTIME_ORDER.lock().unwrap();
+    assert_eq!(order[..2], ["before_runtime", "init"]);
+    assert!(order.contains(&"body"));
+}
+
+#[apply(test!)]
+async fn with_static_executor(ex: &'static Executor<'static>) {
+    // Tasks can use the executor without an `Arc`.
+    let task = ex.spawn(async move { ex.spawn(async { 1 + 1 }).await });
+    assert_eq!(task.await, 2);
+    assert!(smol_macros::main_executor::current_executor().is_some());
+}
+
+smol_macros::main! {
+    async unsafe fn read_unchecked(ex: &Executor<'_>, values: Vec<u32>) -> u32 {
+        ex.spawn(async { 1 }).await + *values.get_unchecked(1)
+    }
+}
+
+#[test]
+fn with_async_unsafe_fn() {
+    // SAFETY: The vector has a second element.
+    assert_eq!(unsafe { read_unchecked(vec![1, 2]) }, 3);
+}
@@ -0,0 +1,56 @@
+//! Using the macros without importing anything from this crate.
+
+mod tests {
+    #[macro_rules_attribute::apply(smol_macros::test!)]
+    async fn executor(ex: &smol_macros::Executor<'_>) {
+        assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+    }
+
+    #[macro_rules_attribute::apply(smol_macros::test!)]
+    async fn local_executor(ex: &smol_macros::LocalExecutor<'_>) {
+        assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+    }
+
+    #[macro_rules_attribute::apply(smol_macros::test!)]
+    async fn arc_executor(ex: std::sync::Arc<smol_macros::Executor<'_>>) {
+        assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+    }
+
+    #[macro_rules_attribute::apply(smol_macros::test!)]
+    async fn rc_local_executor(ex: std::rc::Rc<smol_macros::LocalExecutor<'_>>) {
+        assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+    }
+
+    smol_macros::test! {
+        async fn any_executor(ex: &smol_macros::AnyExecutor<'_>) {
+            assert_eq!(ex.spawn(async { 1 + 1 }).await, 2);
+        }
+    }
+
+    smol_macros::test! {
+        async fn custom_executor(ex: &super::custom::Wrapper) {
+            assert_eq!(ex.0.spawn(async { 1 + 1 }).await, 2);
+            assert_eq!(ex.1, 1);
+        }
+    }
+}
+
+mod custom {
+    /// A user-defined executor.
+    pub struct Wrapper(pub smol_macros::LocalExecutor<'static>, pub usize);
+
+    impl Wrapper {
+        pub async fn run<T>(&self, future: impl std::future::Future<Output = T>) -> T {
+            self.0.run(future).await
+        }
+    }
+
+    impl smol_macros::main_executor::MainExecutor for Wrapper {
+        fn with_main<T, F: FnOnce(&Self) -> T>(
+            _config: &smol_macros::main_executor::Config,
+            f: F,
+        ) -> T {
+            f(&Wrapper(smol_macros::LocalExecutor::new(), 1))
+        }
+    }
+}
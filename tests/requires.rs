@@ -0,0 +1,34 @@
+//! Testing `#[requires]`.
+//!
+//! The allowed resources are read from an environment variable, so these tests live in their own
+//! binary to avoid racing with tests that change the environment.
+
+use macro_rules_attribute::apply;
+use smol_macros::{main, test, Executor};
+
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static RAN: AtomicBool = AtomicBool::new(false);
+
+#[apply(main!)]
+#[requires(smol_macros_resource)]
+async fn needs_resource(_ex: &Executor<'_>) -> Result<(), ()> {
+    RAN.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+#[test]
+fn skipped_unless_allowed() {
+    env::remove_var("SMOL_TEST_ALLOW");
+    assert_eq!(needs_resource(), Ok(()));
+    assert!(!RAN.load(Ordering::SeqCst));
+
+    env::set_var("SMOL_TEST_ALLOW", "network, smol_macros_resource");
+    assert_eq!(needs_resource(), Ok(()));
+    assert!(RAN.load(Ordering::SeqCst));
+}
+
+#[apply(test!)]
+#[requires(network, filesystem)]
+async fn several_resources() {}
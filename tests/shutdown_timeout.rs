@@ -0,0 +1,84 @@
+//! Testing `#[shutdown_timeout]` with worker threads that don't stop in time.
+//!
+//! The scoped pool aborts the process when its threads don't stop, so that test runs this binary
+//! again and looks at how it exited.
+
+#![cfg(not(feature = "single-thread"))]
+
+use macro_rules_attribute::apply;
+use smol_macros::main_executor::{Config, ThreadPool};
+use smol_macros::{main, Executor};
+
+use std::env;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+/// Set when this binary is run by `aborts_when_workers_dont_stop`.
+const CHILD_VAR: &str = "SMOL_MACROS_SHUTDOWN_CHILD";
+
+static BLOCKING: AtomicBool = AtomicBool::new(false);
+
+#[apply(main!)]
+#[threads(1)]
+#[shutdown_timeout(50ms)]
+async fn block_worker(ex: &Executor<'_>) {
+    // Keep the only worker thread busy long after the body returns. Blocking this thread until
+    // the task starts makes sure that it doesn't run here instead.
+    ex.spawn(async {
+        BLOCKING.store(true, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_secs(10));
+    })
+    .detach();
+    while !BLOCKING.load(Ordering::SeqCst) {
+        std::thread::yield_now();
+    }
+}
+
+#[test]
+fn shutdown_child() {
+    if env::var_os(CHILD_VAR).is_some() {
+        block_worker();
+    }
+}
+
+#[test]
+fn aborts_when_workers_dont_stop() {
+    let start = Instant::now();
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "shutdown_child", "--nocapture"])
+        .env(CHILD_VAR, "1")
+        .output()
+        .unwrap();
+
+    // The process is aborted instead of waiting for the blocked task.
+    assert!(!output.status.success());
+    assert_eq!(output.status.code(), None);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("worker threads did not stop within"));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn join_gives_up_on_workers() {
+    let ex = Arc::new(Executor::new());
+    let config = Config::new()
+        .threads(1)
+        .shutdown_timeout(Duration::from_millis(50));
+    let pool = ThreadPool::spawn(&ex, &config).unwrap();
+
+    let (started, wait_started) = mpsc::channel();
+    let (release, blocked) = mpsc::channel::<()>();
+    ex.spawn(async move {
+        started.send(()).unwrap();
+        let _ = blocked.recv();
+    })
+    .detach();
+    wait_started.recv().unwrap();
+
+    // The worker thread is left behind rather than joined.
+    let stop = Instant::now();
+    pool.join();
+    assert!(stop.elapsed() < Duration::from_secs(5));
+    drop(release);
+}